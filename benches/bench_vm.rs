@@ -0,0 +1,52 @@
+use std::rc::Rc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use crow::{shorthand as op, Func, Vm};
+
+/// Number of times the benchmarked function reads the global.
+const READS: i32 = 1000;
+
+/// Read a global variable in a tight loop.
+///
+/// ```text
+/// x = 1;
+/// let i = 0;
+/// do { x; i = i + 1; } while i <= READS - 1;
+/// ```
+fn read_global_loop() -> Rc<Func> {
+    let code = [
+        op::push_int_inlined(1),
+        op::set_global(0),
+        op::push_int_inlined(0),
+        // Loop body
+        op::get_global(0),
+        op::pop(1),
+        op::get_local(1),
+        op::push_int_inlined(1),
+        op::int_add(),
+        op::store_local(1),
+        op::get_local(1),
+        op::push_int_inlined(READS - 1),
+        op::jump_le(-9),
+        op::end(),
+    ];
+
+    Rc::new(Func::from_code(code, 4, &["x"]))
+}
+
+fn bench_get_global(c: &mut Criterion) {
+    let func = read_global_loop();
+    let mut group = c.benchmark_group("get_global");
+
+    for (name, cached) in [("cached", true), ("uncached", false)] {
+        let mut vm = Vm::new();
+        vm.set_global_cache(cached);
+
+        group.bench_function(name, |b| b.iter(|| vm.eval(func.clone()).unwrap()));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_get_global);
+criterion_main!(benches);
@@ -1 +1,49 @@
+//! Dynamic array object.
+use crate::value::Value;
 
+/// Growable array of values.
+#[derive(Debug, Default)]
+pub struct Array {
+    data: Vec<Value>,
+}
+
+impl Array {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty array with room for at least `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            data: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        self.data.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Value> {
+        self.data.get_mut(index)
+    }
+
+    pub fn push(&mut self, value: Value) {
+        self.data.push(value);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.data.iter()
+    }
+}
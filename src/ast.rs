@@ -1,9 +1,8 @@
-use crate::types::{TypeId, TYPE_FLOAT_ID, TYPE_INT_ID, TYPE_STRING_ID};
+use crate::token::Span;
+use crate::types::{TypeId, TYPE_BOOL_ID, TYPE_FLOAT_ID, TYPE_INT_ID, TYPE_NIL_ID, TYPE_STRING_ID};
 
 /// Block of statements between two curly braces.
 ///
-/// A block has a return type.
-///
 /// ```text
 /// {
 ///   let x = b + y;
@@ -12,8 +11,6 @@ use crate::types::{TypeId, TYPE_FLOAT_ID, TYPE_INT_ID, TYPE_STRING_ID};
 /// ```
 #[derive(Debug)]
 pub struct Block {
-    /// Return value type.
-    pub ty: TypeId,
     /// Statements.
     pub stmts: Vec<Stmt>,
 }
@@ -26,10 +23,16 @@ pub struct Block {
 pub enum Stmt {
     /// Local variable declaration.
     Local(Box<LocalDecl>),
-    /// Explicit or implicit return statement.
-    Return,
+    /// Explicit return statement.
+    Return(Box<ReturnStmt>),
+    /// Conditional loop.
+    While(Box<WhileStmt>),
     /// Expression statement.
     Expr(Box<Expr>),
+    /// Type alias declaration.
+    TypeDecl(Box<TypeDeclStmt>),
+    /// Trailing expression without a semicolon, which is the value of its block.
+    Tail(Box<Expr>),
 }
 
 #[derive(Debug)]
@@ -41,14 +44,21 @@ pub struct LocalDecl {
 
 #[derive(Debug)]
 pub struct ReturnStmt {
-    /// Return value type.
-    ///
-    /// [`crate::types::Type::Void`] when nothing is returned.
-    pub ty: TypeId,
     /// Zero or more values to return.
     pub value: Tuple,
 }
 
+/// Loop statement that executes its body while the condition is true.
+///
+/// ```text
+/// "while" <expr> <block>
+/// ```
+#[derive(Debug)]
+pub struct WhileStmt {
+    pub cond: Expr,
+    pub body: Block,
+}
+
 /// List of multiple values to return from a block or function.
 #[derive(Debug)]
 pub struct Tuple {
@@ -57,7 +67,6 @@ pub struct Tuple {
 
 #[derive(Debug)]
 pub struct TupleItem {
-    pub ty: TypeId,
     pub expr: Expr,
 }
 
@@ -70,10 +79,20 @@ pub struct TupleItem {
 pub enum Expr {
     Name(Box<NameAccessExpr>),
     Binary(Box<BinaryExpr>),
+    /// Short-circuiting logical operation.
+    ///
+    /// Kept separate from [`BinaryExpr`] because the right hand
+    /// side is only evaluated depending on the left hand side.
+    Logical(Box<LogicalExpr>),
     Lit(Box<Literal>),
     Func(Box<FuncLit>),
     /// Call to a closure.
     Call(Box<CallExpr>),
+    ArrayLit(Box<ArrayLit>),
+    /// Index into an array or table.
+    Index(Box<IndexExpr>),
+    /// Access a field of a struct.
+    Field(Box<FieldExpr>),
 }
 
 /// Name access expression.
@@ -85,8 +104,32 @@ pub struct NameAccessExpr {
     pub ident: Ident,
 }
 
+/// Index expression.
+///
+/// ```text
+/// <expr> "[" <expr> "]"
+/// ```
+#[derive(Debug)]
+pub struct IndexExpr {
+    pub target: Expr,
+    pub index: Expr,
+}
+
+/// Field access expression.
+///
+/// ```text
+/// <expr> "." <ident>
+/// ```
+#[derive(Debug)]
+pub struct FieldExpr {
+    pub target: Expr,
+    pub field: Ident,
+}
+
 #[derive(Debug)]
 pub struct BinaryExpr {
+    /// Type of both operands, resolved by the type checker.
+    pub operand_ty: TypeId,
     pub op: BinaryOp,
     pub lhs: Expr,
     pub rhs: Expr,
@@ -101,14 +144,51 @@ pub enum BinaryOp {
     Mod,
     Exp,
     Assign,
+    // Comparison
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug)]
+pub struct LogicalExpr {
+    pub op: LogicalOp,
+    pub lhs: Expr,
+    pub rhs: Expr,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LogicalOp {
+    And,
+    Or,
 }
 
 /// Function definition literal.
+///
+/// ```text
+/// fn(<arg>, ...) -> <type-def> { <block> }
+/// ```
 #[derive(Debug)]
 pub struct FuncLit {
-    pub ty: TypeId,
     pub args: Vec<Arg>,
-    pub return_: Tuple,
+    /// Return type annotation.
+    ///
+    /// `None` when the function returns nothing.
+    pub return_: Option<TypeDef>,
+    pub body: Block,
+}
+
+/// Array literal.
+///
+/// ```text
+/// "[" (<expr> ("," <expr>)* ","?)? "]"
+/// ```
+#[derive(Debug)]
+pub struct ArrayLit {
+    pub elements: Vec<Expr>,
 }
 
 #[derive(Debug)]
@@ -120,9 +200,18 @@ pub struct Arg {
 /// Call expression/
 #[derive(Debug)]
 pub struct CallExpr {
-    pub ty: TypeId,
     pub callee: Box<Expr>,
+    /// Positional arguments.
     pub args: Vec<Expr>,
+    /// Named arguments, like `f(b: 2)`, which always follow the positional arguments.
+    pub named_args: Vec<NamedArg>,
+}
+
+/// Argument passed to a parameter by name.
+#[derive(Debug)]
+pub struct NamedArg {
+    pub name: Ident,
+    pub value: Expr,
 }
 
 // ============================================================================ //
@@ -132,12 +221,18 @@ pub struct CallExpr {
 #[derive(Debug)]
 pub struct Ident {
     pub text: String,
+    /// Location in the source text, if the identifier was parsed.
+    pub span: Option<Span>,
 }
 
 #[derive(Debug)]
 pub enum Literal {
-    Num(Number),
+    /// Numeric literal, with its original source text when it was parsed,
+    /// so tooling can reproduce spellings like `0x1F` or `1_000`.
+    Num(Number, Option<String>),
     Str(String),
+    Bool(bool),
+    Nil,
 }
 
 #[derive(Debug)]
@@ -164,12 +259,18 @@ pub struct TypeDeclStmt {
 /// Type definition.
 ///
 /// ```text
-/// <alias|literal>
+/// <alias|literal> "?"?
 /// ```
 #[derive(Debug)]
 pub enum TypeDef {
     Alias(TypeName),
     Lit(TypeLit),
+    /// Optional type, which may also be `nil`.
+    ///
+    /// ```text
+    /// <type-def> "?"
+    /// ```
+    Optional(Box<TypeDef>),
 }
 
 /// A simple type name (an alias) to another existing type.
@@ -221,18 +322,22 @@ pub struct FieldDef {
 // Functions                                                                    //
 // ============================================================================ //
 
-impl Ident {
-    pub fn from_string(text: impl ToString) -> Self {
-        Ident { text: text.to_string() }
+impl BinaryOp {
+    /// Whether the operator compares its operands, resulting in a boolean.
+    pub fn is_comparison(&self) -> bool {
+        use BinaryOp::*;
+        matches!(self, Lt | Le | Gt | Ge | Eq | Ne)
     }
 }
 
 impl Literal {
     pub fn type_id(&self) -> TypeId {
         match self {
-            Literal::Num(Number::Int(_)) => TYPE_INT_ID,
-            Literal::Num(Number::Float(_)) => TYPE_FLOAT_ID,
+            Literal::Num(Number::Int(_), _) => TYPE_INT_ID,
+            Literal::Num(Number::Float(_), _) => TYPE_FLOAT_ID,
             Literal::Str(_) => TYPE_STRING_ID,
+            Literal::Bool(_) => TYPE_BOOL_ID,
+            Literal::Nil => TYPE_NIL_ID,
         }
     }
 }
@@ -0,0 +1,108 @@
+//! Compilation cache.
+use std::rc::Rc;
+
+use fxhash::FxHashMap;
+
+use crate::errors::Error;
+use crate::lexer::Lexer;
+use crate::object::Func;
+
+/// Cache of compiled chunks, so unchanged source files aren't recompiled.
+///
+/// Entries are keyed by filename, and invalidated when the
+/// file's source text changes.
+#[derive(Default)]
+pub struct CompileCache {
+    entries: FxHashMap<String, CacheEntry>,
+}
+
+struct CacheEntry {
+    /// Source text the chunk was compiled from.
+    ///
+    /// The whole text is compared, rather than a hash of it,
+    /// so a collision can't return a stale chunk.
+    source: Box<str>,
+    chunk: Rc<Func>,
+}
+
+impl CompileCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the cached chunk for the file if its source is unchanged,
+    /// otherwise compile it like [`compile_block`](crate::compile_block)
+    /// and cache the result.
+    ///
+    /// Failed compilations are not cached.
+    pub fn get_or_compile(&mut self, source: &str, filename: &str) -> Result<Rc<Func>, Vec<Error>> {
+        if let Some(entry) = self.entries.get(filename) {
+            if &*entry.source == source {
+                return Ok(entry.chunk.clone());
+            }
+        }
+
+        let chunk = crate::compile_chunk(Lexer::new(source, filename))?;
+        self.entries.insert(
+            filename.to_string(),
+            CacheEntry {
+                source: source.into(),
+                chunk: chunk.clone(),
+            },
+        );
+
+        Ok(chunk)
+    }
+
+    /// Remove the cached chunk for the file, if any.
+    pub fn invalidate(&mut self, filename: &str) {
+        self.entries.remove(filename);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::value::Value;
+    use crate::vm::Vm;
+
+    #[test]
+    fn test_compile_cache() -> Result<(), Vec<Error>> {
+        let mut cache = CompileCache::new();
+
+        let first = cache.get_or_compile("let x = 1; x + 1", "main.crow")?;
+        let second = cache.get_or_compile("let x = 1; x + 1", "main.crow")?;
+        assert!(Rc::ptr_eq(&first, &second));
+        // The cached chunk runs like a freshly compiled one.
+        let result = Vm::new().eval(second).map_err(|err| vec![err])?;
+        assert_eq!(result, Some(Value::Int(2)));
+
+        // Changed source
+        let changed = cache.get_or_compile("let x = 2;", "main.crow")?;
+        assert!(!Rc::ptr_eq(&first, &changed));
+
+        // Same source in a different file
+        let other = cache.get_or_compile("let x = 2;", "other.crow")?;
+        assert!(!Rc::ptr_eq(&changed, &other));
+        assert_eq!(cache.len(), 2);
+
+        cache.invalidate("main.crow");
+        let recompiled = cache.get_or_compile("let x = 2;", "main.crow")?;
+        assert!(!Rc::ptr_eq(&changed, &recompiled));
+
+        // Errors aren't cached
+        assert!(cache.get_or_compile("let", "broken.crow").is_err());
+        assert!(cache.get_or_compile("let x: Float = 1;", "broken.crow").is_err());
+        assert_eq!(cache.len(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,523 @@
+//! Bytecode compiler.
+use std::rc::Rc;
+
+use fxhash::FxHashMap;
+
+use crate::ast::{ArrayLit, BinaryExpr, BinaryOp, Block, Expr, Literal, LocalDecl, NameAccessExpr, Number, Stmt};
+use crate::errors::{compile_err, Result};
+use crate::limits::MAX_ARG_24;
+use crate::liveness::Liveness;
+use crate::object::{Constants, CrowStr, Func};
+use crate::op::{shorthand as op, Arg24, Op};
+use crate::string::{Str, StringInterner};
+use crate::types::{Type, TYPE_FLOAT_ID, TYPE_INT_ID};
+
+/// The constant pool a value is stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstantPool {
+    Int,
+    Float,
+    String,
+}
+
+/// Location of a constant within a function's [`Constants`].
+///
+/// The index is only meaningful for the pool it was allocated in,
+/// so an `Int` constant must be loaded with `PushInt`, a `Float`
+/// with `PushFloat`, and so on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConstantRef {
+    pub pool: ConstantPool,
+    pub index: u32,
+}
+
+/// Accumulates the constants of a function while it is being compiled.
+///
+/// Constants are interned, so adding a value that's already in its pool
+/// returns the existing location instead of storing it again.
+#[derive(Default)]
+pub struct ConstantsBuilder {
+    ints: Vec<i64>,
+    floats: Vec<f64>,
+    strings: Vec<Rc<CrowStr>>,
+    interned: FxHashMap<ConstantKey, ConstantRef>,
+    /// String constants are looked up by their shared handle,
+    /// so a lookup doesn't allocate a key.
+    strings_interner: StringInterner,
+}
+
+/// Identity of a constant for deduplication.
+///
+/// Floats compare by bit pattern, so `0.0` and `-0.0` are kept apart,
+/// and a NaN constant is deduplicated with an identical NaN.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Int(i64),
+    Float(u64),
+    String(Str),
+}
+
+impl ConstantsBuilder {
+    /// Add a type-checked literal to the pool matching its type.
+    ///
+    /// Literals without a pool, like `nil` and booleans, are pushed
+    /// by dedicated instructions instead and result in an error.
+    pub fn add_literal(&mut self, literal: &Literal) -> Result<ConstantRef> {
+        let ty = match literal {
+            Literal::Num(Number::Int(value), _) => return Ok(self.add_int(*value)),
+            Literal::Num(Number::Float(value), _) => return Ok(self.add_float(*value)),
+            Literal::Str(value) => return Ok(self.add_string(value)),
+            Literal::Bool(_) => Type::Bool,
+            Literal::Nil => Type::Nil,
+        };
+
+        compile_err(format!("literal of type {ty} has no constant pool")).into()
+    }
+
+    pub fn add_int(&mut self, value: i64) -> ConstantRef {
+        let Self { ints, interned, .. } = self;
+        Self::intern(interned, ConstantKey::Int(value), || {
+            Self::push(ints, ConstantPool::Int, value)
+        })
+    }
+
+    pub fn add_float(&mut self, value: f64) -> ConstantRef {
+        let Self { floats, interned, .. } = self;
+        Self::intern(interned, ConstantKey::Float(value.to_bits()), || {
+            Self::push(floats, ConstantPool::Float, value)
+        })
+    }
+
+    pub fn add_string(&mut self, value: &str) -> ConstantRef {
+        let key = ConstantKey::String(self.strings_interner.intern(value));
+        let Self { strings, interned, .. } = self;
+        Self::intern(interned, key, || {
+            Self::push(strings, ConstantPool::String, Rc::new(CrowStr::new(value)))
+        })
+    }
+
+    fn intern(
+        interned: &mut FxHashMap<ConstantKey, ConstantRef>,
+        key: ConstantKey,
+        push: impl FnOnce() -> ConstantRef,
+    ) -> ConstantRef {
+        *interned.entry(key).or_insert_with(push)
+    }
+
+    fn push<T>(pool: &mut Vec<T>, kind: ConstantPool, value: T) -> ConstantRef {
+        let index = pool.len() as u32;
+        pool.push(value);
+        ConstantRef { pool: kind, index }
+    }
+
+    pub fn build(self) -> Constants {
+        Constants {
+            ints: self.ints.into_boxed_slice(),
+            floats: self.floats.into_boxed_slice(),
+            strings: self.strings.into_boxed_slice(),
+            // Function literals aren't compiled yet.
+            funcs: Box::new([]),
+        }
+    }
+}
+
+/// Compile a type-checked expression into a function that returns its value.
+///
+/// There's no backend for whole modules yet, so only literals, array
+/// literals, and arithmetic or comparisons between numbers, can be compiled.
+/// That's enough to evaluate constant expressions in a REPL.
+pub fn compile_expr(expr: &Expr) -> Result<Func> {
+    let mut compiler = ExprCompiler::default();
+    compiler.compile(expr)?;
+    compiler.emit(op::return_(1));
+
+    Ok(Func {
+        code: compiler.code.into_boxed_slice(),
+        // Slot 0 holds the callable.
+        stack_size: compiler.max_depth + 1,
+        arity: 0,
+        is_varg: false,
+        constants: compiler.constants.build(),
+        up_values: Box::new([]),
+    })
+}
+
+/// Compile a type-checked block of straight-line statements into a function.
+///
+/// Local declarations, expression statements, returns and a trailing
+/// expression can be compiled. Locals are given stack slots by their
+/// liveness, so a slot is reused once the local in it is no longer read,
+/// and the function's stack size only covers the locals alive at once.
+pub fn compile_block(block: &Block) -> Result<Func> {
+    let mut compiler = ExprCompiler::default();
+    let slots = Liveness::analyze(block)
+        .allocate_slots()
+        .into_iter()
+        .map(|slot| u16::try_from(slot).map_err(|_| compile_err("too many local variables")))
+        .collect::<Result<Vec<u16>>>()?;
+
+    // Reserve the slots, so temporaries are pushed above them.
+    let slot_count = slots.iter().max().map_or(0, |slot| *slot);
+    for _ in 0..slot_count {
+        compiler.emit(Op::PushNil);
+    }
+
+    let mut slots = slots.into_iter();
+    for stmt in &block.stmts {
+        match stmt {
+            Stmt::Local(local_decl) => {
+                let slot = slots.next().expect("slot for every local");
+                compiler.compile_local_decl(local_decl, slot)?;
+            }
+            Stmt::Expr(expr) => {
+                compiler.compile(expr)?;
+                compiler.emit(op::pop(1));
+            }
+            Stmt::Return(return_stmt) => {
+                for item in &return_stmt.value.items {
+                    compiler.compile(&item.expr)?;
+                }
+                let results = u8::try_from(return_stmt.value.items.len())
+                    .map_err(|_| compile_err("too many values to return"))?;
+                compiler.emit(op::return_(results));
+            }
+            Stmt::Tail(expr) => {
+                compiler.compile(expr)?;
+                compiler.emit(op::return_(1));
+            }
+            Stmt::TypeDecl(_) => {}
+            Stmt::While(_) => return compile_err("cannot compile loops yet").into(),
+        }
+    }
+    compiler.emit(op::end());
+
+    Ok(Func {
+        code: compiler.code.into_boxed_slice(),
+        // Slot 0 holds the callable.
+        stack_size: compiler.max_depth + 1,
+        arity: 0,
+        is_varg: false,
+        constants: compiler.constants.build(),
+        up_values: Box::new([]),
+    })
+}
+
+#[derive(Default)]
+struct ExprCompiler {
+    code: Vec<Op>,
+    constants: ConstantsBuilder,
+    /// Locals that are in scope, with their slots, and the latest declaration last.
+    locals: Vec<(String, u16)>,
+    /// Number of values on the stack at the current instruction.
+    depth: u32,
+    /// Deepest the stack grows, which determines the function's stack size.
+    max_depth: u32,
+}
+
+impl ExprCompiler {
+    fn emit(&mut self, op: Op) {
+        self.code.push(op);
+        self.depth = self.depth.saturating_add_signed(op.stack_effect() as i32);
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+
+    /// Emit the instructions for the expression.
+    fn compile(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Name(name_access) => self.compile_name(name_access),
+            Expr::Lit(literal) => self.compile_literal(literal),
+            Expr::Binary(binary_expr) => self.compile_binary_expr(binary_expr),
+            Expr::ArrayLit(array_lit) => self.compile_array_lit(array_lit),
+            _ => compile_err("only literals, locals and arithmetic can be compiled as an expression").into(),
+        }
+    }
+
+    /// Store the initial value of the local in its slot, and bring it into scope.
+    fn compile_local_decl(&mut self, local_decl: &LocalDecl, slot: u16) -> Result<()> {
+        match &local_decl.rhs {
+            Some(rhs) => self.compile(rhs)?,
+            // The slot may have held a dead local.
+            None => self.emit(Op::PushNil),
+        }
+        self.emit(op::store_local(slot));
+        self.locals.push((local_decl.name.text.clone(), slot));
+
+        Ok(())
+    }
+
+    fn compile_name(&mut self, name_access: &NameAccessExpr) -> Result<()> {
+        let name = &name_access.ident.text;
+        let (_, slot) = self
+            .locals
+            .iter()
+            .rev()
+            .find(|(local, _)| local == name)
+            .ok_or_else(|| compile_err(format!("cannot compile access to non-local variable: {name}")))?;
+        self.emit(op::get_local(*slot));
+
+        Ok(())
+    }
+
+    fn compile_literal(&mut self, literal: &Literal) -> Result<()> {
+        match literal {
+            Literal::Bool(true) => self.emit(Op::PushTrue),
+            Literal::Bool(false) => self.emit(Op::PushFalse),
+            Literal::Nil => self.emit(Op::PushNil),
+            _ => return self.compile_constant(literal),
+        }
+
+        Ok(())
+    }
+
+    fn compile_constant(&mut self, literal: &Literal) -> Result<()> {
+        // Small integers are inlined in the instruction.
+        if let Literal::Num(Number::Int(value), _) = literal {
+            if let Ok(arg) = Arg24::from_i64(*value) {
+                self.emit(Op::PushIntIn(arg));
+                return Ok(());
+            }
+        }
+
+        let constant = self.constants.add_literal(literal)?;
+        let op = match constant.pool {
+            ConstantPool::Int => op::push_int(constant.index),
+            ConstantPool::Float => op::push_float(constant.index),
+            ConstantPool::String => op::push_string(constant.index),
+        };
+        self.emit(op);
+
+        Ok(())
+    }
+
+    /// Create the array with room for all its elements, and push them in order.
+    fn compile_array_lit(&mut self, array_lit: &ArrayLit) -> Result<()> {
+        // The capacity is only a hint, so a huge literal reserves what fits.
+        let capacity = array_lit.elements.len().min(MAX_ARG_24 as usize - 1);
+        self.emit(op::new_array(capacity as u32));
+
+        for element in &array_lit.elements {
+            self.emit(Op::Dup);
+            self.compile(element)?;
+            self.emit(op::array_push());
+        }
+
+        Ok(())
+    }
+
+    /// The instruction is chosen by the operand type the checker resolved.
+    fn compile_binary_expr(&mut self, binary_expr: &BinaryExpr) -> Result<()> {
+        use BinaryOp::*;
+
+        self.compile(&binary_expr.lhs)?;
+        self.compile(&binary_expr.rhs)?;
+
+        let op = match (binary_expr.operand_ty, binary_expr.op) {
+            (TYPE_INT_ID, op) => match op {
+                Add => Op::Int_Add,
+                Sub => Op::Int_Sub,
+                Mul => Op::Int_Mul,
+                Div => Op::Int_Div,
+                Mod => Op::Int_Mod,
+                Lt => Op::Int_Lt,
+                Le => Op::Int_Le,
+                Gt => Op::Int_Gt,
+                Ge => Op::Int_Ge,
+                Eq => Op::Int_Eq,
+                Ne => Op::Int_Ne,
+                Exp | Assign => return err_unsupported_op(op),
+            },
+            (TYPE_FLOAT_ID, op) => match op {
+                Add => Op::Float_Add,
+                Sub => Op::Float_Sub,
+                Mul => Op::Float_Mul,
+                Div => Op::Float_Div,
+                Mod => Op::Float_Mod,
+                Lt => Op::Float_Lt,
+                Le => Op::Float_Le,
+                Gt => Op::Float_Gt,
+                Ge => Op::Float_Ge,
+                Eq => Op::Float_Eq,
+                Ne => Op::Float_Ne,
+                Exp | Assign => return err_unsupported_op(op),
+            },
+            (_, op) => return err_unsupported_op(op),
+        };
+        self.emit(op);
+
+        Ok(())
+    }
+}
+
+fn err_unsupported_op<T>(op: BinaryOp) -> Result<T> {
+    compile_err(format!("cannot compile operator {op:?} for these operand types yet")).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn int(value: i64) -> Literal {
+        Literal::Num(Number::Int(value), None)
+    }
+
+    fn float(value: f64) -> Literal {
+        Literal::Num(Number::Float(value), None)
+    }
+
+    #[test]
+    fn test_constant_pools() -> Result<()> {
+        let mut builder = ConstantsBuilder::default();
+
+        let refs = [
+            builder.add_literal(&int(7))?,
+            builder.add_literal(&float(1.5))?,
+            builder.add_literal(&Literal::Str("hello".to_string()))?,
+            builder.add_literal(&int(42))?,
+            builder.add_literal(&float(2.0))?,
+        ];
+
+        use ConstantPool as P;
+        let expected = [(P::Int, 0), (P::Float, 0), (P::String, 0), (P::Int, 1), (P::Float, 1)];
+        for (constant, (pool, index)) in refs.iter().zip(expected) {
+            assert_eq!(*constant, ConstantRef { pool, index });
+        }
+
+        // Literals without a pool are rejected.
+        let err = builder.add_literal(&Literal::Nil).unwrap_err();
+        assert_eq!(err.kind, crate::errors::ErrorKind::Compile);
+        assert_eq!(err.message, "literal of type Nil has no constant pool");
+        let err = builder.add_literal(&Literal::Bool(true)).unwrap_err();
+        assert_eq!(err.message, "literal of type Bool has no constant pool");
+
+        let constants = builder.build();
+        assert_eq!(&*constants.ints, &[7, 42]);
+        assert_eq!(&*constants.floats, &[1.5, 2.0]);
+        assert_eq!(constants.strings.len(), 1);
+        assert_eq!(constants.strings[0].as_str(), "hello");
+        assert!(constants.funcs.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_constant_dedup() -> Result<()> {
+        let mut builder = ConstantsBuilder::default();
+
+        let first = builder.add_literal(&float(1.5))?;
+        assert_eq!(builder.add_literal(&float(1.5))?, first);
+        assert_eq!(builder.add_int(42), builder.add_int(42));
+        assert_eq!(builder.add_string("hello"), builder.add_string("hello"));
+
+        // Floats compare by bit pattern.
+        assert_ne!(builder.add_float(0.0), builder.add_float(-0.0));
+        assert_eq!(builder.add_float(f64::NAN), builder.add_float(f64::NAN));
+
+        let constants = builder.build();
+        assert_eq!(&*constants.ints, &[42]);
+        assert_eq!(constants.floats.len(), 4);
+        assert_eq!(constants.strings.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_expr() -> Result<()> {
+        use crate::value::Value;
+        use crate::vm::Vm;
+
+        let compile = |source: &str| crate::compile_expr(source).map_err(|mut errors| errors.remove(0));
+        let eval = |source: &str| -> Result<Vec<Value>> {
+            let func = compile(source)?;
+            let mut vm = Vm::new();
+            vm.run_function((), func)?;
+            Ok(vm.results().to_vec())
+        };
+
+        assert_eq!(eval("1 + 2 * 3")?, [Value::Int(7)]);
+        assert_eq!(eval("(1 + 2) * 3")?, [Value::Int(9)]);
+        assert_eq!(eval("10000000000 - 1")?, [Value::Int(9_999_999_999)]);
+        assert_eq!(eval("1.5 * 2.0")?, [Value::Float(3.0)]);
+        assert_eq!(eval("2 * 3 > 5")?, [Value::from_bool(true)]);
+        assert_eq!(eval("false")?, [Value::Bool(false)]);
+        assert_eq!(eval("nil")?, [Value::Nil]);
+
+        // Arithmetic errors are reported by the VM.
+        assert_eq!(eval("1 / 0").unwrap_err().message, "division by zero");
+        assert_eq!(eval("1 % 0").unwrap_err().message, "division by zero");
+
+        // The expression is type checked first.
+        let err = eval("1 + 2.0").unwrap_err();
+        assert!(err.is_typecheck_err());
+        // Only a single expression is accepted.
+        assert!(eval("1 + 2; 3").is_err());
+        let array = eval("[1, 2 * 3, 10000000000]")?.remove(0);
+        let array = array.as_array().expect("array").borrow();
+        assert_eq!(array.len(), 3);
+        assert_eq!(array.get(1), Some(&Value::Int(6)));
+        assert_eq!(array.get(2), Some(&Value::Int(10_000_000_000)));
+        // Room is reserved for the elements up front.
+        let func = compile("[1.0, 2.0]")?;
+        assert!(
+            matches!(func.code[0], Op::NewArray { capacity } if capacity.as_u32() == 2),
+            "{:?}",
+            func.code
+        );
+
+        // Valid, but not supported by the compiler yet.
+        assert_eq!(eval("2 ** 3").unwrap_err().kind, crate::errors::ErrorKind::Compile);
+
+        // The operand types resolved by the checker select the instruction.
+        let mut expr = Expr::Binary(Box::new(BinaryExpr {
+            operand_ty: crate::types::TypeId::default(),
+            op: BinaryOp::Add,
+            lhs: Expr::Lit(Box::new(int(1))),
+            rhs: Expr::Lit(Box::new(int(2))),
+        }));
+        let err = compile_expr(&expr).err().expect("unchecked operands");
+        assert_eq!(err.kind, crate::errors::ErrorKind::Compile);
+        crate::typechecker::TypeChecker::new().check_expr(&mut expr)?;
+        let func = compile_expr(&expr)?;
+        assert!(matches!(func.code[2], Op::Int_Add), "{:?}", func.code);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_block_slot_reuse() -> Result<()> {
+        use crate::value::Value;
+        use crate::vm::Vm;
+
+        let compile = |source: &str| crate::compile_block(source).map_err(|mut errors| errors.remove(0));
+
+        let source = "
+            let a = 1;
+            let b = a + 1;
+            let c = b * 2;
+            let d = c - 3;
+            let e = d * 10;
+            e + 1
+        ";
+        let func = compile(source)?;
+
+        // Each local is only read by the next statement, so they all share one slot.
+        let temporaries = 2;
+        let naive = 1 + 5 + temporaries;
+        assert_eq!(func.stack_size, 1 + 1 + temporaries);
+        assert!(func.stack_size < naive);
+
+        let mut vm = Vm::new();
+        vm.run_function((), func)?;
+        assert_eq!(vm.results(), [Value::Int(11)]);
+
+        // Locals that are alive at the same time get their own slots.
+        let source = "let a = 1; let b = 2; let c = a + b; a + b + c";
+        let func = compile(source)?;
+        assert_eq!(func.stack_size, 1 + 3 + temporaries);
+
+        let mut vm = Vm::new();
+        vm.run_function((), func)?;
+        assert_eq!(vm.results(), [Value::Int(6)]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+//! Bytecode disassembler.
+use std::fmt::Write;
+
+use crate::object::Func;
+use crate::op::{Arg24, Op};
+
+/// Render a function's bytecode as text, one instruction per line.
+///
+/// Each line has the instruction's index and its decoded operands.
+/// Instructions that refer to a constant are annotated with the
+/// constant's value.
+///
+/// ```text
+/// 0000  PushInt(0)        ; 42
+/// 0001  PushFunc(0)       ; func 0
+/// 0002  End
+///
+/// func 0:
+///     0000  End
+/// ```
+///
+/// Nested function constants are disassembled recursively, indented
+/// one level deeper than their parent.
+pub fn disassemble(func: &Func) -> String {
+    let mut buf = String::new();
+    write_func(&mut buf, func, 0);
+    buf
+}
+
+fn write_func(buf: &mut String, func: &Func, depth: usize) {
+    let indent = "    ".repeat(depth);
+
+    for (index, op) in func.code.iter().enumerate() {
+        let instr = format_op(op);
+        match constant_comment(func, op) {
+            Some(comment) => {
+                let _ = writeln!(buf, "{indent}{index:04}  {instr:<16}  ; {comment}");
+            }
+            None => {
+                let _ = writeln!(buf, "{indent}{index:04}  {instr}");
+            }
+        }
+    }
+
+    for (func_id, nested) in func.constants.funcs.iter().enumerate() {
+        let _ = writeln!(buf, "\n{indent}func {func_id}:");
+        write_func(buf, nested, depth + 1);
+    }
+}
+
+/// Format the instruction with its decoded operands.
+///
+/// Operands are shown unsigned, except for inline integers
+/// and jump offsets, which are sign-extended.
+fn format_op(op: &Op) -> String {
+    let jump = |name: &str, addr: Arg24| format!("{name} {{ addr: {} }}", addr.as_i64());
+
+    match *op {
+        Op::PushIntIn(value) => format!("PushIntIn({})", value.as_i64()),
+        Op::JumpNe { addr } => jump("JumpNe", addr),
+        Op::JumpEq { addr } => jump("JumpEq", addr),
+        Op::JumpLt { addr } => jump("JumpLt", addr),
+        Op::JumpLe { addr } => jump("JumpLe", addr),
+        Op::JumpGt { addr } => jump("JumpGt", addr),
+        Op::JumpGe { addr } => jump("JumpGe", addr),
+        Op::JumpZero { addr } => jump("JumpZero", addr),
+        Op::Jump { addr } => jump("Jump", addr),
+        _ => format!("{op:?}"),
+    }
+}
+
+/// Describe the constant referred to by the instruction, if any.
+fn constant_comment(func: &Func, op: &Op) -> Option<String> {
+    let constants = &func.constants;
+    let missing = || "<missing constant>".to_string();
+
+    let comment = match *op {
+        Op::PushInt(arg) => constants.ints.get(arg.as_usize()).map(|value| value.to_string()),
+        Op::PushFloat(arg) => constants.floats.get(arg.as_usize()).map(|value| format!("{value:?}")),
+        Op::PushString(arg) => constants
+            .strings
+            .get(arg.as_usize())
+            .map(|value| format!("{:?}", value.as_str())),
+        Op::GetGlobal { string } | Op::SetGlobal { string } => constants
+            .strings
+            .get(string as usize)
+            .map(|value| format!("{:?}", value.as_str())),
+        Op::PushFunc(arg) | Op::CreateClosure { func_id: arg } => {
+            let func_id = arg.as_usize();
+            constants.funcs.get(func_id).map(|_| format!("func {func_id}"))
+        }
+        _ => return None,
+    };
+
+    Some(comment.unwrap_or_else(missing))
+}
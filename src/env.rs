@@ -6,6 +6,7 @@
 /// shared between the distinct stages of the pipeline.
 ///
 /// Parser, type checker, compiler and virtual machine.
+#[allow(dead_code)]
 pub struct Env {
     // TODO: types
     // TODO: global vars
@@ -1,11 +1,14 @@
 use std::fmt::{self, Formatter};
 
+use crate::token::{Span, DEFAULT_TAB_WIDTH};
+
 pub type Result<T> = std::result::Result<T, self::Error>;
 
 pub(crate) fn lexer_err(message: impl ToString) -> self::Error {
     Error {
         message: message.to_string(),
         kind: ErrorKind::Lexer,
+        span: None,
     }
 }
 
@@ -13,6 +16,15 @@ pub(crate) fn parser_err(message: impl ToString) -> self::Error {
     Error {
         message: message.to_string(),
         kind: ErrorKind::Parser,
+        span: None,
+    }
+}
+
+pub(crate) fn io_err(path: &str, err: std::io::Error) -> self::Error {
+    Error {
+        message: format!("{path}: {err}"),
+        kind: ErrorKind::Io,
+        span: None,
     }
 }
 
@@ -20,6 +32,7 @@ pub(crate) fn runtime_err(message: impl ToString) -> self::Error {
     Error {
         message: message.to_string(),
         kind: ErrorKind::Runtime,
+        span: None,
     }
 }
 
@@ -27,6 +40,15 @@ pub(crate) fn typecheck_err(message: impl ToString) -> self::Error {
     Error {
         message: message.to_string(),
         kind: ErrorKind::Type,
+        span: None,
+    }
+}
+
+pub(crate) fn compile_err(message: impl ToString) -> self::Error {
+    Error {
+        message: message.to_string(),
+        kind: ErrorKind::Compile,
+        span: None,
     }
 }
 
@@ -34,6 +56,8 @@ pub(crate) fn typecheck_err(message: impl ToString) -> self::Error {
 pub struct Error {
     pub message: String,
     pub kind: ErrorKind,
+    /// Location in the source text that caused the error, if known.
+    pub span: Option<Span>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -42,12 +66,75 @@ pub enum ErrorKind {
     Parser,
     Runtime,
     Type,
+    /// Valid program that the compiler can't generate code for yet.
+    Compile,
+    Io,
 }
 
 impl Error {
     pub fn is_typecheck_err(&self) -> bool {
         matches!(self.kind, ErrorKind::Type)
     }
+
+    /// Point the error at the given span, unless a more precise span was already attached.
+    pub(crate) fn with_span(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+
+    /// Render the error as a diagnostic, with the line and column
+    /// of its span, and a caret underline of the offending fragment.
+    ///
+    /// ```text
+    /// 2:9: type error: unknown variable: y
+    ///     let x = y;
+    ///             ^
+    /// ```
+    ///
+    /// Errors without a span are rendered with only their kind and message.
+    ///
+    /// Tab stops are [`DEFAULT_TAB_WIDTH`] columns apart.
+    pub fn render(&self, source: &str) -> String {
+        self.render_with_tab_width(source, DEFAULT_TAB_WIDTH)
+    }
+
+    /// Render the error as a diagnostic, with tab stops every `tab_width` columns.
+    ///
+    /// See [`Error::render`].
+    pub fn render_with_tab_width(&self, source: &str, tab_width: u32) -> String {
+        let Self { message, kind, span } = self;
+        match span {
+            Some(span) => {
+                let (line, column) = span.line_column(source, tab_width);
+                let snippet = span.caret_snippet(source, tab_width);
+                format!("{line}:{column}: {kind}: {message}\n{snippet}")
+            }
+            None => format!("{kind}: {message}"),
+        }
+    }
+
+    /// Render the error as a diagnostic prefixed with the name of the source file.
+    ///
+    /// See [`Error::render`].
+    pub fn render_file(&self, filename: &str, source: &str) -> String {
+        match self.span {
+            Some(_) => format!("{filename}:{}", self.render(source)),
+            None => format!("{filename}: {}", self.render(source)),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Lexer => write!(f, "lexer error"),
+            Self::Parser => write!(f, "syntax error"),
+            Self::Runtime => write!(f, "runtime error"),
+            Self::Type => write!(f, "type error"),
+            Self::Compile => write!(f, "compile error"),
+            Self::Io => write!(f, "io error"),
+        }
+    }
 }
 
 impl fmt::Display for Error {
@@ -59,6 +146,16 @@ impl fmt::Display for Error {
 
 impl std::error::Error for self::Error {}
 
+impl From<std::io::Error> for self::Error {
+    fn from(err: std::io::Error) -> Self {
+        Error {
+            message: err.to_string(),
+            kind: ErrorKind::Io,
+            span: None,
+        }
+    }
+}
+
 impl<T> From<self::Error> for self::Result<T> {
     fn from(err: self::Error) -> Self {
         Err(err)
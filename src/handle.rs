@@ -12,6 +12,7 @@ pub use std::cell::{Ref, RefMut};
 /// A shared, mutable handle.
 pub struct Handle<T>(Rc<RefCell<T>>);
 
+#[allow(dead_code)]
 pub struct Weak<T>(RcWeak<RefCell<T>>);
 
 impl<T> Handle<T> {
@@ -45,6 +46,13 @@ impl<T> Handle<T> {
         self.0.as_ptr()
     }
 
+    /// Number of strong handles to the shared value.
+    ///
+    /// Useful in tests to check that values aren't leaked.
+    pub fn strong_count(&self) -> usize {
+        Rc::strong_count(&self.0)
+    }
+
     pub fn downgrade(&self) -> Weak<T> {
         Weak(Rc::downgrade(&self.0))
     }
@@ -63,11 +71,13 @@ impl<T: fmt::Debug> fmt::Debug for Handle<T> {
 }
 
 /// A [`Handle`] shared in a circular reference.
+#[allow(dead_code)]
 pub enum Shared<T> {
     Strong(Handle<T>),
     Weak(Weak<T>),
 }
 
+#[allow(dead_code)]
 impl<T> Shared<T> {
     pub fn strong(&self) -> Option<&Handle<T>> {
         match self {
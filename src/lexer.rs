@@ -19,7 +19,10 @@ pub struct Lexer<'a> {
     /// Span of the text fragment that was consumed. `(byte_offset, size)`
     span: Span,
     /// File where the source text is from.
+    #[allow(dead_code)]
     pub(crate) file: Option<String>,
+    /// Indicates that the end-of-file token was yielded by the iterator.
+    done: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -30,9 +33,10 @@ impl<'a> Lexer<'a> {
     pub fn new(text: &'a str, file: impl ToString) -> Self {
         Self {
             text,
-            rest: text,
+            rest: skip_bom(text),
             span: Span::new(0, 0),
             file: Some(file.to_string()),
+            done: false,
         }
     }
 
@@ -42,9 +46,10 @@ impl<'a> Lexer<'a> {
     pub(crate) fn from_source(text: &'a str) -> Self {
         Self {
             text,
-            rest: text,
+            rest: skip_bom(text),
             span: Span::new(0, 0),
             file: None,
+            done: false,
         }
     }
 
@@ -52,7 +57,17 @@ impl<'a> Lexer<'a> {
         self.text
     }
 
+    /// Scan the next token.
+    ///
+    /// Errors point at the text scanned for the failed token.
     pub fn next_token(&mut self) -> Result<Token> {
+        self.lex_token().map_err(|err| {
+            let Span(start, _) = self.span;
+            err.with_span(Span(start, self.pos() as u32 - start))
+        })
+    }
+
+    fn lex_token(&mut self) -> Result<Token> {
         use crate::token::TokenKind::*;
 
         loop {
@@ -62,7 +77,7 @@ impl<'a> Lexer<'a> {
             let token = match self.bump() {
                 Some((_, ch)) => match ch {
                     '0'..='9' => self.lex_number()?,
-                    'a'..='z' | 'A'..='Z' => self.lex_ident(),
+                    'a'..='z' | 'A'..='Z' | '_' => self.lex_ident(),
 
                     // --------------------------------------------------------
                     // Punctuation
@@ -75,22 +90,24 @@ impl<'a> Lexer<'a> {
                             self.make_token(Eq)
                         }
                     }
-                    '!' => {
-                        if self.match_char('=') {
-                            self.make_token(NotEq)
-                        } else {
-                            return lexer_err(format!("unexpected character {ch:?}")).into();
-                        }
-                    }
+                    // A lone `!` is an unexpected character.
+                    '!' if self.match_char('=') => self.make_token(NotEq),
                     '#' => self.make_token(Hash),
                     ':' => self.make_token(Colon),
                     ';' => self.make_token(Semi),
                     '%' => self.make_token(Perc),
+                    '?' => self.make_token(Question),
 
                     // --------------------------------------------------------
                     // Operators
                     '+' => self.make_token(Plus),
-                    '-' => self.make_token(Minus),
+                    '-' => {
+                        if self.match_char('>') {
+                            self.make_token(Arrow)
+                        } else {
+                            self.make_token(Minus)
+                        }
+                    }
                     '*' => {
                         if self.match_char('*') {
                             self.make_token(StarStar)
@@ -123,7 +140,15 @@ impl<'a> Lexer<'a> {
                     '}' => self.make_token(BraceRight),
                     '[' => self.make_token(BracketLeft),
                     ']' => self.make_token(BracketRight),
-                    '"' => self.lex_string_literal(),
+                    '"' => {
+                        if self.peek() == Some('"') && self.peek2() == Some('"') {
+                            self.bump();
+                            self.bump();
+                            self.lex_block_string()?
+                        } else {
+                            self.lex_string_literal()
+                        }
+                    }
 
                     // --------------------------------------------------------
                     // Comparison
@@ -229,6 +254,32 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// Iterate the tokens of the source text.
+///
+/// The [`TokenKind::Eof`] token is yielded once, after which the iterator is exhausted.
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.next_token();
+        if matches!(
+            result,
+            Ok(Token {
+                kind: TokenKind::Eof,
+                ..
+            })
+        ) {
+            self.done = true;
+        }
+
+        Some(result)
+    }
+}
+
 impl<'a> Lexer<'a> {
     /// Ignore all whitespace. Newlines are not significant to this language.
     fn ignore_whitespace(&mut self) {
@@ -253,12 +304,10 @@ impl<'a> Lexer<'a> {
 
     fn ignore_block_comment(&mut self) {
         while let Some(ch) = self.peek() {
-            if ch == '*' {
-                if self.peek2() == Some('/') {
-                    self.bump();
-                    self.bump();
-                    break;
-                }
+            if ch == '*' && self.peek2() == Some('/') {
+                self.bump();
+                self.bump();
+                break;
             }
             self.bump();
         }
@@ -281,13 +330,17 @@ impl<'a> Lexer<'a> {
 
         match self.fragment() {
             "and"    => Some(And),
+            "false"  => Some(False),
             "fn"     => Some(Fn),
             "for"    => Some(For),
             "let"    => Some(Let),
             "if"     => Some(If),
             "import" => Some(Import),
+            "nil"    => Some(Nil),
             "or"     => Some(Or),
+            "return" => Some(Return),
             "struct" => Some(Struct),
+            "true"   => Some(True),
             "type"   => Some(Type),
             "while"  => Some(While),
             _ => None,
@@ -295,23 +348,118 @@ impl<'a> Lexer<'a> {
     }
 
     /// Numbers are sequences of digits.
+    ///
+    /// The first digit has already been consumed. The kind of number is
+    /// decided by what follows the leading digits:
+    ///
+    /// | Source    | Leading | Followed by                        | Result |
+    /// |-----------|---------|------------------------------------|--------|
+    /// | `0xFF`    | `0`     | `x` or `X`, then hex digits        | Int    |
+    /// | `0.5`     | digits  | `.` then a digit                   | Float  |
+    /// | `1.5e3`   | digits  | `.` then a digit, then an exponent | Float  |
+    /// | `0e0`     | digits  | `e` or `E`, optional sign, a digit | Float  |
+    /// | `0`, `42` | digits  | anything else                      | Int    |
+    ///
+    /// A dot that isn't followed by a digit is member access on an
+    /// integer, like `1.foo`. Hexadecimal floats like `0x1p4` are
+    /// not supported.
     fn lex_number(&mut self) -> Result<Token> {
         // trace!("    lex_number()");
 
+        if self.fragment() == "0" && matches!(self.peek(), Some('x' | 'X')) {
+            return self.lex_hex_number();
+        }
+
+        self.ignore_digits();
+
+        let mut is_float = false;
+
+        if self.peek() == Some('.') && self.peek2().map(|ch| ch.is_ascii_digit()).unwrap_or(false) {
+            self.bump(); // .
+            self.ignore_digits();
+            is_float = true;
+        }
+
+        if self.is_exponent() {
+            self.bump(); // e
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.bump();
+            }
+            self.ignore_digits();
+            is_float = true;
+        }
+
+        if is_float {
+            let fragment = self.fragment();
+            let value = fragment
+                .parse::<f64>()
+                .map(LitValue::Float)
+                .map_err(|err| lexer_err(format!("failed to parse float literal: {err}")))?;
+
+            Ok(self.make_literal(TokenKind::Float, value))
+        } else {
+            let fragment = self.fragment();
+            let value = fragment
+                .parse::<i64>()
+                .map(LitValue::Int)
+                .map_err(|err| lexer_err(format!("failed to parse integer literal: {err}")))?;
+
+            Ok(self.make_literal(TokenKind::Int, value))
+        }
+    }
+
+    /// Hexadecimal integer literal, like `0xFF`.
+    ///
+    /// The leading `0` has been consumed, and the `x` is next.
+    fn lex_hex_number(&mut self) -> Result<Token> {
+        self.bump(); // x
+
         while let Some(ch) = self.peek() {
-            if ch.is_ascii_digit() {
+            if ch.is_ascii_hexdigit() {
                 self.bump();
             } else {
                 break;
             }
         }
 
-        let fragment = self.fragment();
-        let value = i64::from_str_radix(fragment, 10)
+        // Skip the 0x prefix.
+        let digits = &self.fragment()[2..];
+        if digits.is_empty() {
+            return lexer_err("hexadecimal literal has no digits").into();
+        }
+
+        let value = i64::from_str_radix(digits, 16)
             .map(LitValue::Int)
-            .map_err(|err| lexer_err(format!("failed to parser number literal: {err}")))?;
+            .map_err(|err| lexer_err(format!("failed to parse hexadecimal literal: {err}")))?;
+
+        Ok(self.make_literal(TokenKind::Int, value))
+    }
+
+    /// Checks whether the cursor is at the exponent part of a float literal.
+    ///
+    /// An exponent is `e` or `E`, followed by an optional sign, and at least one digit.
+    fn is_exponent(&self) -> bool {
+        let mut chars = self.rest.chars();
+
+        if !matches!(chars.next(), Some('e' | 'E')) {
+            return false;
+        }
 
-        Ok(self.make_literal(TokenKind::Num, value))
+        match chars.next() {
+            Some('+' | '-') => chars.next().map(|ch| ch.is_ascii_digit()).unwrap_or(false),
+            Some(ch) => ch.is_ascii_digit(),
+            None => false,
+        }
+    }
+
+    fn ignore_digits(&mut self) {
+        while let Some(ch) = self.peek() {
+            if ch.is_ascii_digit() {
+                self.bump();
+            } else {
+                break;
+            }
+        }
     }
 
     /// Identifiers start with a letter or underscore,
@@ -349,14 +497,129 @@ impl<'a> Lexer<'a> {
 
         self.make_literal(TokenKind::Str, LitValue::Str(value))
     }
+
+    /// Block strings are enclosed in triple quotes, and have their
+    /// common leading indentation stripped.
+    ///
+    /// ```text
+    /// """
+    ///     first
+    ///       second
+    ///     """
+    /// ```
+    ///
+    /// The opening quotes have already been consumed, and must be followed by
+    /// a new line. When the closing quotes are on their own line, that line
+    /// is not part of the string, and no line may be less indented than it.
+    ///
+    /// Indentation may be either tabs or spaces, but the stripped indentation
+    /// must be identical on every line. Since the width of a tab is ambiguous,
+    /// mixing the two is an error.
+    fn lex_block_string(&mut self) -> Result<Token> {
+        // The rest of the opening line must be empty.
+        loop {
+            match self.bump() {
+                Some((_, ' ' | '\t' | '\r')) => {}
+                Some((_, '\n')) => break,
+                Some(_) => return lexer_err("block string must start on a new line").into(),
+                None => return lexer_err("unterminated block string").into(),
+            }
+        }
+
+        let start = self.pos();
+
+        while !self.rest.starts_with(r#"""""#) {
+            if self.bump().is_none() {
+                return lexer_err("unterminated block string").into();
+            }
+        }
+
+        let text = self.text;
+        let raw = &text[start..self.pos()];
+
+        // Closing quotes
+        for _ in 0..3 {
+            self.bump();
+        }
+
+        let value = dedent_block_string(raw)?;
+
+        Ok(self.make_literal(TokenKind::Str, LitValue::Str(value)))
+    }
+}
+
+/// Skip the UTF-8 byte-order mark at the start of the source text, if any.
+///
+/// Token spans remain offsets into the full text, including the mark.
+fn skip_bom(text: &str) -> &str {
+    text.strip_prefix('\u{FEFF}').unwrap_or(text)
+}
+
+/// Strip the common leading indentation from the lines of a block string.
+///
+/// See [`Lexer::lex_block_string()`].
+fn dedent_block_string(raw: &str) -> Result<String> {
+    fn is_indent(ch: char) -> bool {
+        ch == ' ' || ch == '\t'
+    }
+
+    fn is_blank(line: &str) -> bool {
+        line.chars().all(is_indent)
+    }
+
+    fn indent(line: &str) -> &str {
+        &line[..line.len() - line.trim_start_matches(is_indent).len()]
+    }
+
+    let mut lines: Vec<&str> = raw
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+
+    // When the closing quotes are on their own line, the whitespace before them
+    // is their indentation, and not part of the string.
+    let closing_indent = match lines.last() {
+        Some(last) if is_blank(last) => lines.pop(),
+        _ => None,
+    };
+
+    let common = lines
+        .iter()
+        .filter(|line| !is_blank(line))
+        .map(|line| indent(line))
+        .min_by_key(|indent| indent.len());
+
+    if let Some(common) = common {
+        if lines.iter().any(|line| !is_blank(line) && !line.starts_with(common)) {
+            return lexer_err("inconsistent indentation in block string; mixed tabs and spaces").into();
+        }
+
+        if let Some(closing_indent) = closing_indent {
+            if common.len() < closing_indent.len() {
+                return lexer_err("block string line is less indented than its closing quotes").into();
+            }
+            if !common.starts_with(closing_indent) {
+                return lexer_err("inconsistent indentation in block string; mixed tabs and spaces").into();
+            }
+        }
+    }
+
+    let strip = common.map(str::len).unwrap_or(0);
+    let dedented: Vec<&str> = lines
+        .iter()
+        .map(|line| if is_blank(line) { "" } else { &line[strip..] })
+        .collect();
+
+    Ok(dedented.join("\n"))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::errors::Result;
+    use crate::errors::{ErrorKind, Result};
     use crate::token::Keyword::*;
     use crate::token::TokenKind::*;
+    use crate::token::DEFAULT_TAB_WIDTH;
 
     /// Shorthand convenience function for creating a token.
     fn token(kind: TokenKind, span: (u32, u32)) -> Token {
@@ -371,13 +634,14 @@ mod test {
     #[test]
     #[rustfmt::skip]
     fn test_tokenisation_punctuation() -> Result<()> {
-        let mut lexer = Lexer::from_source(", . = # ;");
+        let mut lexer = Lexer::from_source(", . = # ; ?");
 
-        assert_eq!(lexer.next_token()?, token(Comma, (0, 1)));
-        assert_eq!(lexer.next_token()?, token(Dot,   (2, 1)));
-        assert_eq!(lexer.next_token()?, token(Eq,    (4, 1)));
-        assert_eq!(lexer.next_token()?, token(Hash,  (6, 1)));
-        assert_eq!(lexer.next_token()?, token(Semi,  (8, 1)));
+        assert_eq!(lexer.next_token()?, token(Comma,    (0, 1)));
+        assert_eq!(lexer.next_token()?, token(Dot,      (2, 1)));
+        assert_eq!(lexer.next_token()?, token(Eq,       (4, 1)));
+        assert_eq!(lexer.next_token()?, token(Hash,     (6, 1)));
+        assert_eq!(lexer.next_token()?, token(Semi,     (8, 1)));
+        assert_eq!(lexer.next_token()?, token(Question, (10, 1)));
 
         Ok(())
     }
@@ -395,6 +659,21 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn test_tokenisation_arrow() -> Result<()> {
+        let mut lexer = Lexer::from_source("-> - a - >b");
+
+        assert_eq!(lexer.next_token()?, token(Arrow, (0, 2)));
+        assert_eq!(lexer.next_token()?, token(Minus, (3, 1)));
+        assert_eq!(lexer.next_token()?, token(Ident, (5, 1)));
+        assert_eq!(lexer.next_token()?, token(Minus, (7, 1)));
+        assert_eq!(lexer.next_token()?, token(Great, (9, 1)));
+        assert_eq!(lexer.next_token()?, token(Ident, (10, 1)));
+
+        Ok(())
+    }
+
     #[test]
     #[rustfmt::skip]
     fn test_tokenisation_enclosing() -> Result<()> {
@@ -423,21 +702,205 @@ mod test {
         Ok(())
     }
 
+    fn lex_str_lit(source: &str) -> Result<String> {
+        match Lexer::from_source(source).next_token()?.lit {
+            Some(LitValue::Str(value)) => Ok(value),
+            lit => panic!("expected string literal: {lit:?}"),
+        }
+    }
+
+    #[test]
+    fn test_tokenisation_block_string() -> Result<()> {
+        let source = "\"\"\"\n    first\n      second\n\n    third\n    \"\"\"";
+        assert_eq!(lex_str_lit(source)?, "first\n  second\n\nthird");
+
+        // Closing quotes less indented than the content
+        let source = "\"\"\"\n    first\n      second\n  \"\"\"";
+        assert_eq!(lex_str_lit(source)?, "first\n  second");
+
+        // Closing quotes at the end of the last line
+        let source = "\"\"\"\n\t\tfirst\n\tsecond\"\"\"";
+        assert_eq!(lex_str_lit(source)?, "\tfirst\nsecond");
+
+        // Windows line endings
+        let source = "\"\"\"\r\n  first\r\n  second\r\n  \"\"\"";
+        assert_eq!(lex_str_lit(source)?, "first\nsecond");
+
+        assert_eq!(lex_str_lit("\"\"\"\n\"\"\"")?, "");
+
+        // Regular strings are unaffected
+        assert_eq!(lex_str_lit("\"\"")?, "");
+        assert_eq!(lex_str_lit("\"  a\"")?, "  a");
+
+        // The token spans the whole block, including quotes
+        let mut lexer = Lexer::from_source("\"\"\"\n  a\n  \"\"\" ;");
+        assert_eq!(lexer.next_token()?.span, Span(0, 13));
+        assert_eq!(lexer.next_token()?.kind, Semi);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tokenisation_block_string_errors() {
+        // Mixed tabs and spaces
+        let source = "\"\"\"\n\tfirst\n    second\n\"\"\"";
+        assert!(lex_str_lit(source).is_err());
+
+        // Line less indented than the closing quotes
+        let source = "\"\"\"\n    first\n  second\n    \"\"\"";
+        assert!(lex_str_lit(source).is_err());
+
+        // Closing quotes indented with different whitespace
+        let source = "\"\"\"\n  first\n\t\"\"\"";
+        assert!(lex_str_lit(source).is_err());
+
+        // Content on the opening line
+        assert!(lex_str_lit("\"\"\"first\n\"\"\"").is_err());
+
+        // Unterminated
+        assert!(lex_str_lit("\"\"\"\n  first\n").is_err());
+    }
+
+    #[test]
+    fn test_bom() -> Result<()> {
+        let source = "\u{FEFF}let x";
+
+        let mut lexer = Lexer::from_source(source);
+        let let_ = lexer.next_token()?;
+        assert_eq!(let_, keyword(Let, (3, 3)));
+        assert_eq!(let_.span.fragment(source), "let");
+        assert_eq!(let_.span.line_column(source, DEFAULT_TAB_WIDTH), (1, 1));
+        assert_eq!(let_.span.caret_snippet(source, DEFAULT_TAB_WIDTH), "let x\n^^^");
+        assert_eq!(lexer.next_token()?, token(Ident, (7, 1)));
+        assert_eq!(lexer.next_token()?.kind, Eof);
+
+        // Only a leading mark is skipped.
+        let mut lexer = Lexer::from_source("let \u{FEFF}x");
+        assert_eq!(lexer.next_token()?, keyword(Let, (0, 3)));
+        assert!(lexer.next_token().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tab_width() -> Result<()> {
+        let source = "let a = 1;\n\tlet b =\tfoo;";
+
+        let mut lexer = Lexer::from_source(source);
+        let tokens = std::iter::from_fn(|| lexer.next_token().ok())
+            .take_while(|token| token.kind != Eof)
+            .collect::<Vec<_>>();
+        let foo = &tokens[8];
+        assert_eq!(foo.span.fragment(source), "foo");
+
+        assert_eq!(DEFAULT_TAB_WIDTH, 4);
+        assert_eq!(tokens[0].span.line_column(source, 4), (1, 1));
+        assert_eq!(tokens[5].span.line_column(source, 4), (2, 5));
+        // Tab after `=` on column 11 advances to the next tab stop.
+        assert_eq!(foo.span.line_column(source, 4), (2, 13));
+        assert_eq!(foo.span.caret_snippet(source, 4), "    let b = foo;\n            ^^^");
+
+        assert_eq!(tokens[5].span.line_column(source, 8), (2, 9));
+        assert_eq!(foo.span.line_column(source, 8), (2, 17));
+        assert_eq!(
+            foo.span.caret_snippet(source, 8),
+            "        let b = foo;\n                ^^^"
+        );
+
+        // Errors are rendered with the same tab stops.
+        let err = crate::errors::lexer_err("bad").with_span(foo.span.clone());
+        assert_eq!(
+            err.render_with_tab_width(source, 8),
+            "2:17: lexer error: bad\n        let b = foo;\n                ^^^"
+        );
+
+        Ok(())
+    }
+
     #[test]
     #[rustfmt::skip]
     fn test_tokenisation_keywords() -> Result<()> {
-        let mut lexer = Lexer::from_source("and fn for let if import or struct type while");
+        let mut lexer = Lexer::from_source("and false fn for let if import nil or return struct true type while");
 
         assert_eq!(lexer.next_token()?, keyword(And,    (0, 3)));
-        assert_eq!(lexer.next_token()?, keyword(Fn,     (4, 2)));
-        assert_eq!(lexer.next_token()?, keyword(For,    (7, 3)));
-        assert_eq!(lexer.next_token()?, keyword(Let,    (11, 3)));
-        assert_eq!(lexer.next_token()?, keyword(If,     (15, 2)));
-        assert_eq!(lexer.next_token()?, keyword(Import, (18, 6)));
-        assert_eq!(lexer.next_token()?, keyword(Or,     (25, 2)));
-        assert_eq!(lexer.next_token()?, keyword(Struct, (28, 6)));
-        assert_eq!(lexer.next_token()?, keyword(Type,   (35, 4)));
-        assert_eq!(lexer.next_token()?, keyword(While,  (40, 5)));
+        assert_eq!(lexer.next_token()?, keyword(False,  (4, 5)));
+        assert_eq!(lexer.next_token()?, keyword(Fn,     (10, 2)));
+        assert_eq!(lexer.next_token()?, keyword(For,    (13, 3)));
+        assert_eq!(lexer.next_token()?, keyword(Let,    (17, 3)));
+        assert_eq!(lexer.next_token()?, keyword(If,     (21, 2)));
+        assert_eq!(lexer.next_token()?, keyword(Import, (24, 6)));
+        assert_eq!(lexer.next_token()?, keyword(Nil,    (31, 3)));
+        assert_eq!(lexer.next_token()?, keyword(Or,     (35, 2)));
+        assert_eq!(lexer.next_token()?, keyword(Return, (38, 6)));
+        assert_eq!(lexer.next_token()?, keyword(Struct, (45, 6)));
+        assert_eq!(lexer.next_token()?, keyword(True,   (52, 4)));
+        assert_eq!(lexer.next_token()?, keyword(Type,   (57, 4)));
+        assert_eq!(lexer.next_token()?, keyword(While,  (62, 5)));
+
+        Ok(())
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_tokenisation_numbers() -> Result<()> {
+        let mut lexer = Lexer::from_source("42 4.2 7.foo");
+
+        let int = lexer.next_token()?;
+        assert_eq!(int, token(Int, (0, 2)));
+        assert_eq!(int.lit, Some(LitValue::Int(42)));
+
+        let float = lexer.next_token()?;
+        assert_eq!(float, token(Float, (3, 3)));
+        assert_eq!(float.lit, Some(LitValue::Float(4.2)));
+
+        // Member access on an integer is not a float.
+        assert_eq!(lexer.next_token()?, token(Int,   (7, 1)));
+        assert_eq!(lexer.next_token()?, token(Dot,   (8, 1)));
+        assert_eq!(lexer.next_token()?, token(Ident, (9, 3)));
+
+        Ok(())
+    }
+
+    /// Lex a single number literal.
+    fn lex_number_lit(source: &str) -> Result<(TokenKind, LitValue)> {
+        let token = Lexer::from_source(source).next_token()?;
+        assert_eq!(
+            token.span.count() as usize,
+            source.len(),
+            "number literal must span the whole source"
+        );
+        Ok((token.kind, token.lit.unwrap()))
+    }
+
+    #[test]
+    fn test_tokenisation_number_formats() -> Result<()> {
+        assert_eq!(lex_number_lit("0xFF")?, (Int, LitValue::Int(255)));
+        assert_eq!(lex_number_lit("0x1f")?, (Int, LitValue::Int(31)));
+        assert_eq!(lex_number_lit("1.5e3")?, (Float, LitValue::Float(1500.0)));
+        assert_eq!(lex_number_lit("2E-2")?, (Float, LitValue::Float(0.02)));
+        assert_eq!(lex_number_lit("1e+2")?, (Float, LitValue::Float(100.0)));
+        assert_eq!(lex_number_lit("0.5")?, (Float, LitValue::Float(0.5)));
+        assert_eq!(lex_number_lit("0")?, (Int, LitValue::Int(0)));
+        assert_eq!(lex_number_lit("0e0")?, (Float, LitValue::Float(0.0)));
+
+        // Exponent must have digits, otherwise it's an identifier.
+        let mut lexer = Lexer::from_source("1e");
+        assert_eq!(lexer.next_token()?, token(Int, (0, 1)));
+        assert_eq!(lexer.next_token()?, token(Ident, (1, 1)));
+
+        assert!(Lexer::from_source("0x").next_token().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_iterator() -> Result<()> {
+        let lexer = Lexer::from_source("a + b");
+
+        let kinds = lexer
+            .map(|result| result.map(|token| token.kind))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(kinds, vec![Ident, Plus, Ident, Eof]);
 
         Ok(())
     }
@@ -473,4 +936,20 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_error_span() -> Result<()> {
+        let source = "let x = 1 ! 2;";
+        let mut lexer = Lexer::from_source(source);
+        for _ in 0..4 {
+            lexer.next_token()?;
+        }
+
+        let err = lexer.next_token().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Lexer);
+        assert_eq!(err.span, Some(Span(10, 1)));
+        assert_eq!(err.span.unwrap().fragment(source), "!");
+
+        Ok(())
+    }
 }
@@ -2,11 +2,17 @@
 
 mod array;
 mod ast;
+mod cache;
+mod compiler;
+mod disasm;
 mod env;
 mod errors;
 mod handle;
 mod lexer;
 mod limits;
+mod liveness;
+#[cfg(feature = "nanbox")]
+mod nanbox;
 mod object;
 mod op;
 mod parser;
@@ -19,17 +25,32 @@ mod types;
 mod value;
 mod vm;
 
+pub use cache::CompileCache;
+pub use disasm::disassemble;
+pub use errors::{Error, ErrorKind, Result};
+#[cfg(feature = "nanbox")]
+pub use nanbox::NanBox;
+pub use object::Func;
 pub use op::{shorthand, Op};
-pub use vm::Vm;
+pub use vm::{Coroutine, CoroutineStatus, OpTraceHook, RunState, StepResult, TraceEvent, Vm};
+
+/// Result of successfully compiling a module.
+#[derive(Debug)]
+pub struct Module {
+    /// Non-fatal diagnostics, like unused variables, for the caller to report.
+    pub warnings: Vec<Error>,
+}
 
 /// Compile the given source code text into an executable chunk.
-pub fn compile(source: &str, filename: &str) -> self::errors::Result<()> {
+///
+/// All the syntax errors, or otherwise all the type errors,
+/// found in the source are returned together.
+pub fn compile(source: &str, filename: &str) -> std::result::Result<Module, Vec<Error>> {
     let lexer = self::lexer::Lexer::new(source, filename);
     let mut parser = self::parser::Parser::new(lexer);
-    let block = parser.parse_module()?;
-    println!("Syntax Tree:\n{block:#?}");
+    let mut block = parser.parse_module()?;
     let mut checker = self::typechecker::TypeChecker::new();
-    let _ = checker.check_block(&block)?;
+    let _ = checker.check_module(&mut block)?;
 
     // loop {
     //     let token = lexer.next_token()?;
@@ -39,18 +60,52 @@ pub fn compile(source: &str, filename: &str) -> self::errors::Result<()> {
     //     }
     // }
 
-    Ok(())
+    Ok(Module {
+        warnings: checker.take_warnings(),
+    })
 }
 
-pub fn compile_file(filename: &str) -> self::errors::Result<()> {
-    // TODO: Wrap std::io::Error
-    let source_text = std::fs::read_to_string(filename).unwrap();
+pub fn compile_file(filename: &str) -> std::result::Result<Module, Vec<Error>> {
+    let source_text = std::fs::read_to_string(filename).map_err(|err| vec![self::errors::io_err(filename, err)])?;
     compile(source_text.as_str(), filename)
 }
 
-/// Compile the given string as an expression.
+/// Compile the given string as an expression, into a function
+/// that returns the expression's value when run.
+///
+/// Errors are returned as a list like [`compile_block()`], though
+/// compiling an expression stops at the first error.
 ///
 /// Useful for REPL input.
-pub fn compile_expr(_expression: &str) -> self::errors::Result<()> {
-    todo!("compile bare expression")
+pub fn compile_expr(expression: &str) -> std::result::Result<std::rc::Rc<Func>, Vec<Error>> {
+    let lexer = self::lexer::Lexer::from_source(expression);
+    let mut parser = self::parser::Parser::new(lexer);
+    let mut expr = parser.parse_bare_expr().map_err(|err| vec![err])?;
+    let mut checker = self::typechecker::TypeChecker::new();
+    checker.check_expr(&mut expr).map_err(|err| vec![err])?;
+    self::compiler::compile_expr(&expr)
+        .map(std::rc::Rc::new)
+        .map_err(|err| vec![err])
+}
+
+/// Compile the given string as a block of statements, into a function
+/// that returns the value of its trailing expression when run.
+///
+/// Only straight-line code over locals can be compiled so far.
+/// Useful for REPL input.
+pub fn compile_block(source: &str) -> std::result::Result<std::rc::Rc<Func>, Vec<Error>> {
+    compile_chunk(self::lexer::Lexer::from_source(source))
+}
+
+/// Compile the block of statements read by the lexer into a function.
+///
+/// All the syntax errors, or otherwise all the type errors, are returned together.
+pub(crate) fn compile_chunk(lexer: self::lexer::Lexer) -> std::result::Result<std::rc::Rc<Func>, Vec<Error>> {
+    let mut parser = self::parser::Parser::new(lexer);
+    let mut block = parser.parse_module()?;
+    let mut checker = self::typechecker::TypeChecker::new();
+    checker.check_module(&mut block)?;
+    self::compiler::compile_block(&block)
+        .map(std::rc::Rc::new)
+        .map_err(|err| vec![err])
 }
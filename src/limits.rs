@@ -1,2 +1,20 @@
 pub const MAX_ARG_24: i64 = 1 << 24;
 pub const MIN_ARG24: i64 = !0 << 23;
+
+/// Maximum depth of nested parentheses, brackets and braces in the parser.
+pub const MAX_NESTING_DEPTH: u32 = 128;
+
+/// Maximum number of decimal places when formatting a float as a string.
+pub const MAX_FLOAT_PRECISION: i64 = 32;
+
+/// Maximum number of nested calls before the VM reports a call stack overflow.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// Maximum number of global variable lookups cached by the VM, before the cache is cleared.
+pub const MAX_GLOBAL_CACHE_SIZE: usize = 256;
+
+/// Maximum depth of nested tables and arrays in a serialized value.
+pub const MAX_SERIALIZE_DEPTH: usize = 128;
+
+/// Maximum depth of nested function constants in a bytecode chunk.
+pub const MAX_CHUNK_DEPTH: usize = 128;
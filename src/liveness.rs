@@ -0,0 +1,207 @@
+//! Local variable liveness analysis.
+use crate::ast::*;
+
+/// Last use of each local variable declared in a block.
+///
+/// Locals are numbered in the order they're declared by the block's
+/// own statements. Uses are recorded at the granularity of the block's
+/// statements, so a local read anywhere inside a nested block, like a
+/// loop body, is live until the end of the statement containing it.
+#[derive(Debug)]
+pub(crate) struct Liveness {
+    /// Index of the statement that declares each local.
+    declared_at: Vec<usize>,
+    /// Index of the last statement that uses each local.
+    ///
+    /// A local that's never used is last used by its declaration.
+    last_uses: Vec<usize>,
+}
+
+impl Liveness {
+    /// Compute the last use of each local declared in the block.
+    ///
+    /// A name refers to the latest local declared with it before the
+    /// statement, so shadowed locals are tracked separately. Names that
+    /// aren't locals of the block, like globals, are ignored. Assignments
+    /// count as uses, which keeps the local alive until it's assigned.
+    pub(crate) fn analyze(block: &Block) -> Self {
+        let mut liveness = Self {
+            declared_at: Vec::new(),
+            last_uses: Vec::new(),
+        };
+        // Visible locals, with the latest declaration last.
+        let mut scope: Vec<(&str, usize)> = Vec::new();
+
+        for (index, stmt) in block.stmts.iter().enumerate() {
+            let mut visit = |name: &str| {
+                if let Some((_, local_id)) = scope.iter().rev().find(|(local, _)| *local == name) {
+                    liveness.last_uses[*local_id] = index;
+                }
+            };
+
+            match stmt {
+                Stmt::Local(local_decl) => {
+                    if let Some(rhs) = &local_decl.rhs {
+                        visit_expr(rhs, &mut visit);
+                    }
+                    scope.push((local_decl.name.text.as_str(), liveness.last_uses.len()));
+                    liveness.declared_at.push(index);
+                    liveness.last_uses.push(index);
+                }
+                stmt => visit_stmt(stmt, &mut visit),
+            }
+        }
+
+        liveness
+    }
+
+    /// Number of locals declared in the block.
+    pub(crate) fn len(&self) -> usize {
+        self.last_uses.len()
+    }
+
+    pub(crate) fn last_use(&self, local_id: usize) -> usize {
+        self.last_uses[local_id]
+    }
+
+    /// Assign each local a stack slot, in declaration order.
+    ///
+    /// A slot is freed after the last statement that uses its local, and
+    /// reused by the next local declared, so locals that are never alive
+    /// at the same time share a slot. Slot 0 holds the callable, so the
+    /// locals start at 1.
+    pub(crate) fn allocate_slots(&self) -> Vec<usize> {
+        let mut slots: Vec<usize> = Vec::with_capacity(self.len());
+        // Locals that occupy a slot, by their id.
+        let mut active: Vec<usize> = Vec::new();
+        let mut free: Vec<usize> = Vec::new();
+        let mut slot_count = 0;
+
+        for (local_id, &index) in self.declared_at.iter().enumerate() {
+            // The initializer is evaluated before the local is stored, so the
+            // locals it reads for the last time can give up their slots to it.
+            active.retain(|&active_id| {
+                let alive = self.last_use(active_id) > index;
+                if !alive {
+                    free.push(slots[active_id]);
+                }
+                alive
+            });
+
+            let slot = free.pop().unwrap_or_else(|| {
+                slot_count += 1;
+                slot_count
+            });
+            slots.push(slot);
+            active.push(local_id);
+        }
+
+        slots
+    }
+}
+
+/// Call `visit` with every name the statement reads or assigns.
+fn visit_stmt(stmt: &Stmt, visit: &mut impl FnMut(&str)) {
+    match stmt {
+        Stmt::Local(local_decl) => {
+            if let Some(rhs) = &local_decl.rhs {
+                visit_expr(rhs, visit);
+            }
+        }
+        Stmt::Return(return_stmt) => {
+            for item in &return_stmt.value.items {
+                visit_expr(&item.expr, visit);
+            }
+        }
+        Stmt::While(while_stmt) => {
+            visit_expr(&while_stmt.cond, visit);
+            visit_block(&while_stmt.body, visit);
+        }
+        Stmt::TypeDecl(_) => {}
+        Stmt::Expr(expr) | Stmt::Tail(expr) => visit_expr(expr, visit),
+    }
+}
+
+fn visit_block(block: &Block, visit: &mut impl FnMut(&str)) {
+    for stmt in &block.stmts {
+        visit_stmt(stmt, visit);
+    }
+}
+
+fn visit_expr(expr: &Expr, visit: &mut impl FnMut(&str)) {
+    match expr {
+        Expr::Name(name_access) => visit(&name_access.ident.text),
+        Expr::Binary(binary_expr) => {
+            visit_expr(&binary_expr.lhs, visit);
+            visit_expr(&binary_expr.rhs, visit);
+        }
+        Expr::Logical(logical_expr) => {
+            visit_expr(&logical_expr.lhs, visit);
+            visit_expr(&logical_expr.rhs, visit);
+        }
+        Expr::Lit(_) => {}
+        // Locals captured by a function literal are used where it's declared.
+        Expr::Func(func_lit) => visit_block(&func_lit.body, visit),
+        Expr::Call(call_expr) => {
+            visit_expr(&call_expr.callee, visit);
+            for arg in &call_expr.args {
+                visit_expr(arg, visit);
+            }
+            for named_arg in &call_expr.named_args {
+                visit_expr(&named_arg.value, visit);
+            }
+        }
+        Expr::ArrayLit(array_lit) => {
+            for element in &array_lit.elements {
+                visit_expr(element, visit);
+            }
+        }
+        Expr::Index(index_expr) => {
+            visit_expr(&index_expr.target, visit);
+            visit_expr(&index_expr.index, visit);
+        }
+        Expr::Field(field_expr) => visit_expr(&field_expr.target, visit),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn analyze(source: &str) -> Liveness {
+        let block = Parser::new(Lexer::from_source(source)).parse_module().unwrap();
+        Liveness::analyze(&block)
+    }
+
+    #[test]
+    fn test_liveness() {
+        let liveness = analyze("let a = 1; let b = a; let a = 2; while b { f(x: a); } let c = 3; a");
+
+        assert_eq!(liveness.len(), 4);
+        // The first `a` is shadowed after its last read.
+        assert_eq!(liveness.last_use(0), 1);
+        // Used by the loop condition.
+        assert_eq!(liveness.last_use(1), 3);
+        // Read in the loop body, and by the trailing expression.
+        assert_eq!(liveness.last_use(2), 5);
+        // Never read.
+        assert_eq!(liveness.last_use(3), 4);
+    }
+
+    #[test]
+    fn test_slot_reuse() {
+        let liveness = analyze("let a = 1; let b = a + 1; let c = b * 2; let d = 3; let e = c + d; e");
+        let slots = liveness.allocate_slots();
+
+        // Each initializer reads the previous local for the last time and takes
+        // over its slot, except for `d`, which is declared while `c` is alive.
+        assert_eq!(slots, [1, 1, 1, 2, 2]);
+
+        // Slot 0 holds the callable.
+        let stack_size = slots.iter().max().unwrap() + 1;
+        let naive_stack_size = liveness.len() + 1;
+        assert!(stack_size < naive_stack_size, "{stack_size} >= {naive_stack_size}");
+    }
+}
@@ -0,0 +1,409 @@
+//! NaN-boxed value representation.
+//!
+//! Packs a [`Value`] into a single `u64` by storing everything that isn't
+//! a float in the unused payload bits of a quiet `NaN`. Enabled with the
+//! `nanbox` feature.
+//!
+//! This is a standalone encoding for storing values compactly. The VM
+//! still uses the [`Value`] enum on its stack and in its objects, and
+//! values are converted at the boundary with [`NanBox::try_from_value()`]
+//! and [`NanBox::into_value()`].
+//!
+//! ```text
+//! float: any bit pattern that isn't a quiet NaN, or the canonical NaN
+//! boxed: s 11111111111 1 ttt pppp...pppp
+//!        ^ tag bit 3     ^ tag bits 0-2, then the 48-bit payload
+//! ```
+//!
+//! Integers that fit in 48 bits are stored inline, and wider integers are
+//! moved to the heap. Objects are moved to a shared heap cell, so the box
+//! can lend out the same references as [`Value`] does. The payload is a
+//! pointer to the heap, which requires addresses to fit in 48 bits like
+//! they do on current 64-bit platforms. Values with wider addresses can't
+//! be boxed, and are handed back by [`NanBox::try_from_value()`].
+use std::fmt::{self, Formatter};
+use std::rc::Rc;
+
+use crate::array::Array;
+use crate::handle::Handle;
+use crate::object::*;
+use crate::value::Value;
+use crate::vm::Coroutine;
+
+/// Quiet NaN with no payload, which all NaN floats are canonicalised to.
+const QNAN: u64 = 0x7FF8_0000_0000_0000;
+const PAYLOAD_MASK: u64 = (1 << 48) - 1;
+
+const TAG_NIL: u64 = 1;
+const TAG_BOOL: u64 = 2;
+const TAG_INT: u64 = 3;
+const TAG_WIDE_INT: u64 = 4;
+const TAG_UINT: u64 = 5;
+const TAG_OBJECT: u64 = 6;
+
+/// Smallest and largest integers stored inline.
+const INLINE_INT_MIN: i64 = -(1 << 47);
+const INLINE_INT_MAX: i64 = (1 << 47) - 1;
+
+/// A [`Value`] packed into 64 bits.
+///
+/// Owns the value it contains, so objects are kept alive
+/// until the box is dropped.
+pub struct NanBox(u64);
+
+impl NanBox {
+    /// Pack the value into a box.
+    ///
+    /// Returns the value unchanged if it's moved to an address that
+    /// doesn't fit in the 48-bit payload.
+    pub fn try_from_value(value: Value) -> std::result::Result<Self, Value> {
+        let (tag, payload) = match value {
+            Value::Nil => (TAG_NIL, 0),
+            Value::Bool(val) => (TAG_BOOL, val as u64),
+            Value::Int(int) if (INLINE_INT_MIN..=INLINE_INT_MAX).contains(&int) => (TAG_INT, int as u64 & PAYLOAD_MASK),
+            Value::Int(int) => (TAG_WIDE_INT, addr(Rc::into_raw(Rc::new(int)))),
+            Value::UInt(uint) => (TAG_UINT, addr(Rc::into_raw(Rc::new(uint)))),
+            Value::Float(float) if float.is_nan() => return Ok(Self(QNAN)),
+            Value::Float(float) => return Ok(Self(float.to_bits())),
+            Value::Object(object) => (TAG_OBJECT, addr(Rc::into_raw(Rc::new(object)))),
+        };
+
+        if payload > PAYLOAD_MASK {
+            // Take back the heap cell that was released into the pointer.
+            return Err(unsafe { decode_tagged(tag, payload) });
+        }
+
+        Ok(Self::tagged(tag, payload))
+    }
+
+    /// Copy the contained value, sharing any objects.
+    pub fn to_value(&self) -> Value {
+        self.clone().into_value()
+    }
+
+    pub fn into_value(self) -> Value {
+        let value = unsafe { self.decode() };
+        std::mem::forget(self);
+        value
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        (self.tag() == Some(TAG_BOOL)).then(|| self.payload() != 0)
+    }
+
+    pub fn is_nil(&self) -> bool {
+        self.tag() == Some(TAG_NIL)
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self.tag() {
+            Some(TAG_INT) => Some(sign_extend(self.payload())),
+            Some(TAG_WIDE_INT) => Some(unsafe { *ptr::<i64>(self.payload()) }),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self.tag() {
+            None => Some(f64::from_bits(self.0)),
+            Some(_) => None,
+        }
+    }
+
+    pub fn as_func(&self) -> Option<&Rc<Func>> {
+        match self.as_object()? {
+            Object::Func(func_rc) => Some(func_rc),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Handle<Table>> {
+        match self.as_object()? {
+            Object::Table(table_handle) => Some(table_handle),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Handle<Array>> {
+        match self.as_object()? {
+            Object::Array(array_handle) => Some(array_handle),
+            _ => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<&Rc<CrowStr>> {
+        match self.as_object()? {
+            Object::String(string_rc) => Some(string_rc),
+            _ => None,
+        }
+    }
+
+    pub fn as_native(&self) -> Option<&Rc<Native>> {
+        match self.as_object()? {
+            Object::Native(rc) => Some(rc),
+            _ => None,
+        }
+    }
+
+    pub fn as_closure(&self) -> Option<&Rc<Closure>> {
+        match self.as_object()? {
+            Object::Closure(rc) => Some(rc),
+            _ => None,
+        }
+    }
+
+    pub fn as_coroutine(&self) -> Option<&Handle<Coroutine>> {
+        match self.as_object()? {
+            Object::Coroutine(handle) => Some(handle),
+            _ => None,
+        }
+    }
+
+    /// Borrow the object in the heap cell behind the payload pointer.
+    fn as_object(&self) -> Option<&Object> {
+        // The box holds a strong reference to the cell, so the object outlives the borrow.
+        (self.tag() == Some(TAG_OBJECT)).then(|| unsafe { &*ptr::<Object>(self.payload()) })
+    }
+
+    fn tagged(tag: u64, payload: u64) -> Self {
+        debug_assert!(tag > 0 && tag < 16);
+        debug_assert!(payload <= PAYLOAD_MASK);
+        Self(QNAN | ((tag & 0b1000) << 60) | ((tag & 0b0111) << 48) | payload)
+    }
+
+    /// The type tag, or `None` when the box holds a float.
+    fn tag(&self) -> Option<u64> {
+        if self.0 & QNAN != QNAN {
+            return None;
+        }
+        let tag = ((self.0 >> 60) & 0b1000) | ((self.0 >> 48) & 0b0111);
+        (tag != 0).then_some(tag)
+    }
+
+    fn payload(&self) -> u64 {
+        self.0 & PAYLOAD_MASK
+    }
+
+    /// Rebuild the contained value, taking over the box's strong reference
+    /// to its heap cell, if any.
+    ///
+    /// # Safety
+    ///
+    /// The box must not be dropped afterwards.
+    unsafe fn decode(&self) -> Value {
+        match self.tag() {
+            None => Value::Float(f64::from_bits(self.0)),
+            Some(tag) => decode_tagged(tag, self.payload()),
+        }
+    }
+}
+
+impl Clone for NanBox {
+    fn clone(&self) -> Self {
+        // Heap cells are shared, so the copy takes another strong reference.
+        match self.tag() {
+            Some(TAG_WIDE_INT) => unsafe { Rc::increment_strong_count(ptr::<i64>(self.payload())) },
+            Some(TAG_UINT) => unsafe { Rc::increment_strong_count(ptr::<u64>(self.payload())) },
+            Some(TAG_OBJECT) => unsafe { Rc::increment_strong_count(ptr::<Object>(self.payload())) },
+            _ => {}
+        }
+        Self(self.0)
+    }
+}
+
+impl Drop for NanBox {
+    fn drop(&mut self) {
+        drop(unsafe { self.decode() });
+    }
+}
+
+impl fmt::Debug for NanBox {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "NanBox(0x{:016x})", self.0)
+    }
+}
+
+/// Rebuild a value from its tag and payload, taking over the strong
+/// reference to its heap cell, if any.
+///
+/// The value is moved out of the cell when that's the last reference,
+/// and cloned otherwise.
+///
+/// # Safety
+///
+/// The payload must have been created for the tag by [`NanBox::try_from_value()`],
+/// and its strong reference must not be taken again.
+unsafe fn decode_tagged(tag: u64, payload: u64) -> Value {
+    match tag {
+        TAG_NIL => Value::Nil,
+        TAG_BOOL => Value::Bool(payload != 0),
+        TAG_INT => Value::Int(sign_extend(payload)),
+        TAG_WIDE_INT => Value::Int(Rc::unwrap_or_clone(Rc::from_raw(ptr::<i64>(payload)))),
+        TAG_UINT => Value::UInt(Rc::unwrap_or_clone(Rc::from_raw(ptr::<u64>(payload)))),
+        TAG_OBJECT => Value::Object(Rc::unwrap_or_clone(Rc::from_raw(ptr::<Object>(payload)))),
+        _ => unreachable!("invalid NaN-box tag: {tag}"),
+    }
+}
+
+/// Address of a pointer, as a payload.
+///
+/// The address may be wider than [`PAYLOAD_MASK`], which callers must check.
+fn addr<T>(ptr: *const T) -> u64 {
+    ptr.expose_provenance() as u64
+}
+
+/// Pointer from a payload created by [`addr()`].
+fn ptr<T>(payload: u64) -> *const T {
+    std::ptr::with_exposed_provenance(payload as usize)
+}
+
+fn sign_extend(payload: u64) -> i64 {
+    ((payload << 16) as i64) >> 16
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::errors::Result;
+
+    fn pack(value: Value) -> NanBox {
+        NanBox::try_from_value(value).expect("value should fit in a NaN-box")
+    }
+
+    fn round_trip(value: Value) -> Value {
+        pack(value).into_value()
+    }
+
+    #[test]
+    fn test_nanbox_size() {
+        assert_eq!(std::mem::size_of::<NanBox>(), 8);
+    }
+
+    #[test]
+    fn test_nanbox_numbers() {
+        for int in [
+            0,
+            1,
+            -1,
+            INLINE_INT_MIN,
+            INLINE_INT_MAX,
+            INLINE_INT_MIN - 1,
+            INLINE_INT_MAX + 1,
+            i64::MIN,
+            i64::MAX,
+        ] {
+            let boxed = pack(Value::Int(int));
+            assert_eq!(boxed.as_int(), Some(int));
+            assert_eq!(boxed.as_float(), None);
+            assert_eq!(boxed.clone().into_value().as_int(), Some(int));
+            assert_eq!(boxed.to_value().as_int(), Some(int));
+        }
+
+        assert!(pack(Value::Nil).is_nil());
+        assert!(matches!(round_trip(Value::Nil), Value::Nil));
+        for boolean in [true, false] {
+            assert_eq!(pack(Value::Bool(boolean)).as_bool(), Some(boolean));
+            assert!(matches!(round_trip(Value::Bool(boolean)), Value::Bool(value) if value == boolean));
+        }
+
+        for uint in [0, u64::MAX] {
+            assert!(matches!(round_trip(Value::UInt(uint)), Value::UInt(value) if value == uint));
+        }
+
+        for float in [
+            0.0,
+            -0.0,
+            1.5,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ] {
+            let boxed = pack(Value::Float(float));
+            assert_eq!(boxed.as_float().map(f64::to_bits), Some(float.to_bits()));
+            assert_eq!(boxed.as_int(), None);
+        }
+
+        // Every NaN is canonicalised, so its bits can't be mistaken for a tag.
+        let other_nan = f64::from_bits(0xFFF8_0000_0000_0001);
+        for nan in [f64::NAN, -f64::NAN, other_nan] {
+            let boxed = pack(Value::Float(nan));
+            assert!(boxed.as_float().unwrap().is_nan());
+            assert_eq!(boxed.as_int(), None);
+        }
+    }
+
+    #[test]
+    fn test_nanbox_objects() -> Result<()> {
+        let func = Rc::new(Func {
+            code: Box::new([crate::op::Op::End]),
+            stack_size: 1,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+        });
+        let closure = Rc::new(Closure::new(func.clone()));
+        let string = Rc::new(CrowStr::new("hello"));
+
+        let boxed = pack(Value::from_closure(closure.clone()));
+        assert_eq!(Rc::strong_count(&closure), 2);
+        assert!(Rc::ptr_eq(boxed.as_closure().unwrap(), &closure));
+        assert!(boxed.as_func().is_none());
+        assert!(boxed.as_int().is_none());
+        assert!(!boxed.is_nil());
+
+        // Copies of the box share the object's heap cell.
+        let copy = boxed.clone();
+        assert_eq!(Rc::strong_count(&closure), 2);
+        drop(boxed);
+        let value = copy.to_value();
+        assert_eq!(Rc::strong_count(&closure), 3);
+        drop(copy);
+        drop(value);
+        assert_eq!(Rc::strong_count(&closure), 1);
+
+        let boxed = pack(Value::from_func(func.clone()));
+        assert!(Rc::ptr_eq(boxed.as_func().unwrap(), &func));
+        assert!(Rc::ptr_eq(boxed.into_value().as_func().unwrap(), &func));
+
+        let boxed = pack(Value::Object(Object::String(string.clone())));
+        assert_eq!(boxed.as_string().unwrap().as_str(), "hello");
+        assert!(Rc::ptr_eq(boxed.to_value().as_string().unwrap(), &string));
+        drop(boxed);
+        assert_eq!(Rc::strong_count(&string), 1);
+
+        let table = Handle::new(Table::new());
+        let boxed = pack(Value::Object(Object::Table(table.clone())));
+        assert!(boxed.as_table().unwrap().ptr_eq(&table));
+        assert!(boxed.into_value().as_table().unwrap().ptr_eq(&table));
+
+        let array = Handle::new(Array::new());
+        let boxed = pack(Value::Object(Object::Array(array.clone())));
+        assert!(boxed.as_array().unwrap().ptr_eq(&array));
+        let value = boxed.into_value();
+        assert!(value.as_array().unwrap().ptr_eq(&array));
+        drop(value);
+        assert_eq!(array.strong_count(), 1);
+
+        let boxed = pack(Value::from_native(Native::new(7_u32)));
+        assert_eq!(boxed.as_native().unwrap().downcast_ref::<u32>(), Some(&7));
+        let value = boxed.into_value();
+        assert_eq!(value.as_native().unwrap().downcast_ref::<u32>(), Some(&7));
+
+        fn native(_vm: &mut crate::vm::Vm, _args: &[Value]) -> Result<Vec<Value>> {
+            Ok(vec![])
+        }
+        let value = round_trip(Value::Object(Object::NativeFn(native)));
+        assert!(
+            matches!(value, Value::Object(Object::NativeFn(native_fn)) if std::ptr::fn_addr_eq(native_fn, native as NativeFn))
+        );
+
+        Ok(())
+    }
+}
@@ -1,20 +1,30 @@
 //! Objects (heap allocated reference types)
+use std::any::Any;
 use std::cell::RefCell;
 use std::fmt::{self, Formatter};
 use std::rc::Rc;
 
 use fxhash::FxHashMap;
+use smallvec::SmallVec;
 
+use crate::array::Array;
+use crate::errors::{runtime_err, Result};
 use crate::handle::Handle;
+use crate::limits::MAX_CHUNK_DEPTH;
 use crate::op::Op;
-use crate::value::Value;
+use crate::value::{err_truncated, read_bytes, read_len, write_len, Value, ValueKey};
+use crate::vm::{Coroutine, Vm};
 
 #[derive(Clone)]
 pub enum Object {
     Closure(Rc<Closure>),
     Func(Rc<Func>),
     Table(Handle<Table>),
+    Array(Handle<Array>),
     String(Rc<CrowStr>),
+    Native(Rc<Native>),
+    NativeFn(NativeFn),
+    Coroutine(Handle<Coroutine>),
 }
 
 impl fmt::Debug for Object {
@@ -23,7 +33,11 @@ impl fmt::Debug for Object {
             Object::Closure(rc) => write!(f, "Closure(0x{:?})", Rc::as_ptr(rc)),
             Object::Func(rc) => write!(f, "Func(0x{:?})", Rc::as_ptr(rc)),
             Object::Table(table) => write!(f, "Table({:?})", table.borrow().data),
+            Object::Array(array) => write!(f, "{:?}", array.borrow()),
             Object::String(string) => write!(f, "{:?}", string.as_str()),
+            Object::Native(rc) => write!(f, "Native(0x{:?})", Rc::as_ptr(rc)),
+            Object::NativeFn(func) => write!(f, "NativeFn(0x{:?})", *func as *const ()),
+            Object::Coroutine(coroutine) => write!(f, "Coroutine(0x{:?})", coroutine.as_ptr()),
         }
     }
 }
@@ -36,7 +50,17 @@ pub struct Func {
     /// including the callable object.
     pub(crate) stack_size: u32,
 
+    /// Number of declared parameters, not counting the variadic parameter.
+    pub(crate) arity: u32,
+
     /// Indicates whether the function takes variable arguments.
+    ///
+    /// When called, arguments beyond the function's arity are collected into an
+    /// array, which is bound to the slot after the declared parameters. Missing
+    /// arguments are filled with `nil`, so the array is always in the same slot.
+    /// The `stack_size` must include the array's slot.
+    ///
+    /// An entry function is run without arguments, so nothing is collected.
     pub(crate) is_varg: bool,
 
     pub(crate) constants: Constants,
@@ -92,6 +116,77 @@ pub enum UpValueOrigin {
     Outer(u32), // up-value id
 }
 
+/// Leading bytes of serialized bytecode.
+const CHUNK_MAGIC: [u8; 4] = *b"CROW";
+
+/// Version of the serialized bytecode format.
+///
+/// Must be incremented when the layout of the chunk, or the encoding of an
+/// existing opcode, changes. New opcodes are appended to the end, so chunks
+/// from before they were added still decode.
+const CHUNK_VERSION: u16 = 2;
+
+impl Func {
+    /// Assemble a function prototype from hand written bytecode.
+    ///
+    /// The function takes no arguments, and its only constants are the
+    /// given strings, like the names of the globals it accesses. Useful
+    /// for exercising the VM directly, for example in benchmarks.
+    pub fn from_code(code: impl Into<Box<[Op]>>, stack_size: u32, strings: &[&str]) -> Self {
+        Self {
+            code: code.into(),
+            stack_size,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: strings.iter().map(|string| Rc::new(CrowStr::new(*string))).collect(),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+        }
+    }
+
+    /// Serialize the function, and its nested function constants,
+    /// into a binary chunk.
+    ///
+    /// The chunk starts with a magic header and format version,
+    /// so incompatible bytecode is rejected when loaded.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&CHUNK_MAGIC);
+        buf.extend_from_slice(&CHUNK_VERSION.to_le_bytes());
+        write_func(&mut buf, self);
+        buf
+    }
+
+    /// Deserialize a function from a chunk created by [`Func::to_bytes()`].
+    ///
+    /// Function constants can only be nested a limited number of levels deep.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Rc<Func>> {
+        let mut cursor = bytes;
+
+        if read_bytes::<4>(&mut cursor)? != CHUNK_MAGIC {
+            return runtime_err("not a bytecode chunk").into();
+        }
+        let version = u16::from_le_bytes(read_bytes(&mut cursor)?);
+        if version != CHUNK_VERSION {
+            return runtime_err(format!(
+                "incompatible bytecode version; expected {CHUNK_VERSION}, found {version}"
+            ))
+            .into();
+        }
+
+        let func = read_func(&mut cursor, 0)?;
+        if !cursor.is_empty() {
+            return runtime_err("trailing bytes after bytecode chunk").into();
+        }
+
+        Ok(Rc::new(func))
+    }
+}
+
 struct FuncFmt<'a>(&'a Func);
 
 impl<'a> fmt::Debug for FuncFmt<'a> {
@@ -100,6 +195,12 @@ impl<'a> fmt::Debug for FuncFmt<'a> {
     }
 }
 
+/// List of up-values captured by a closure.
+///
+/// Most closures capture only a few variables, so these are stored
+/// inline to avoid a heap allocation per closure instantiation.
+pub(crate) type UpValues = SmallVec<[Handle<UpValue>; 3]>;
+
 /// A callable instance of a function, optionally with captured outer variables.
 ///
 /// Closures can be stored in vairables and used as values.
@@ -114,18 +215,18 @@ pub struct Closure {
     pub(crate) func: Rc<Func>,
 
     /// List of up-values to outer scope variables.
-    pub(crate) up_values: RefCell<Box<[Handle<UpValue>]>>,
+    pub(crate) up_values: RefCell<UpValues>,
 }
 
 impl Closure {
     pub(crate) fn new(func: Rc<Func>) -> Self {
         Self {
             func,
-            up_values: RefCell::new(Box::new([])),
+            up_values: RefCell::new(UpValues::new()),
         }
     }
 
-    pub(crate) fn with_up_values(func: Rc<Func>, up_values: Box<[Handle<UpValue>]>) -> Self {
+    pub(crate) fn with_up_values(func: Rc<Func>, up_values: UpValues) -> Self {
         Self {
             func,
             up_values: RefCell::new(up_values),
@@ -192,15 +293,65 @@ impl CrowStr {
     }
 }
 
-impl ToString for CrowStr {
-    fn to_string(&self) -> String {
-        self.data.to_string()
+impl fmt::Display for CrowStr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.data)
+    }
+}
+
+/// Opaque data owned by the host, stored in a script value.
+///
+/// An optional finalizer is called with the data when the
+/// last reference to the object is dropped, so the host can
+/// release any resources it's associated with.
+pub struct Native {
+    data: Box<dyn Any>,
+    finalizer: Option<Finalizer>,
+}
+
+/// Callback that releases the resources of a [`Native`] object.
+pub type Finalizer = Box<dyn FnOnce(&mut dyn Any)>;
+
+impl Native {
+    pub fn new(data: impl Any) -> Self {
+        Self {
+            data: Box::new(data),
+            finalizer: None,
+        }
+    }
+
+    /// Set the callback to run when the object is dropped.
+    pub fn with_finalizer(mut self, finalizer: impl FnOnce(&mut dyn Any) + 'static) -> Self {
+        self.finalizer = Some(Box::new(finalizer));
+        self
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.data.downcast_ref()
+    }
+}
+
+impl Drop for Native {
+    fn drop(&mut self) {
+        if let Some(finalizer) = self.finalizer.take() {
+            finalizer(&mut *self.data);
+        }
     }
 }
 
+/// Host function that can be called from bytecode like a closure.
+///
+/// Receives the call's arguments, and returns its results. See [`Vm::register_native()`].
+///
+/// The VM can be used to read and write globals, but running bytecode
+/// with it from inside the native function is an error.
+pub type NativeFn = fn(&mut Vm, &[Value]) -> Result<Vec<Value>>;
+
 /// Hash table.
+///
+/// See [`ValueKey`] for how keys are compared.
 pub struct Table {
-    data: FxHashMap<String, Value>,
+    data: FxHashMap<ValueKey, Value>,
 }
 
 impl Table {
@@ -210,15 +361,154 @@ impl Table {
         }
     }
 
-    pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+    /// Create an empty table with room for at least `capacity` entries.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            data: FxHashMap::with_capacity_and_hasher(capacity, Default::default()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    pub fn insert(&mut self, key: ValueKey, value: Value) -> Option<Value> {
         self.data.insert(key, value)
     }
 
-    pub fn get(&self, key: &str) -> Option<&Value> {
+    pub fn get(&self, key: &ValueKey) -> Option<&Value> {
         self.data.get(key)
     }
 
-    pub fn remove(&mut self, key: &str) {
+    pub fn remove(&mut self, key: &ValueKey) {
         self.data.remove(key);
     }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ValueKey, &Value)> {
+        self.data.iter()
+    }
+}
+
+// ============================================================================ //
+// Serialization                                                                //
+// ============================================================================ //
+
+/// Write the function's fields in declaration order. Lists are
+/// a `u32` length followed by their items.
+///
+/// Panics when a list is too long for its length to be serialized,
+/// which a function within the VM's limits never is.
+fn write_func(buf: &mut Vec<u8>, func: &Func) {
+    let write_len = |buf: &mut Vec<u8>, len: usize| write_len(buf, len).expect("function too large to serialize");
+
+    write_len(buf, func.code.len());
+    for op in func.code.iter() {
+        buf.extend_from_slice(&op.encode());
+    }
+    buf.extend_from_slice(&func.stack_size.to_le_bytes());
+    buf.extend_from_slice(&func.arity.to_le_bytes());
+    buf.push(func.is_varg as u8);
+
+    let constants = &func.constants;
+    write_len(buf, constants.ints.len());
+    for int in constants.ints.iter() {
+        buf.extend_from_slice(&int.to_le_bytes());
+    }
+    write_len(buf, constants.floats.len());
+    for float in constants.floats.iter() {
+        buf.extend_from_slice(&float.to_bits().to_le_bytes());
+    }
+    write_len(buf, constants.strings.len());
+    for string in constants.strings.iter() {
+        write_len(buf, string.as_str().len());
+        buf.extend_from_slice(string.as_str().as_bytes());
+    }
+    write_len(buf, constants.funcs.len());
+    for func in constants.funcs.iter() {
+        write_func(buf, func);
+    }
+
+    write_len(buf, func.up_values.len());
+    for up_value in func.up_values.iter() {
+        let (tag, id) = match up_value {
+            UpValueOrigin::Parent(local_id) => (0, local_id),
+            UpValueOrigin::Outer(up_value_id) => (1, up_value_id),
+        };
+        buf.push(tag);
+        buf.extend_from_slice(&id.to_le_bytes());
+    }
+}
+
+/// Read a function from the cursor.
+///
+/// `depth` is the number of functions the function is a constant of.
+fn read_func(cursor: &mut &[u8], depth: usize) -> Result<Func> {
+    if depth >= MAX_CHUNK_DEPTH {
+        return runtime_err(format!("function constants are nested deeper than {MAX_CHUNK_DEPTH}")).into();
+    }
+
+    let code = read_list(cursor, |cursor| match Op::decode(read_bytes(cursor)?)? {
+        // The VM can't execute these yet, so a chunk using them is rejected up front.
+        op @ (Op::Str_Concat | Op::Str_Slice) => runtime_err(format!("unsupported instruction: {op:?}")).into(),
+        op => Ok(op),
+    })?;
+    let stack_size = u32::from_le_bytes(read_bytes(cursor)?);
+    let arity = u32::from_le_bytes(read_bytes(cursor)?);
+    let [is_varg] = read_bytes(cursor)?;
+
+    let constants = Constants {
+        ints: read_list(cursor, |cursor| Ok(i64::from_le_bytes(read_bytes(cursor)?)))?,
+        floats: read_list(cursor, |cursor| {
+            Ok(f64::from_bits(u64::from_le_bytes(read_bytes(cursor)?)))
+        })?,
+        strings: read_list(cursor, |cursor| {
+            let len = read_len(cursor)?;
+            if cursor.len() < len {
+                return Err(err_truncated());
+            }
+            let (bytes, rest) = cursor.split_at(len);
+            *cursor = rest;
+            let string = std::str::from_utf8(bytes).map_err(|_| runtime_err("string constant is not valid UTF-8"))?;
+            Ok(Rc::new(CrowStr::new(string)))
+        })?,
+        funcs: read_list(cursor, |cursor| read_func(cursor, depth + 1).map(Rc::new))?,
+    };
+
+    let up_values = read_list(cursor, |cursor| {
+        let [tag] = read_bytes(cursor)?;
+        let id = u32::from_le_bytes(read_bytes(cursor)?);
+        match tag {
+            0 => Ok(UpValueOrigin::Parent(id)),
+            1 => Ok(UpValueOrigin::Outer(id)),
+            _ => runtime_err(format!("unknown up-value origin: {tag}")).into(),
+        }
+    })?;
+
+    Ok(Func {
+        code,
+        stack_size,
+        arity,
+        is_varg: is_varg != 0,
+        constants,
+        up_values,
+    })
+}
+
+fn read_list<T>(cursor: &mut &[u8], mut read_item: impl FnMut(&mut &[u8]) -> Result<T>) -> Result<Box<[T]>> {
+    let len = read_len(cursor)?;
+    // The length isn't trusted for preallocation, since a corrupt chunk
+    // would otherwise be able to request an arbitrarily large allocation.
+    let mut items = Vec::new();
+    for _ in 0..len {
+        items.push(read_item(cursor)?);
+    }
+    Ok(items.into_boxed_slice())
 }
@@ -1,6 +1,7 @@
+use std::fmt;
+
 use crate::errors::{runtime_err, Result};
 use crate::limits::*;
-use crate::object::UpValueOrigin;
 
 /// Bytecode instruction.
 #[derive(Debug, Clone, Copy)]
@@ -11,35 +12,97 @@ pub enum Op {
 
     /// Remove and discard the top value from the stack.
     Pop(Arg24),
+    /// Push a copy of the top value of the stack.
+    Dup,
+    /// Exchange the top two values of the stack.
+    Swap,
+    /// End of a function's bytecode.
+    ///
+    /// Equivalent to a [`Op::Return`] with no results.
     End,
     Return {
         /// Actual number of result values returned by the callee.
         results: u8,
     },
+    /// Suspend the running coroutine, passing the values on top of the
+    /// stack to its resumer.
+    ///
+    /// When the coroutine is resumed, the values it is resumed with are
+    /// pushed onto the stack in place of the yielded values. Yielding
+    /// outside of a coroutine is a runtime error.
+    ///
+    /// See [`Vm::resume_coroutine()`](crate::vm::Vm::resume_coroutine).
+    Yield {
+        /// Number of values yielded to the resumer.
+        results: u8,
+    },
 
     /// Call either a script or native function.
+    ///
+    /// The callable is expected at `base`, followed by the arguments
+    /// up to the top of the stack. The callable's slot becomes slot 0
+    /// of the callee's frame, and the arguments its first locals.
+    ///
+    /// ```text
+    /// caller slots: [caller, local, ..., callable, arg0, arg1]
+    ///                                    ^ base
+    /// ```
+    ///
+    /// When the callee returns, its results replace the callable
+    /// and the arguments, starting at `base`. Extra results are
+    /// discarded, so a call with zero `results` leaves the stack
+    /// as it was before the callable was pushed.
     Call {
         /// Stack base relative to the caller's stack base.
+        ///
+        /// Must be greater than zero, since slot 0 is the caller's own callable.
         base: u16,
         /// Number of result values the caller expects to be returned
         /// from the callee.
         results: u8,
     },
 
+    /// Call the callable at `base`, reusing the current function's frame.
+    ///
+    /// The stack is laid out like [`Op::Call`], but the callable and its
+    /// arguments replace the current function, so the callee's results are
+    /// returned directly to the current function's caller. Tail recursion
+    /// therefore runs in constant call stack depth.
+    TailCall {
+        /// Stack base relative to the caller's stack base.
+        ///
+        /// Must be greater than zero, since slot 0 is the caller's own callable.
+        base: u16,
+    },
+
     /// Copy multiple values from the stack offset to the top.
+    ///
+    /// The offset is relative to the current frame's base,
+    /// so slot 0 is the frame's own callable.
     Load {
         offset: u16,
         len: u8,
     },
-    /// Copy multiple values from the top of the stack to the given offset.
+    /// Pop multiple values from the top of the stack, and write them
+    /// in order to the slots starting at the given offset.
+    ///
+    /// The inverse of [`Op::Load`].
     Store {
         offset: u16,
         len: u8,
     },
 
+    /// Copy the top value of the stack into a local variable slot,
+    /// leaving it on the stack as the result of an assignment expression.
     SetLocal {
         slot: u16,
     },
+    /// Pop the top value of the stack into a local variable slot.
+    ///
+    /// Used for assignment statements, where the value is consumed.
+    StoreLocal {
+        slot: u16,
+    },
     GetLocal {
         slot: u16,
     },
@@ -51,9 +114,17 @@ pub enum Op {
         upvalue_id: u16,
     },
 
+    /// Pop the top value off the stack and assign it to a global variable,
+    /// defining it if it doesn't exist yet.
+    ///
+    /// The global is named by a string constant.
     SetGlobal {
         string: u16,
     },
+    /// Push the value of a global variable onto the stack,
+    /// or `nil` if it isn't defined.
+    ///
+    /// The global is named by a string constant.
     GetGlobal {
         string: u16,
     },
@@ -62,32 +133,59 @@ pub enum Op {
     PushIntIn(Arg24),
     /// Push an integer constant onto the stack.
     PushInt(Arg24),
+    /// Push a float constant onto the stack.
     PushFloat(Arg24),
     PushString(Arg24),
     PushFunc(Arg24),
-
-    /// Capture a variable as an up-value for the coming closure creation. See [`Op::CreateClosure`]
-    // CaptureValue(UpValueOrigin),
+    /// Push the boolean `true` onto the stack.
+    PushTrue,
+    /// Push the boolean `false` onto the stack.
+    PushFalse,
+    /// Push `nil` onto the stack.
+    PushNil,
 
     /// Instantiate a new closure object.
     ///
     /// The `func_id` argument is the location of the function prototype
     /// that this closure instantiates.
     ///
-    /// This instruction is preceded by zero or more  [`Op::CaptureValue`] operations
-    /// that setup the stack with up-values.
+    /// The up-values to capture are described by the prototype's
+    /// [`Func::up_values`](crate::object::Func) table, rather than by separate
+    /// capture instructions, which keeps every instruction within 32 bits.
     CreateClosure {
         func_id: Arg24,
     },
 
     // Integer arithmetic.
     Int_Neg,
+    /// Absolute value of the integer on top of the stack.
+    ///
+    /// `i64::MIN` has no positive counterpart, so it results in
+    /// an overflow runtime error instead of wrapping.
+    Int_Abs,
     Int_Add,
     Int_Sub,
     Int_Mul,
     Int_Div,
     Int_Mod,
 
+    // Integer bitwise operations.
+    Int_And,
+    Int_Or,
+    Int_Xor,
+    /// Bitwise complement of the integer on top of the stack.
+    Int_Not,
+    /// Shift left by the integer on top of the stack.
+    ///
+    /// Shifting by 64 bits or more results in zero, and
+    /// shifting by a negative amount is a runtime error.
+    Int_Shl,
+    /// Arithmetic shift right by the integer on top of the stack.
+    ///
+    /// Shifting by 64 bits or more results in zero, and
+    /// shifting by a negative amount is a runtime error.
+    Int_Shr,
+
     // Integer Comparison
     Int_Ne,
     Int_Eq,
@@ -98,6 +196,8 @@ pub enum Op {
 
     // Float arithmetic
     Float_Neg,
+    /// Absolute value of the float on top of the stack.
+    Float_Abs,
     Float_Add,
     Float_Sub,
     Float_Mul,
@@ -105,6 +205,9 @@ pub enum Op {
     Float_Mod,
 
     // Float Comparison
+    //
+    // Equality follows IEEE 754, so `-0.0 == 0.0` and `NaN != NaN`.
+    // Note that table keys are canonicalised differently. See [`crate::value::ValueKey`].
     Float_Ne,
     Float_Eq,
     Float_Lt,
@@ -112,9 +215,64 @@ pub enum Op {
     Float_Gt,
     Float_Ge,
 
+    // Conversion
+    /// Pop an integer radix and an integer, and push the integer
+    /// formatted as a string in that radix.
+    ///
+    /// The radix must be between 2 and 36. Digits above 9 are lowercase letters.
+    Int_ToStr,
+    /// Pop an integer precision and a float, and push the float
+    /// formatted as a string with that many decimal places.
+    ///
+    /// The precision must be between 0 and [`crate::limits::MAX_FLOAT_PRECISION`].
+    Float_ToStr,
+    /// Pop an integer, and push it converted to the nearest float.
+    Int_ToFloat,
+    /// Pop a float, and push it truncated toward zero to an integer.
+    ///
+    /// NaN, and floats beyond the range of an integer, are a runtime error.
+    Float_ToInt,
+
     // String operations
     Str_Concat,
     Str_Slice,
+    /// Pop an integer index and a string, and push the character
+    /// at that index as a new string of length one.
+    ///
+    /// Strings are indexed by Unicode scalar value, not by byte,
+    /// so indexing takes linear time.
+    Str_CharAt,
+    /// Pop a needle string and a haystack string, and push
+    /// whether the haystack contains the needle.
+    ///
+    /// Every string contains the empty string.
+    Str_Contains,
+    /// Pop a prefix string and a string, and push whether the string starts with the prefix.
+    Str_StartsWith,
+    /// Pop a suffix string and a string, and push whether the string ends with the suffix.
+    Str_EndsWith,
+
+    // Containers
+    /// Create an empty array on the top of the stack, with
+    /// room reserved for at least `capacity` elements.
+    NewArray {
+        capacity: Arg24,
+    },
+    /// Create an empty table on the top of the stack, with
+    /// room reserved for at least `capacity` entries.
+    NewTable {
+        capacity: Arg24,
+    },
+
+    // Array
+    /// Pop a value, and append it to the array below it.
+    Array_Push,
+    /// Pop an integer index and an array, and push a copy of the element at that index.
+    Array_Get,
+    /// Pop a value, an integer index and an array, and replace the element at that index.
+    ///
+    /// The index must be within the array's bounds; use [`Op::Array_Push`] to grow the array.
+    Array_Set,
 
     // Hash Table
     /// Create new table intance on the top of the stack.
@@ -122,6 +280,9 @@ pub enum Op {
     /// Insert a value at the given key into the table.
     Table_Insert,
     /// Copy the value at the given key from the table and push it onto the stack.
+    ///
+    /// A missing key pushes `nil`. Use [`Op::Table_Contains`] to tell a missing
+    /// key apart from one that maps to `nil`.
     Table_Get,
     /// Checks whether the given key exists in the table.
     Table_Contains,
@@ -147,6 +308,7 @@ pub enum Op {
     JumpGe {
         addr: Arg24,
     },
+    /// Pop a value and jump if it is the integer zero or `false`.
     JumpZero {
         addr: Arg24,
     },
@@ -156,14 +318,244 @@ pub enum Op {
 }
 
 impl Op {
+    /// Net number of values the instruction pushes onto (positive),
+    /// or pops off (negative), the stack.
+    ///
+    /// How many values a call consumes depends on the stack height above
+    /// its `base`, which the instruction doesn't encode, so a call only
+    /// counts its results. Instructions that leave the function, like
+    /// returns and tail calls, have no effect on its stack.
     pub fn stack_effect(&self) -> isize {
-        todo!()
+        use Op::*;
+
+        match *self {
+            NoOp | End | Return { .. } | TailCall { .. } | Jump { .. } => 0,
+            Pop(n) => -(n.as_usize() as isize),
+            Dup => 1,
+            Swap => 0,
+            Call { results, .. } => results as isize,
+            Yield { results } => -(results as isize),
+            Load { len, .. } => len as isize,
+            Store { len, .. } => -(len as isize),
+            SetLocal { .. } => 0,
+            StoreLocal { .. } | SetUpValue { .. } | SetGlobal { .. } => -1,
+            GetLocal { .. } | GetUpValue { .. } | GetGlobal { .. } => 1,
+            PushIntIn(_)
+            | PushInt(_)
+            | PushFloat(_)
+            | PushString(_)
+            | PushFunc(_)
+            | PushTrue
+            | PushFalse
+            | PushNil
+            | CreateClosure { .. } => 1,
+            Int_Neg | Int_Abs | Int_Not | Float_Neg | Float_Abs | Int_ToFloat | Float_ToInt => 0,
+            Int_Add | Int_Sub | Int_Mul | Int_Div | Int_Mod => -1,
+            Int_And | Int_Or | Int_Xor | Int_Shl | Int_Shr => -1,
+            Int_Ne | Int_Eq | Int_Lt | Int_Le | Int_Gt | Int_Ge => -1,
+            Float_Add | Float_Sub | Float_Mul | Float_Div | Float_Mod => -1,
+            Float_Ne | Float_Eq | Float_Lt | Float_Le | Float_Gt | Float_Ge => -1,
+            Int_ToStr | Float_ToStr => -1,
+            Str_Concat | Str_CharAt | Str_Contains | Str_StartsWith | Str_EndsWith => -1,
+            // String, start and end.
+            Str_Slice => -2,
+            NewArray { .. } | NewTable { .. } | Table_Create => 1,
+            Array_Push => -2,
+            Array_Get => -1,
+            Array_Set => -3,
+            Table_Insert => -3,
+            Table_Get | Table_Contains => -1,
+            Table_Remove => -2,
+            JumpNe { .. } | JumpEq { .. } | JumpLt { .. } | JumpLe { .. } | JumpGt { .. } | JumpGe { .. } => -2,
+            JumpZero { .. } => -1,
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Instruction operand that can be encoded in the serialized bytecode format.
+trait Operand: Sized {
+    fn write(self, buf: &mut [u8; 4], pos: &mut usize);
+    fn read(bytes: &[u8; 4], pos: &mut usize) -> Self;
+}
+
+impl Operand for u8 {
+    fn write(self, buf: &mut [u8; 4], pos: &mut usize) {
+        buf[*pos] = self;
+        *pos += 1;
+    }
+
+    fn read(bytes: &[u8; 4], pos: &mut usize) -> Self {
+        *pos += 1;
+        bytes[*pos - 1]
+    }
+}
+
+impl Operand for u16 {
+    fn write(self, buf: &mut [u8; 4], pos: &mut usize) {
+        buf[*pos..*pos + 2].copy_from_slice(&self.to_le_bytes());
+        *pos += 2;
+    }
+
+    fn read(bytes: &[u8; 4], pos: &mut usize) -> Self {
+        *pos += 2;
+        u16::from_le_bytes([bytes[*pos - 2], bytes[*pos - 1]])
+    }
+}
+
+impl Operand for Arg24 {
+    fn write(self, buf: &mut [u8; 4], pos: &mut usize) {
+        buf[*pos..*pos + 3].copy_from_slice(&self.0);
+        *pos += 3;
+    }
+
+    fn read(bytes: &[u8; 4], pos: &mut usize) -> Self {
+        *pos += 3;
+        Arg24([bytes[*pos - 3], bytes[*pos - 2], bytes[*pos - 1]])
+    }
+}
+
+/// Assign each instruction a stable opcode for the serialized bytecode format.
+///
+/// An encoded instruction is always 4 bytes; the opcode followed by
+/// the operands in declaration order, padded with zeroes.
+macro_rules! opcodes {
+    ($($code:literal => $name:ident $({ $($field:ident),* })? $(($arg:ident))?,)*) => {
+        impl Op {
+            /// Encode the instruction into its serialized form.
+            pub(crate) fn encode(self) -> [u8; 4] {
+                let mut buf = [0; 4];
+                let mut _pos = 1;
+                match self {
+                    $(Op::$name $({ $($field),* })? $(($arg))? => {
+                        buf[0] = $code;
+                        $($(Operand::write($field, &mut buf, &mut _pos);)*)?
+                        $(Operand::write($arg, &mut buf, &mut _pos);)?
+                    })*
+                }
+                buf
+            }
+
+            /// Decode an instruction created by [`Op::encode()`].
+            pub(crate) fn decode(bytes: [u8; 4]) -> Result<Op> {
+                let mut _pos = 1;
+                match bytes[0] {
+                    $($code => Ok(Op::$name
+                        $({ $($field: Operand::read(&bytes, &mut _pos)),* })?
+                        $(({
+                            let $arg = Operand::read(&bytes, &mut _pos);
+                            $arg
+                        }))?
+                    ),)*
+                    code => runtime_err(format!("unknown opcode: {code}")).into(),
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    0 => NoOp,
+    1 => Pop(n),
+    2 => End,
+    3 => Return { results },
+    4 => Call { base, results },
+    5 => TailCall { base },
+    6 => Load { offset, len },
+    7 => Store { offset, len },
+    8 => SetLocal { slot },
+    9 => StoreLocal { slot },
+    10 => GetLocal { slot },
+    11 => SetUpValue { upvalue_id },
+    12 => GetUpValue { upvalue_id },
+    13 => SetGlobal { string },
+    14 => GetGlobal { string },
+    15 => PushIntIn(arg),
+    16 => PushInt(arg),
+    17 => PushFloat(arg),
+    18 => PushString(arg),
+    19 => PushFunc(arg),
+    20 => CreateClosure { func_id },
+    21 => Int_Neg,
+    22 => Int_Abs,
+    23 => Int_Add,
+    24 => Int_Sub,
+    25 => Int_Mul,
+    26 => Int_Div,
+    27 => Int_Mod,
+    28 => Int_Ne,
+    29 => Int_Eq,
+    30 => Int_Lt,
+    31 => Int_Le,
+    32 => Int_Gt,
+    33 => Int_Ge,
+    34 => Float_Neg,
+    35 => Float_Abs,
+    36 => Float_Add,
+    37 => Float_Sub,
+    38 => Float_Mul,
+    39 => Float_Div,
+    40 => Float_Mod,
+    41 => Float_Ne,
+    42 => Float_Eq,
+    43 => Float_Lt,
+    44 => Float_Le,
+    45 => Float_Gt,
+    46 => Float_Ge,
+    47 => Int_ToStr,
+    48 => Float_ToStr,
+    49 => Str_Concat,
+    50 => Str_Slice,
+    51 => Str_CharAt,
+    52 => Str_Contains,
+    53 => Str_StartsWith,
+    54 => Str_EndsWith,
+    55 => NewArray { capacity },
+    56 => NewTable { capacity },
+    57 => Array_Push,
+    58 => Array_Get,
+    59 => Array_Set,
+    60 => Table_Create,
+    61 => Table_Insert,
+    62 => Table_Get,
+    63 => Table_Contains,
+    64 => Table_Remove,
+    65 => JumpNe { addr },
+    66 => JumpEq { addr },
+    67 => JumpLt { addr },
+    68 => JumpLe { addr },
+    69 => JumpGt { addr },
+    70 => JumpGe { addr },
+    71 => JumpZero { addr },
+    72 => Jump { addr },
+    73 => Int_And,
+    74 => Int_Or,
+    75 => Int_Xor,
+    76 => Int_Not,
+    77 => Int_Shl,
+    78 => Int_Shr,
+    79 => Int_ToFloat,
+    80 => Float_ToInt,
+    81 => Dup,
+    82 => Swap,
+    83 => PushTrue,
+    84 => PushFalse,
+    85 => PushNil,
+    86 => Yield { results },
+}
+
+#[derive(Clone, Copy)]
 pub struct Arg24([u8; 3]);
 
+/// Shows the decoded unsigned value, rather than the raw bytes.
+///
+/// Most operands are indices. The disassembler shows the signed
+/// operands, inline integers and jump offsets, sign-extended.
+impl fmt::Debug for Arg24 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_u32())
+    }
+}
+
 impl Arg24 {
     #[inline(always)]
     pub fn as_i64(self) -> i64 {
@@ -235,6 +627,14 @@ pub mod shorthand {
         }
     }
 
+    pub fn dup() -> Op {
+        Op::Dup
+    }
+
+    pub fn swap() -> Op {
+        Op::Swap
+    }
+
     pub fn end() -> Op {
         Op::End
     }
@@ -243,6 +643,10 @@ pub mod shorthand {
         Op::Return { results: result_count }
     }
 
+    pub fn yield_(result_count: u8) -> Op {
+        Op::Yield { results: result_count }
+    }
+
     pub fn call(base: u16, result_count: u8) -> Op {
         Op::Call {
             base,
@@ -252,14 +656,30 @@ pub mod shorthand {
 
     // ...
 
+    pub fn tail_call(base: u16) -> Op {
+        Op::TailCall { base }
+    }
+
     pub fn set_local(slot: u16) -> Op {
         Op::SetLocal { slot }
     }
 
+    pub fn store_local(slot: u16) -> Op {
+        Op::StoreLocal { slot }
+    }
+
     pub fn get_local(slot: u16) -> Op {
         Op::GetLocal { slot }
     }
 
+    pub fn load(offset: u16, len: u8) -> Op {
+        Op::Load { offset, len }
+    }
+
+    pub fn store(offset: u16, len: u8) -> Op {
+        Op::Store { offset, len }
+    }
+
     pub fn set_upvalue(upvalue_id: u16) -> Op {
         Op::SetUpValue { upvalue_id }
     }
@@ -283,6 +703,20 @@ pub mod shorthand {
         }
     }
 
+    pub fn push_int(const_id: u32) -> Op {
+        match Arg24::from_u32(const_id).map(Op::PushInt) {
+            Ok(op) => op,
+            Err(err) => encode_panic(err),
+        }
+    }
+
+    pub fn push_float(const_id: u32) -> Op {
+        match Arg24::from_u32(const_id).map(Op::PushFloat) {
+            Ok(op) => op,
+            Err(err) => encode_panic(err),
+        }
+    }
+
     pub fn push_string(string_id: u32) -> Op {
         match Arg24::from_u32(string_id).map(Op::PushString) {
             Ok(op) => op,
@@ -290,6 +724,25 @@ pub mod shorthand {
         }
     }
 
+    pub fn push_func(func_id: u32) -> Op {
+        match Arg24::from_u32(func_id).map(Op::PushFunc) {
+            Ok(op) => op,
+            Err(err) => encode_panic(err),
+        }
+    }
+
+    pub fn push_true() -> Op {
+        Op::PushTrue
+    }
+
+    pub fn push_false() -> Op {
+        Op::PushFalse
+    }
+
+    pub fn push_nil() -> Op {
+        Op::PushNil
+    }
+
     // ...
 
     pub fn create_closure(func_id: u32) -> Op {
@@ -301,6 +754,14 @@ pub mod shorthand {
 
     // ...
 
+    pub fn int_neg() -> Op {
+        Op::Int_Neg
+    }
+
+    pub fn int_abs() -> Op {
+        Op::Int_Abs
+    }
+
     pub fn int_add() -> Op {
         Op::Int_Add
     }
@@ -309,6 +770,100 @@ pub mod shorthand {
         Op::Int_Sub
     }
 
+    pub fn int_and() -> Op {
+        Op::Int_And
+    }
+
+    pub fn int_or() -> Op {
+        Op::Int_Or
+    }
+
+    pub fn int_xor() -> Op {
+        Op::Int_Xor
+    }
+
+    pub fn int_not() -> Op {
+        Op::Int_Not
+    }
+
+    pub fn int_shl() -> Op {
+        Op::Int_Shl
+    }
+
+    pub fn int_shr() -> Op {
+        Op::Int_Shr
+    }
+
+    pub fn float_neg() -> Op {
+        Op::Float_Neg
+    }
+
+    pub fn float_add() -> Op {
+        Op::Float_Add
+    }
+
+    pub fn float_abs() -> Op {
+        Op::Float_Abs
+    }
+
+    pub fn int_to_str() -> Op {
+        Op::Int_ToStr
+    }
+
+    pub fn float_to_str() -> Op {
+        Op::Float_ToStr
+    }
+
+    pub fn int_to_float() -> Op {
+        Op::Int_ToFloat
+    }
+
+    pub fn float_to_int() -> Op {
+        Op::Float_ToInt
+    }
+
+    pub fn str_char_at() -> Op {
+        Op::Str_CharAt
+    }
+
+    pub fn str_contains() -> Op {
+        Op::Str_Contains
+    }
+
+    pub fn str_starts_with() -> Op {
+        Op::Str_StartsWith
+    }
+
+    pub fn str_ends_with() -> Op {
+        Op::Str_EndsWith
+    }
+
+    pub fn new_array(capacity: u32) -> Op {
+        match Arg24::from_u32(capacity).map(|capacity| Op::NewArray { capacity }) {
+            Ok(op) => op,
+            Err(err) => encode_panic(err),
+        }
+    }
+
+    pub fn new_table(capacity: u32) -> Op {
+        match Arg24::from_u32(capacity).map(|capacity| Op::NewTable { capacity }) {
+            Ok(op) => op,
+            Err(err) => encode_panic(err),
+        }
+    }
+
+    pub fn array_push() -> Op {
+        Op::Array_Push
+    }
+
+    pub fn array_get() -> Op {
+        Op::Array_Get
+    }
+
+    pub fn array_set() -> Op {
+        Op::Array_Set
+    }
+
     pub fn table_create() -> Op {
         Op::Table_Create
     }
@@ -368,4 +923,16 @@ mod test {
         assert_eq!(Arg24::from_i64(1).unwrap().0, [1, 0, 0]);
         assert_eq!(Arg24::from_i64(1).unwrap().as_i64(), 1);
     }
+
+    #[test]
+    fn test_stack_effect() {
+        assert_eq!(shorthand::dup().stack_effect(), 1);
+        assert_eq!(shorthand::swap().stack_effect(), 0);
+        assert_eq!(shorthand::pop(3).stack_effect(), -3);
+        assert_eq!(shorthand::push_int_inlined(1).stack_effect(), 1);
+        assert_eq!(shorthand::int_add().stack_effect(), -1);
+        assert_eq!(shorthand::int_neg().stack_effect(), 0);
+        assert_eq!(shorthand::load(0, 2).stack_effect(), 2);
+        assert_eq!(shorthand::call(1, 2).stack_effect(), 2);
+    }
 }
@@ -1,8 +1,9 @@
 //! Syntactic parser.
 use crate::ast::*;
-use crate::errors::{parser_err, Result};
+use crate::errors::{parser_err, Error, ErrorKind, Result};
 use crate::lexer::Lexer;
-use crate::token::{Associativity, LitValue, Precedence, Token, TokenKind};
+use crate::limits::MAX_NESTING_DEPTH;
+use crate::token::{Associativity, Keyword, LitValue, Precedence, Span, Token, TokenKind};
 use crate::types::TypeId;
 
 macro_rules! trace {
@@ -18,18 +19,71 @@ pub struct Parser<'a> {
     lexer: Lexer<'a>,
     /// The current token, if the next has been peeked.
     token: Option<Token>,
+    /// Span of the most recently consumed token.
+    last_span: Span,
+    /// Errors recovered from while parsing statements.
+    errors: Vec<Error>,
+    /// Current depth of nested enclosures and operators.
+    nesting: u32,
+    /// Maximum depth of nested enclosures and operators, to guard
+    /// against pathological input overflowing the host stack.
+    max_nesting: u32,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(lexer: Lexer<'a>) -> Self {
-        Self { lexer, token: None }
+        Self {
+            lexer,
+            token: None,
+            last_span: Span::new(0, 0),
+            errors: Vec::new(),
+            nesting: 0,
+            max_nesting: MAX_NESTING_DEPTH,
+        }
     }
 
-    fn next_token(&mut self) -> Result<Token> {
-        match self.token.take() {
-            Some(token) => Ok(token),
-            None => self.lexer.next_token(),
+    /// Set the maximum depth of nested enclosures and operators.
+    ///
+    /// Defaults to [`MAX_NESTING_DEPTH`].
+    #[allow(dead_code)]
+    pub fn with_max_nesting(mut self, max_nesting: u32) -> Self {
+        self.max_nesting = max_nesting;
+        self
+    }
+
+    /// Parse the contents of an enclosure one level of nesting deeper.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.enter_nesting()?;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    /// Go one level of nesting deeper, unless that exceeds the limit.
+    fn enter_nesting(&mut self) -> Result<()> {
+        if self.nesting >= self.max_nesting {
+            return parser_err("nesting too deep").into();
         }
+
+        self.nesting += 1;
+        Ok(())
+    }
+
+    fn next_token(&mut self) -> Result<Token> {
+        let token = match self.token.take() {
+            Some(token) => token,
+            None => self.lexer.next_token()?,
+        };
+        self.last_span = token.span.clone();
+        Ok(token)
+    }
+
+    /// Point the error at the offending token.
+    ///
+    /// That's the peeked token if there is one, otherwise the token consumed last.
+    fn spanned(&self, err: Error) -> Error {
+        let span = self.token.as_ref().map(|token| &token.span).unwrap_or(&self.last_span);
+        err.with_span(span.clone())
     }
 
     fn peek_token(&mut self) -> Result<&Token> {
@@ -67,36 +121,103 @@ impl<'a> Parser<'a> {
     }
 
     /// Parse the source text as if its a top-level module file.
-    pub fn parse_module(&mut self) -> Result<Block> {
+    ///
+    /// The parser recovers from syntax errors in statements, so all
+    /// errors in the module are returned together.
+    pub fn parse_module(&mut self) -> std::result::Result<Block, Vec<Error>> {
         // A module is syntactically identical to a block body.
+        let stmts = self.parse_stmts(TokenKind::Eof).unwrap_or_else(|err| {
+            let err = self.spanned(err);
+            self.errors.push(err);
+            Vec::new()
+        });
+
+        if self.errors.is_empty() {
+            Ok(Block { stmts })
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Parse a block of statements enclosed in curly braces.
+    fn parse_block(&mut self) -> Result<Block> {
+        self.consume_token(TokenKind::BraceLeft)?;
+
         Ok(Block {
-            ty: TypeId::default(),
-            stmts: self.parse_stmts()?,
+            stmts: self.nested(|parser| parser.parse_stmts(TokenKind::BraceRight))?,
         })
     }
 
-    /// Parse zero or more statements.
-    fn parse_stmts(&mut self) -> Result<Vec<Stmt>> {
+    /// Parse zero or more statements, until the given terminating token is consumed.
+    ///
+    /// A statement with a syntax error is recorded and skipped, so parsing
+    /// can continue with the next statement.
+    fn parse_stmts(&mut self, end: TokenKind) -> Result<Vec<Stmt>> {
         use crate::token::{Keyword::*, TokenKind::*};
 
         let mut stmts = Vec::new();
 
         loop {
+            if self.match_token(end)? {
+                break;
+            }
+
+            // Nothing left to recover with.
+            if self.peek_kind()? == Eof {
+                return parser_err(format!("unexpected end of file, expected {end:?}")).into();
+            }
+
             let token = self.next_token()?;
 
-            let stmt = match token.kind {
-                Kw(Let) => self.parse_let_stmt().map(Box::new).map(Stmt::Local)?,
-                Ident => self.parse_expr_stmt(token).map(Box::new).map(Stmt::Expr)?,
-                Eof => break,
-                _ => return parser_err(format!("unexpected token: {:?}", token.kind)).into(),
+            let result = match token.kind {
+                // Empty statements are skipped.
+                Semi => continue,
+                Kw(Let) => self.parse_let_stmt().map(Box::new).map(Stmt::Local),
+                Kw(Return) => self.parse_return_stmt().map(Box::new).map(Stmt::Return),
+                Kw(While) => self.parse_while_stmt().map(Box::new).map(Stmt::While),
+                Kw(Type) => self.parse_type_decl().map(Box::new).map(Stmt::TypeDecl),
+                Ident | Int | Float | Str | ParenLeft | BracketLeft | Kw(Fn | True | False | Nil) => {
+                    self.parse_expr_stmt(token, end)
+                }
+                _ => parser_err(format!("unexpected token: {:?}", token.kind)).into(),
             };
 
-            stmts.push(stmt);
+            match result {
+                Ok(stmt) => stmts.push(stmt),
+                // The lexer can't be trusted to make progress after an error.
+                Err(err) if err.kind == ErrorKind::Lexer => return Err(err),
+                Err(err) => {
+                    let err = self.spanned(err);
+                    self.errors.push(err);
+                    self.synchronize()?;
+                }
+            }
         }
 
         Ok(stmts)
     }
 
+    /// Skip tokens until the likely start of the next statement.
+    ///
+    /// Stops after a semicolon, or before a statement keyword,
+    /// closing brace or the end of file.
+    fn synchronize(&mut self) -> Result<()> {
+        use crate::token::{Keyword::*, TokenKind::*};
+
+        loop {
+            match self.peek_kind()? {
+                Semi => {
+                    self.next_token()?;
+                    return Ok(());
+                }
+                Kw(Let | Return | While | Type) | BraceRight | Eof => return Ok(()),
+                _ => {
+                    self.next_token()?;
+                }
+            }
+        }
+    }
+
     /// Parse a local variable declaration statement.
     fn parse_let_stmt(&mut self) -> Result<LocalDecl> {
         let name = self.parse_ident()?;
@@ -118,17 +239,216 @@ impl<'a> Parser<'a> {
         Ok(LocalDecl { name, ty, rhs })
     }
 
+    /// Parse a return statement.
+    ///
+    /// The `return` keyword has already been consumed.
+    ///
+    /// ```text
+    /// "return" (<expr> ("," <expr>)*)? ";"
+    /// ```
+    fn parse_return_stmt(&mut self) -> Result<ReturnStmt> {
+        let mut items = Vec::new();
+
+        if self.peek_kind()? != TokenKind::Semi {
+            loop {
+                items.push(TupleItem {
+                    expr: self.parse_expr()?,
+                });
+
+                if !self.match_token(TokenKind::Comma)? {
+                    break;
+                }
+            }
+        }
+
+        self.consume_token(TokenKind::Semi)?;
+
+        Ok(ReturnStmt { value: Tuple { items } })
+    }
+
+    /// Parse a type declaration statement.
+    ///
+    /// The `type` keyword has already been consumed.
+    ///
+    /// ```text
+    /// "type" <ident> "=" <type-def> ";"
+    /// ```
+    fn parse_type_decl(&mut self) -> Result<TypeDeclStmt> {
+        let name = self.parse_ident()?;
+
+        if !self.match_token(TokenKind::Eq)? {
+            return parser_err(format!(
+                "expected '=' after type name '{}' in type declaration, found {:?}",
+                name.text,
+                self.peek_kind()?
+            ))
+            .into();
+        }
+
+        let rhs = self.parse_type_def()?;
+        self.consume_token(TokenKind::Semi)?;
+
+        Ok(TypeDeclStmt { name, rhs })
+    }
+
+    /// Parse a while loop statement.
+    ///
+    /// The `while` keyword has already been consumed.
+    fn parse_while_stmt(&mut self) -> Result<WhileStmt> {
+        let cond = self.parse_expr()?;
+        let body = self.parse_block()?;
+
+        Ok(WhileStmt { cond, body })
+    }
+
     /// Parse an expression statement.
     ///
     /// Only a subset of expression may be valid statements.
-    fn parse_expr_stmt(&mut self, _token: Token) -> Result<Expr> {
-        todo!("expression statement")
+    ///
+    /// An expression without a semicolon directly before the end of
+    /// the block is the block's value, and becomes a [`Stmt::Tail`].
+    ///
+    /// The leading token has already been consumed, and is re-injected
+    /// so the expression parser can start from it.
+    ///
+    /// ```text
+    /// <expr> ("=" <expr>)? ";"
+    /// <expr> <end>
+    /// ```
+    fn parse_expr_stmt(&mut self, token: Token, end: TokenKind) -> Result<Stmt> {
+        trace!("parse_expr_stmt({token:?})");
+        debug_assert!(
+            self.token.is_none(),
+            "expression statement token re-injected after peek"
+        );
+        self.token = Some(token);
+
+        // Stop short of assignment, so the target can be validated.
+        let expr = self.parse_precedence(Precedence::Assignment + 1)?;
+
+        let expr = if self.match_token(TokenKind::Eq)? {
+            if !matches!(expr, Expr::Name(_) | Expr::Index(_) | Expr::Field(_)) {
+                return parser_err("invalid assignment target").into();
+            }
+
+            Expr::Binary(Box::new(BinaryExpr {
+                operand_ty: TypeId::default(),
+                op: BinaryOp::Assign,
+                lhs: expr,
+                rhs: self.parse_expr()?,
+            }))
+        } else if self.peek_kind()? == end {
+            return Ok(Stmt::Tail(Box::new(expr)));
+        } else {
+            if !matches!(expr, Expr::Call(_)) {
+                return parser_err("expression statement must be a call or assignment").into();
+            }
+
+            expr
+        };
+
+        self.consume_token(TokenKind::Semi)?;
+
+        Ok(Stmt::Expr(Box::new(expr)))
     }
 }
 
 impl<'a> Parser<'a> {
+    /// Parse a type definition.
+    ///
+    /// ```text
+    /// (<ident> | <type-lit>) "?"?
+    /// ```
     fn parse_type_def(&mut self) -> Result<TypeDef> {
-        todo!("parse type definition")
+        trace!("parse_type_def");
+
+        let token = self.next_token()?;
+
+        let type_def = match token.kind {
+            TokenKind::Ident => TypeDef::Alias(TypeName {
+                text: self.make_ident(&token),
+            }),
+            TokenKind::BracketLeft => self.nested(Self::parse_array_type).map(TypeDef::Lit)?,
+            TokenKind::BraceLeft => self.nested(Self::parse_table_type).map(TypeDef::Lit)?,
+            TokenKind::Kw(Keyword::Struct) => self.nested(Self::parse_struct_type).map(TypeDef::Lit)?,
+            _ => return parser_err(format!("type expected, found {:?}", token.kind)).into(),
+        };
+
+        // Postfix `?` makes the type optional.
+        if self.match_token(TokenKind::Question)? {
+            Ok(TypeDef::Optional(Box::new(type_def)))
+        } else {
+            Ok(type_def)
+        }
+    }
+
+    /// Parse a fixed size or dynamic array type literal.
+    ///
+    /// The opening bracket has already been consumed.
+    ///
+    /// ```text
+    /// "[" <type-def> (";" <int-lit>)? "]"
+    /// ```
+    fn parse_array_type(&mut self) -> Result<TypeLit> {
+        let element = self.parse_type_def().map(Box::new)?;
+
+        let type_lit = if self.match_token(TokenKind::Semi)? {
+            let size = match self.consume_token(TokenKind::Int)?.lit {
+                Some(LitValue::Int(size)) => usize::try_from(size).map_err(|_| parser_err("invalid array size"))?,
+                _ => return parser_err("expected integer literal value in token").into(),
+            };
+            TypeLit::Array { element, size }
+        } else {
+            TypeLit::DynArray { element }
+        };
+
+        self.consume_token(TokenKind::BracketRight)?;
+
+        Ok(type_lit)
+    }
+
+    /// Parse a hash table type literal.
+    ///
+    /// The opening brace has already been consumed.
+    ///
+    /// ```text
+    /// "{" <type-def> ":" <type-def> "}"
+    /// ```
+    fn parse_table_type(&mut self) -> Result<TypeLit> {
+        let key = self.parse_type_def().map(Box::new)?;
+        self.consume_token(TokenKind::Colon)?;
+        let value = self.parse_type_def().map(Box::new)?;
+        self.consume_token(TokenKind::BraceRight)?;
+
+        Ok(TypeLit::Table { key, value })
+    }
+
+    /// Parse a structure type literal.
+    ///
+    /// The `struct` keyword has already been consumed.
+    ///
+    /// ```text
+    /// "struct" "{" (<ident> ":" <type-def> ("," <ident> ":" <type-def>)* ","?)? "}"
+    /// ```
+    fn parse_struct_type(&mut self) -> Result<TypeLit> {
+        self.consume_token(TokenKind::BraceLeft)?;
+
+        let mut fields = Vec::new();
+
+        while self.peek_kind()? != TokenKind::BraceRight {
+            let name = self.parse_ident()?;
+            self.consume_token(TokenKind::Colon)?;
+            let ty = self.parse_type_def().map(Box::new)?;
+            fields.push(FieldDef { name, ty });
+
+            if !self.match_token(TokenKind::Comma)? {
+                break;
+            }
+        }
+
+        self.consume_token(TokenKind::BraceRight)?;
+
+        Ok(TypeLit::Struct { fields })
     }
 }
 
@@ -139,6 +459,15 @@ impl<'a> Parser<'a> {
         self.parse_precedence(Precedence::Lowest)
     }
 
+    /// Parse the whole source text as a single expression.
+    ///
+    /// Anything following the expression is an error.
+    pub fn parse_bare_expr(&mut self) -> Result<Expr> {
+        let expr = self.parse_expr().map_err(|err| self.spanned(err))?;
+        self.consume_token(TokenKind::Eof).map_err(|err| self.spanned(err))?;
+        Ok(expr)
+    }
+
     /// Entrypoint for the top-down precedence parser.
     ///
     /// The implementation is a straight forward Pratt parser.
@@ -148,12 +477,22 @@ impl<'a> Parser<'a> {
         let token = self.next_token()?;
         trace!("parse_precedence(..); token -> {token:?}");
 
-        let mut left = self.parse_prefix(token)?;
+        let left = self.parse_prefix(token)?;
+
+        // Each operator nests the expression on its left one level deeper,
+        // so long operator chains count towards the nesting limit too.
+        let nesting = self.nesting;
+        let result = self.parse_infix_chain(left, precedence);
+        self.nesting = nesting;
+        result
+    }
 
-        while precedence <= self.peek_kind().map(|kind| Precedence::of(kind))? {
+    fn parse_infix_chain(&mut self, mut left: Expr, precedence: Precedence) -> Result<Expr> {
+        while precedence <= self.peek_kind().map(Precedence::of)? {
             // When thre is no expression right of the last one, we just return what we have.
+            self.enter_nesting()?;
             let op = self.next_token()?;
-            left = self.parse_infix(left, op).map(Box::new).map(Expr::Binary)?;
+            left = self.parse_infix(left, op)?;
         }
 
         Ok(left)
@@ -165,19 +504,42 @@ impl<'a> Parser<'a> {
         use crate::token::{Keyword::*, TokenKind::*};
 
         match token.kind {
-            Num => self.parse_num_lit(token).map(Literal::Num).map(Box::new).map(Expr::Lit),
+            Int => self.parse_int_lit(token).map(Box::new).map(Expr::Lit),
+            Float => self.parse_float_lit(token).map(Box::new).map(Expr::Lit),
+            Str => self.parse_str_lit(token).map(Literal::Str).map(Box::new).map(Expr::Lit),
             Ident => self.parse_postfix(token),
-            BracketLeft => todo!("array literal"),
+            ParenLeft => self.nested(Self::parse_group),
+            BracketLeft => self.nested(Self::parse_array_lit).map(Box::new).map(Expr::ArrayLit),
             BraceLeft => todo!("table literal"),
             Kw(Fn) => self.parse_func_lit().map(Box::new).map(Expr::Func),
+            Kw(True) => Ok(Expr::Lit(Box::new(Literal::Bool(true)))),
+            Kw(False) => Ok(Expr::Lit(Box::new(Literal::Bool(false)))),
+            Kw(Nil) => Ok(Expr::Lit(Box::new(Literal::Nil))),
             _ => parser_err("expression expected").into(),
         }
     }
 
-    fn parse_infix(&mut self, left: Expr, op: Token) -> Result<BinaryExpr> {
-        use crate::token::TokenKind::*;
+    fn parse_infix(&mut self, left: Expr, op: Token) -> Result<Expr> {
         trace!("parse_infix({left:?}, {op:?})");
 
+        match op.kind {
+            TokenKind::ParenLeft => self.parse_call(left).map(Box::new).map(Expr::Call),
+            TokenKind::BracketLeft => self.parse_index(left).map(Box::new).map(Expr::Index),
+            TokenKind::Dot => self.parse_field(left).map(Box::new).map(Expr::Field),
+            TokenKind::Kw(Keyword::And | Keyword::Or) => self.parse_logical(left, op).map(Box::new).map(Expr::Logical),
+            _ => self.parse_binary(left, op).map(Box::new).map(Expr::Binary),
+        }
+    }
+
+    fn parse_binary(&mut self, left: Expr, op: Token) -> Result<BinaryExpr> {
+        use crate::token::TokenKind::*;
+        trace!("parse_binary({left:?}, {op:?})");
+
+        // Assignment is parsed by the expression statement, since it has no value.
+        if op.kind == Eq {
+            return parser_err("assignment is only valid as a statement").into();
+        }
+
         let precedence = Precedence::of(op.kind);
 
         // Associativity is handled by adjusting the precedence.
@@ -199,7 +561,15 @@ impl<'a> Parser<'a> {
 
         match op.kind {
             // Binary Operations
-            Plus | Minus | Star | Slash | StarStar | Eq | EqEq | NotEq => Ok(BinaryExpr {
+            Plus | Minus | Star | Slash | Perc | StarStar => Ok(BinaryExpr {
+                operand_ty: TypeId::default(),
+                op: Self::parse_binary_op(op.kind)?,
+                lhs: left,
+                rhs: right,
+            }),
+            // Comparison Operations
+            Less | LessEq | Great | GreatEq | EqEq | NotEq => Ok(BinaryExpr {
+                operand_ty: TypeId::default(),
                 op: Self::parse_binary_op(op.kind)?,
                 lhs: left,
                 rhs: right,
@@ -208,6 +578,156 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse a short-circuiting logical expression.
+    ///
+    /// ```text
+    /// <expr> ("and" | "or") <expr>
+    /// ```
+    fn parse_logical(&mut self, left: Expr, op: Token) -> Result<LogicalExpr> {
+        trace!("parse_logical({left:?}, {op:?})");
+
+        let op_kind = match op.kind {
+            TokenKind::Kw(Keyword::And) => LogicalOp::And,
+            TokenKind::Kw(Keyword::Or) => LogicalOp::Or,
+            _ => return parser_err("logical operator expected").into(),
+        };
+
+        // Logical operators are left associative.
+        let right = self.parse_precedence(Precedence::of(op.kind) + 1)?;
+
+        Ok(LogicalExpr {
+            op: op_kind,
+            lhs: left,
+            rhs: right,
+        })
+    }
+
+    /// Parse an expression grouped in parentheses.
+    ///
+    /// The opening parenthesis has already been consumed.
+    ///
+    /// ```text
+    /// "(" <expr> ")"
+    /// ```
+    fn parse_group(&mut self) -> Result<Expr> {
+        trace!("parse_group");
+
+        let expr = self.parse_expr()?;
+        self.consume_token(TokenKind::ParenRight)?;
+
+        Ok(expr)
+    }
+
+    /// Parse a call expression.
+    ///
+    /// The callee has already been parsed, and the opening parenthesis consumed.
+    ///
+    /// ```text
+    /// <expr> "(" (<expr> ("," <expr>)* ","?)? ")"
+    /// ```
+    fn parse_call(&mut self, callee: Expr) -> Result<CallExpr> {
+        trace!("parse_call({callee:?})");
+
+        let mut args = Vec::new();
+        let mut named_args = Vec::new();
+
+        while self.peek_kind()? != TokenKind::ParenRight {
+            let expr = self.parse_expr()?;
+
+            match expr {
+                // Named argument
+                Expr::Name(name_expr) if self.match_token(TokenKind::Colon)? => {
+                    named_args.push(NamedArg {
+                        name: name_expr.ident,
+                        value: self.parse_expr()?,
+                    });
+                }
+                _ if !named_args.is_empty() => {
+                    return parser_err("positional argument cannot follow named arguments").into();
+                }
+                _ => args.push(expr),
+            }
+
+            if !self.match_token(TokenKind::Comma)? {
+                break;
+            }
+        }
+
+        self.consume_token(TokenKind::ParenRight)?;
+
+        Ok(CallExpr {
+            callee: Box::new(callee),
+            args,
+            named_args,
+        })
+    }
+
+    /// Parse an array literal.
+    ///
+    /// The opening bracket has already been consumed.
+    ///
+    /// ```text
+    /// "[" (<expr> ("," <expr>)* ","?)? "]"
+    /// ```
+    fn parse_array_lit(&mut self) -> Result<ArrayLit> {
+        trace!("parse_array_lit");
+
+        let mut elements = Vec::new();
+
+        while self.peek_kind()? != TokenKind::BracketRight {
+            elements.push(self.parse_expr()?);
+
+            if !self.match_token(TokenKind::Comma)? {
+                break;
+            }
+        }
+
+        self.consume_token(TokenKind::BracketRight)?;
+
+        Ok(ArrayLit { elements })
+    }
+
+    /// Parse an index expression.
+    ///
+    /// The target has already been parsed, and the opening bracket consumed.
+    ///
+    /// ```text
+    /// <expr> "[" <expr> "]"
+    /// ```
+    fn parse_index(&mut self, target: Expr) -> Result<IndexExpr> {
+        trace!("parse_index({target:?})");
+
+        let index = self.parse_expr()?;
+
+        if !self.match_token(TokenKind::BracketRight)? {
+            return parser_err(format!(
+                "expected ']' to close index expression, found {:?}",
+                self.peek_kind()?
+            ))
+            .into();
+        }
+
+        Ok(IndexExpr { target, index })
+    }
+
+    /// Parse a field access expression.
+    ///
+    /// The target has already been parsed, and the dot consumed.
+    ///
+    /// ```text
+    /// <expr> "." <ident>
+    /// ```
+    fn parse_field(&mut self, target: Expr) -> Result<FieldExpr> {
+        trace!("parse_field({target:?})");
+
+        if self.peek_kind()? != TokenKind::Ident {
+            return parser_err(format!("expected field name after '.', found {:?}", self.peek_kind()?)).into();
+        }
+        let field = self.parse_ident()?;
+
+        Ok(FieldExpr { target, field })
+    }
+
     /// Parse a postfix expression.
     fn parse_postfix(&mut self, token: Token) -> Result<Expr> {
         trace!("parse_postfix({token:?})");
@@ -221,23 +741,12 @@ impl<'a> Parser<'a> {
         // in an expression as a prefix.
         //
         // The simplest case is the expression is referencing a variable.
-        let mut _expr = Expr::Name(Box::new(NameAccessExpr {
+        //
+        // Calls, indexing and field access are parsed as infix operators.
+        // See [`Parser::parse_infix()`].
+        Ok(Expr::Name(Box::new(NameAccessExpr {
             ident: self.make_ident(&token),
-        }));
-
-        // Keep parsing the rest of the expression to transform `expr`
-        // into something else.
-        loop {
-            match self.peek_kind()? {
-                TokenKind::Eq => todo!("assignment"),
-                TokenKind::BracketLeft => todo!("subscript"),
-                TokenKind::ParenLeft => todo!("call"),
-                TokenKind::Dot => todo!("member access"),
-                _ => break,
-            }
-        }
-
-        todo!("postfix expression")
+        })))
     }
 
     fn parse_binary_op(op_kind: TokenKind) -> Result<BinaryOp> {
@@ -249,16 +758,39 @@ impl<'a> Parser<'a> {
             TokenKind::Perc => Ok(BinaryOp::Mod),
             TokenKind::StarStar => Ok(BinaryOp::Exp),
             TokenKind::Eq => Ok(BinaryOp::Assign),
+            TokenKind::Less => Ok(BinaryOp::Lt),
+            TokenKind::LessEq => Ok(BinaryOp::Le),
+            TokenKind::Great => Ok(BinaryOp::Gt),
+            TokenKind::GreatEq => Ok(BinaryOp::Ge),
+            TokenKind::EqEq => Ok(BinaryOp::Eq),
+            TokenKind::NotEq => Ok(BinaryOp::Ne),
             _ => parser_err("invalid token for binary operation").into(),
         }
     }
 
-    fn parse_num_lit(&mut self, token: Token) -> Result<Number> {
+    fn parse_int_lit(&mut self, token: Token) -> Result<Literal> {
+        let raw = Some(token.span.fragment(self.lexer.text()).to_string());
         match token.lit {
-            Some(LitValue::Int(value)) => Ok(Number::Int(value)),
-            Some(LitValue::Float(value)) => Ok(Number::Float(value)),
-            Some(_) => parser_err("expected number literal value in token, found string literal value").into(),
-            None => parser_err("expected number literal value in token, found none").into(),
+            Some(LitValue::Int(value)) => Ok(Literal::Num(Number::Int(value), raw)),
+            Some(_) => parser_err("expected integer literal value in token").into(),
+            None => parser_err("expected integer literal value in token, found none").into(),
+        }
+    }
+
+    fn parse_float_lit(&mut self, token: Token) -> Result<Literal> {
+        let raw = Some(token.span.fragment(self.lexer.text()).to_string());
+        match token.lit {
+            Some(LitValue::Float(value)) => Ok(Literal::Num(Number::Float(value), raw)),
+            Some(_) => parser_err("expected float literal value in token").into(),
+            None => parser_err("expected float literal value in token, found none").into(),
+        }
+    }
+
+    fn parse_str_lit(&mut self, token: Token) -> Result<String> {
+        match token.lit {
+            Some(LitValue::Str(value)) => Ok(value),
+            Some(_) => parser_err("expected string literal value in token").into(),
+            None => parser_err("expected string literal value in token, found none").into(),
         }
     }
 
@@ -271,10 +803,842 @@ impl<'a> Parser<'a> {
         let fragment = token.span.fragment(self.lexer.text());
         Ident {
             text: fragment.to_string(),
+            span: Some(token.span.clone()),
         }
     }
 
+    /// Parse a function literal.
+    ///
+    /// The `fn` keyword has already been consumed.
+    ///
+    /// ```text
+    /// "fn" "(" (<ident> ":" <ident> ("," <ident> ":" <ident>)* ","?)? ")" ("->" <type-def>)? <block>
+    /// ```
     fn parse_func_lit(&mut self) -> Result<FuncLit> {
-        todo!("parse function literal")
+        trace!("parse_func_lit");
+
+        self.consume_token(TokenKind::ParenLeft)?;
+
+        let mut args = Vec::new();
+
+        while self.peek_kind()? != TokenKind::ParenRight {
+            let name = self.parse_ident()?;
+            self.consume_token(TokenKind::Colon)?;
+            let ty_name = self.parse_ident()?;
+            args.push(Arg { name, ty_name });
+
+            if !self.match_token(TokenKind::Comma)? {
+                break;
+            }
+        }
+
+        self.consume_token(TokenKind::ParenRight)?;
+
+        let return_ = if self.match_token(TokenKind::Arrow)? {
+            Some(self.parse_type_def()?)
+        } else {
+            None
+        };
+
+        let body = self.parse_block()?;
+
+        Ok(FuncLit { args, return_, body })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_expr(source: &str) -> Result<Expr> {
+        Parser::new(Lexer::from_source(source)).parse_expr()
+    }
+
+    /// Parse a module, keeping only the first error.
+    fn parse_module(source: &str) -> Result<Block> {
+        Parser::new(Lexer::from_source(source))
+            .parse_module()
+            .map_err(|mut errors| errors.remove(0))
+    }
+
+    fn parse_module_errors(source: &str) -> Vec<Error> {
+        Parser::new(Lexer::from_source(source)).parse_module().unwrap_err()
+    }
+
+    /// Unwrap a binary expression, or panic.
+    fn binary(expr: &Expr) -> &BinaryExpr {
+        match expr {
+            Expr::Binary(binary_expr) => binary_expr,
+            _ => panic!("expected binary expression: {expr:?}"),
+        }
+    }
+
+    fn int(expr: &Expr) -> i64 {
+        match expr {
+            Expr::Lit(literal) => match **literal {
+                Literal::Num(Number::Int(value), _) => value,
+                _ => panic!("expected integer literal: {literal:?}"),
+            },
+            _ => panic!("expected literal expression: {expr:?}"),
+        }
+    }
+
+    /// Unwrap a call expression, or panic.
+    fn call(expr: &Expr) -> &CallExpr {
+        match expr {
+            Expr::Call(call_expr) => call_expr,
+            _ => panic!("expected call expression: {expr:?}"),
+        }
+    }
+
+    fn name(expr: &Expr) -> &str {
+        match expr {
+            Expr::Name(name) => name.ident.text.as_str(),
+            _ => panic!("expected name expression: {expr:?}"),
+        }
+    }
+
+    #[test]
+    fn test_empty_stmt() -> Result<()> {
+        assert_eq!(parse_module(";")?.stmts.len(), 0);
+        assert_eq!(parse_module(";;")?.stmts.len(), 0);
+        assert_eq!(parse_module("let x = 1;;")?.stmts.len(), 1);
+        assert_eq!(parse_module("; let x = 1; ; let y = 2;")?.stmts.len(), 2);
+
+        Ok(())
+    }
+
+    /// Unwrap the return statement, or panic.
+    fn return_stmt(stmt: &Stmt) -> &ReturnStmt {
+        match stmt {
+            Stmt::Return(return_stmt) => return_stmt,
+            _ => panic!("expected return statement: {stmt:?}"),
+        }
+    }
+
+    fn expr_stmt(stmt: &Stmt) -> &Expr {
+        match stmt {
+            Stmt::Expr(expr) => expr,
+            _ => panic!("expected expression statement: {stmt:?}"),
+        }
+    }
+
+    fn tail_expr(stmt: &Stmt) -> &Expr {
+        match stmt {
+            Stmt::Tail(expr) => expr,
+            _ => panic!("expected trailing expression: {stmt:?}"),
+        }
+    }
+
+    /// Unwrap a local variable declaration, or panic.
+    fn local_decl(stmt: &Stmt) -> &LocalDecl {
+        match stmt {
+            Stmt::Local(local_decl) => local_decl,
+            _ => panic!("expected local variable declaration: {stmt:?}"),
+        }
+    }
+
+    #[test]
+    fn test_optional_type() -> Result<()> {
+        let block = parse_module("let x: Int? = nil;")?;
+        let decl = local_decl(&block.stmts[0]);
+        match &decl.ty {
+            Some(TypeDef::Optional(inner)) => match &**inner {
+                TypeDef::Alias(name) => assert_eq!(name.text.text, "Int"),
+                _ => panic!("expected type alias: {inner:?}"),
+            },
+            _ => panic!("expected optional type: {:?}", decl.ty),
+        }
+        assert!(matches!(decl.rhs, Some(Expr::Lit(ref lit)) if matches!(**lit, Literal::Nil)));
+
+        let block = parse_module("let x: Int;")?;
+        assert!(matches!(local_decl(&block.stmts[0]).ty, Some(TypeDef::Alias(_))));
+
+        assert!(parse_module("let x: Int??;").is_err());
+        assert!(parse_module("let x: ?;").is_err());
+
+        Ok(())
+    }
+
+    /// Parse the type annotation of a local variable declaration.
+    fn parse_type(source: &str) -> Result<TypeDef> {
+        let block = parse_module(&format!("let x: {source};"))?;
+        match block.stmts.into_iter().next() {
+            Some(Stmt::Local(local_decl)) => Ok(local_decl.ty.expect("type annotation")),
+            stmt => panic!("expected local variable declaration: {stmt:?}"),
+        }
+    }
+
+    fn alias(type_def: &TypeDef) -> &str {
+        match type_def {
+            TypeDef::Alias(name) => name.text.text.as_str(),
+            _ => panic!("expected type alias: {type_def:?}"),
+        }
+    }
+
+    #[test]
+    fn test_type_lit() -> Result<()> {
+        match parse_type("[Int; 4]")? {
+            TypeDef::Lit(TypeLit::Array { element, size }) => {
+                assert_eq!(alias(&element), "Int");
+                assert_eq!(size, 4);
+            }
+            type_def => panic!("expected array type: {type_def:?}"),
+        }
+
+        match parse_type("[Float]")? {
+            TypeDef::Lit(TypeLit::DynArray { element }) => assert_eq!(alias(&element), "Float"),
+            type_def => panic!("expected dynamic array type: {type_def:?}"),
+        }
+
+        match parse_type("{String: Int}")? {
+            TypeDef::Lit(TypeLit::Table { key, value }) => {
+                assert_eq!(alias(&key), "String");
+                assert_eq!(alias(&value), "Int");
+            }
+            type_def => panic!("expected table type: {type_def:?}"),
+        }
+
+        match parse_type("struct { a: Int, b: [String], }")? {
+            TypeDef::Lit(TypeLit::Struct { fields }) => {
+                assert_eq!(fields.len(), 2);
+                assert_eq!(fields[0].name.text, "a");
+                assert_eq!(alias(&fields[0].ty), "Int");
+                assert_eq!(fields[1].name.text, "b");
+                assert!(matches!(*fields[1].ty, TypeDef::Lit(TypeLit::DynArray { .. })));
+            }
+            type_def => panic!("expected struct type: {type_def:?}"),
+        }
+
+        match parse_type("struct {}")? {
+            TypeDef::Lit(TypeLit::Struct { fields }) => assert!(fields.is_empty()),
+            type_def => panic!("expected struct type: {type_def:?}"),
+        }
+
+        // Nested types
+        match parse_type("[[Int]]")? {
+            TypeDef::Lit(TypeLit::DynArray { element }) => match *element {
+                TypeDef::Lit(TypeLit::DynArray { element }) => assert_eq!(alias(&element), "Int"),
+                type_def => panic!("expected dynamic array type: {type_def:?}"),
+            },
+            type_def => panic!("expected dynamic array type: {type_def:?}"),
+        }
+
+        match parse_type("{String: [Int?; 2]}?")? {
+            TypeDef::Optional(table) => match *table {
+                TypeDef::Lit(TypeLit::Table { value, .. }) => match *value {
+                    TypeDef::Lit(TypeLit::Array { element, size: 2 }) => {
+                        assert!(matches!(*element, TypeDef::Optional(_)))
+                    }
+                    type_def => panic!("expected array type: {type_def:?}"),
+                },
+                type_def => panic!("expected table type: {type_def:?}"),
+            },
+            type_def => panic!("expected optional type: {type_def:?}"),
+        }
+
+        assert!(parse_type("[Int; 2.0]").is_err());
+        assert!(parse_type("[Int; n]").is_err());
+        assert!(parse_type("{String}").is_err());
+        assert!(parse_type("struct { a }").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_decl() -> Result<()> {
+        let block = parse_module("type Id = Int;")?;
+        match &block.stmts[0] {
+            Stmt::TypeDecl(type_decl) => {
+                assert_eq!(type_decl.name.text, "Id");
+                assert_eq!(alias(&type_decl.rhs), "Int");
+            }
+            stmt => panic!("expected type declaration: {stmt:?}"),
+        }
+
+        let block = parse_module("type Ids = [Id];")?;
+        assert!(matches!(block.stmts[0], Stmt::TypeDecl(_)));
+
+        let err = parse_module("type Id Int;").unwrap_err();
+        assert!(err.to_string().contains("expected '='"), "{err}");
+        assert!(parse_module("type Id = Int").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_return_stmt() -> Result<()> {
+        let block = parse_module("return;")?;
+        assert_eq!(return_stmt(&block.stmts[0]).value.items.len(), 0);
+
+        let block = parse_module("return x;")?;
+        let items = &return_stmt(&block.stmts[0]).value.items;
+        assert_eq!(items.len(), 1);
+        assert_eq!(name(&items[0].expr), "x");
+
+        let block = parse_module("return a, b;")?;
+        let items = &return_stmt(&block.stmts[0]).value.items;
+        assert_eq!(items.len(), 2);
+        assert_eq!(name(&items[0].expr), "a");
+        assert_eq!(name(&items[1].expr), "b");
+
+        // Missing semicolon
+        assert!(parse_module("return x").is_err());
+
+        // Trailing comma
+        assert!(parse_module("return x,;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_while_stmt() -> Result<()> {
+        let block = parse_module("while x { let y = 1; let z = 2; }")?;
+        assert_eq!(block.stmts.len(), 1);
+        let Stmt::While(while_stmt) = &block.stmts[0] else {
+            panic!("expected while statement: {:?}", block.stmts[0]);
+        };
+        assert_eq!(name(&while_stmt.cond), "x");
+        assert_eq!(while_stmt.body.stmts.len(), 2);
+
+        // Missing opening brace
+        assert!(parse_module("while x let y = 1; }").is_err());
+
+        // Missing closing brace
+        assert!(parse_module("while x { let y = 1;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_group() -> Result<()> {
+        // Grouping overrides precedence: 2 * (3 + 4)
+        let expr = parse_expr("2 * (3 + 4)")?;
+        let mul = binary(&expr);
+        assert!(matches!(mul.op, BinaryOp::Mul));
+        assert!(matches!(binary(&mul.rhs).op, BinaryOp::Add));
+
+        assert_eq!(int(&parse_expr("((1))")?), 1);
+        assert!(parse_expr("(1").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |open: &str, close: &str, depth: usize| format!("{}1{}", open.repeat(depth), close.repeat(depth));
+        let max_depth = MAX_NESTING_DEPTH as usize;
+
+        // Each repetition of the enclosures nests this many levels deeper.
+        for (open, close, levels) in [("(", ")", 1), ("[", "]", 1), ("a[", "]", 1), ("([", "])", 2)] {
+            let depth = max_depth / levels;
+            assert!(parse_expr(&nested(open, close, depth)).is_ok(), "{open}");
+            let err = parse_expr(&nested(open, close, depth + 1)).unwrap_err();
+            assert_eq!(err.message, "nesting too deep", "{open}");
+        }
+
+        // Pathological input fails gracefully instead of overflowing the stack.
+        let source = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        let err = parse_expr(&source).unwrap_err();
+        assert_eq!(err.message, "nesting too deep");
+
+        // Blocks count towards the limit.
+        let source = format!("{}{}", "while x { ".repeat(10_000), "}".repeat(10_000));
+        let errors = parse_module_errors(&source);
+        assert!(errors.iter().any(|err| err.message == "nesting too deep"));
+
+        // So do operator chains, which nest the expression on their left.
+        for chain in [" + 1", " ** 1", ".a", "[0]", "(1)"] {
+            let source = format!("let x = 1{};", chain.repeat(max_depth));
+            assert!(parse_module(&source).is_ok(), "{chain}");
+            let source = format!("let x = 1{};", chain.repeat(10_000));
+            let err = parse_module(&source).unwrap_err();
+            assert_eq!(err.message, "nesting too deep", "{chain}");
+        }
+    }
+
+    #[test]
+    fn test_max_nesting() {
+        let parse_nested = |max_nesting: u32, source: &str| {
+            Parser::new(Lexer::from_source(source))
+                .with_max_nesting(max_nesting)
+                .parse_expr()
+        };
+
+        for source in ["((1))", "[[1]]", "a[(1)]", "1 + 2 + 3", "a.b.c", "f(1)(2)"] {
+            assert!(parse_nested(2, source).is_ok(), "{source}");
+        }
+
+        for source in ["(((1)))", "[[[1]]]", "([f(1)])", "a[b[c[0]]]", "1 + 2 + 3 + 4", "a.b.c.d"] {
+            let err = parse_nested(2, source).unwrap_err();
+            assert_eq!(err.message, "nesting too deep", "{source}");
+        }
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let errors = parse_module_errors("let = 1; let y = 2; return x let z = 3; x + 1;");
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        assert!(errors.iter().all(|err| err.kind == ErrorKind::Parser));
+        // The first error points at the `=` where a name was expected.
+        assert_eq!(errors[0].span, Some(Span(4, 1)));
+
+        // Errors inside a block are recovered within that block.
+        let errors = parse_module_errors("while x { let = 1; let y = 2; } let 3;");
+        assert_eq!(errors.len(), 2, "{errors:?}");
+
+        // An unterminated block is reported once.
+        let errors = parse_module_errors("while x { let y = 1;");
+        assert_eq!(errors.len(), 1, "{errors:?}");
+    }
+
+    #[test]
+    fn test_call() -> Result<()> {
+        let expr = parse_expr("f(g(x), y)")?;
+        let f = call(&expr);
+        assert_eq!(name(&f.callee), "f");
+        assert_eq!(f.args.len(), 2);
+        assert_eq!(name(&f.args[1]), "y");
+
+        let g = call(&f.args[0]);
+        assert_eq!(name(&g.callee), "g");
+        assert_eq!(g.args.len(), 1);
+        assert_eq!(name(&g.args[0]), "x");
+
+        // No arguments
+        assert_eq!(call(&parse_expr("f()")?).args.len(), 0);
+
+        // Trailing comma
+        assert_eq!(call(&parse_expr("f(1, 2,)")?).args.len(), 2);
+
+        // Call binds tighter than binary operators
+        let expr = parse_expr("1 + f(2)")?;
+        assert_eq!(name(&call(&binary(&expr).rhs).callee), "f");
+
+        assert!(parse_expr("f(1 2)").is_err());
+
+        // Named arguments
+        let expr = parse_expr("f(1, c: 3, b: g(2))")?;
+        let f = call(&expr);
+        assert_eq!(f.args.len(), 1);
+        assert_eq!(f.named_args.len(), 2);
+        assert_eq!(f.named_args[0].name.text, "c");
+        assert_eq!(int(&f.named_args[0].value), 3);
+        assert_eq!(f.named_args[1].name.text, "b");
+        assert_eq!(name(&call(&f.named_args[1].value).callee), "g");
+
+        let err = parse_expr("f(a: 1, 2)").unwrap_err();
+        assert!(err.message.contains("positional argument"), "{err}");
+        assert!(parse_expr("f(1: 2)").is_err());
+
+        Ok(())
+    }
+
+    /// Unwrap an array literal, or panic.
+    fn array(expr: &Expr) -> &ArrayLit {
+        match expr {
+            Expr::ArrayLit(array_lit) => array_lit,
+            _ => panic!("expected array literal: {expr:?}"),
+        }
+    }
+
+    #[test]
+    fn test_array_lit() -> Result<()> {
+        let expr = parse_expr("[1, 2, 3]")?;
+        let elements = &array(&expr).elements;
+        assert_eq!(elements.len(), 3);
+        assert_eq!(int(&elements[2]), 3);
+
+        assert_eq!(array(&parse_expr("[]")?).elements.len(), 0);
+        assert_eq!(array(&parse_expr("[1, 2,]")?).elements.len(), 2);
+
+        // Nested arrays
+        let expr = parse_expr("[[1], [2, 3]]")?;
+        assert_eq!(array(&array(&expr).elements[1]).elements.len(), 2);
+
+        assert!(parse_expr("[1 2]").is_err());
+        assert!(parse_expr("[1, 2").is_err());
+        assert!(parse_expr("[,]").is_err());
+
+        Ok(())
+    }
+
+    /// Unwrap an index expression, or panic.
+    fn index(expr: &Expr) -> &IndexExpr {
+        match expr {
+            Expr::Index(index_expr) => index_expr,
+            _ => panic!("expected index expression: {expr:?}"),
+        }
+    }
+
+    #[test]
+    fn test_index() -> Result<()> {
+        // Chained indexing nests left-to-right
+        let expr = parse_expr("m[1][2]")?;
+        let outer = index(&expr);
+        assert_eq!(int(&outer.index), 2);
+        let inner = index(&outer.target);
+        assert_eq!(name(&inner.target), "m");
+        assert_eq!(int(&inner.index), 1);
+
+        // Index binds tighter than binary operators
+        let expr = parse_expr("a[i + 1] * 2")?;
+        let mul = binary(&expr);
+        assert!(matches!(mul.op, BinaryOp::Mul));
+        assert!(matches!(binary(&index(&mul.lhs).index).op, BinaryOp::Add));
+
+        // Index on a call result
+        let expr = parse_expr("f()[0]")?;
+        assert_eq!(name(&call(&index(&expr).target).callee), "f");
+
+        // Assignment target
+        let block = parse_module("a[0] = 1;")?;
+        assert_eq!(name(&index(&binary(expr_stmt(&block.stmts[0])).lhs).target), "a");
+
+        let err = parse_expr("a[1").unwrap_err();
+        assert!(err.to_string().contains("expected ']'"), "{err}");
+        assert!(parse_expr("a[]").is_err());
+
+        Ok(())
+    }
+
+    /// Unwrap a field access expression, or panic.
+    fn field(expr: &Expr) -> &FieldExpr {
+        match expr {
+            Expr::Field(field_expr) => field_expr,
+            _ => panic!("expected field expression: {expr:?}"),
+        }
+    }
+
+    #[test]
+    fn test_field() -> Result<()> {
+        // Chained access nests left-to-right
+        let expr = parse_expr("a.b.c")?;
+        let outer = field(&expr);
+        assert_eq!(outer.field.text, "c");
+        let inner = field(&outer.target);
+        assert_eq!(inner.field.text, "b");
+        assert_eq!(name(&inner.target), "a");
+
+        // Mixed with indexing, binding tighter than binary operators
+        let expr = parse_expr("a.b[0].c + 1")?;
+        let add = binary(&expr);
+        assert!(matches!(add.op, BinaryOp::Add));
+        assert_eq!(field(&index(&field(&add.lhs).target).target).field.text, "b");
+
+        // Assignment target
+        let block = parse_module("p.x = 1;")?;
+        assert_eq!(field(&binary(expr_stmt(&block.stmts[0])).lhs).field.text, "x");
+
+        let err = parse_expr("a.1").unwrap_err();
+        assert!(err.to_string().contains("expected field name"), "{err}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_method_chain() -> Result<()> {
+        // ((((a.b)()).c)(1)).d
+        let expr = parse_expr("a.b().c(1).d")?;
+        let d = field(&expr);
+        assert_eq!(d.field.text, "d");
+        let c_call = call(&d.target);
+        assert_eq!(c_call.args.len(), 1);
+        assert_eq!(int(&c_call.args[0]), 1);
+        let c = field(&c_call.callee);
+        assert_eq!(c.field.text, "c");
+        let b_call = call(&c.target);
+        assert!(b_call.args.is_empty());
+        let b = field(&b_call.callee);
+        assert_eq!(b.field.text, "b");
+        assert_eq!(name(&b.target), "a");
+
+        // A chain as a statement
+        let block = parse_module("arr.map(f).filter(g);")?;
+        let filter = call(expr_stmt(&block.stmts[0]));
+        assert_eq!(field(&filter.callee).field.text, "filter");
+        assert_eq!(field(&call(&field(&filter.callee).target).callee).field.text, "map");
+
+        let err = parse_expr("a.().b").unwrap_err();
+        assert!(err.to_string().contains("expected field name"), "{err}");
+        assert!(parse_module("a.b.;").is_err());
+
+        Ok(())
+    }
+
+    /// Unwrap a logical expression, or panic.
+    fn logical(expr: &Expr) -> &LogicalExpr {
+        match expr {
+            Expr::Logical(logical_expr) => logical_expr,
+            _ => panic!("expected logical expression: {expr:?}"),
+        }
+    }
+
+    #[test]
+    fn test_logical() -> Result<()> {
+        // `and` binds tighter than `or`
+        let expr = parse_expr("a and b or c")?;
+        let or = logical(&expr);
+        assert!(matches!(or.op, LogicalOp::Or));
+        assert_eq!(name(&or.rhs), "c");
+        let and = logical(&or.lhs);
+        assert!(matches!(and.op, LogicalOp::And));
+        assert_eq!(name(&and.lhs), "a");
+        assert_eq!(name(&and.rhs), "b");
+
+        let expr = parse_expr("a or b and c")?;
+        let or = logical(&expr);
+        assert_eq!(name(&or.lhs), "a");
+        assert!(matches!(logical(&or.rhs).op, LogicalOp::And));
+
+        // Left associative
+        let expr = parse_expr("a or b or c")?;
+        assert_eq!(name(&logical(&expr).rhs), "c");
+        assert_eq!(name(&logical(&logical(&expr).lhs).lhs), "a");
+
+        // Arithmetic binds tighter than logical operators
+        let expr = parse_expr("a + 1 and b")?;
+        assert!(matches!(binary(&logical(&expr).lhs).op, BinaryOp::Add));
+
+        assert!(parse_expr("a and").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expr_stmt() -> Result<()> {
+        let block = parse_module("x = 1;")?;
+        let assign = binary(expr_stmt(&block.stmts[0]));
+        assert!(matches!(assign.op, BinaryOp::Assign));
+        assert_eq!(name(&assign.lhs), "x");
+        assert_eq!(int(&assign.rhs), 1);
+
+        let block = parse_module("f();")?;
+        let f = call(expr_stmt(&block.stmts[0]));
+        assert_eq!(name(&f.callee), "f");
+        assert_eq!(f.args.len(), 0);
+
+        // Assignment has no value, so it can't be nested in an expression.
+        let chain = format!("x = {}1;", "x = ".repeat(10_000));
+        for source in ["x = y = 1 + 2;", "let y = 1 = 2;", "f(x = 1);", &chain] {
+            let err = parse_module(source).unwrap_err();
+            assert_eq!(err.message, "assignment is only valid as a statement", "{source}");
+        }
+        let err = parse_expr("1 = 2").unwrap_err();
+        assert_eq!(err.message, "assignment is only valid as a statement");
+
+        assert!(parse_module("x = 1").is_err());
+        assert!(parse_module("x + 1;").is_err());
+        assert!(parse_module("f() = 1;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailing_expr() -> Result<()> {
+        // A trailing expression without a semicolon is the block's value.
+        let block = parse_module("while x { let y = 1; y + 1 }")?;
+        let Stmt::While(while_stmt) = &block.stmts[0] else {
+            panic!("expected while statement: {:?}", block.stmts[0]);
+        };
+        let body = &while_stmt.body.stmts;
+        assert_eq!(body.len(), 2);
+        assert!(matches!(binary(tail_expr(&body[1])).op, BinaryOp::Add));
+
+        // Including at the end of a module, and for any kind of expression.
+        let block = parse_module("let x = 1; 42")?;
+        assert_eq!(int(tail_expr(&block.stmts[1])), 42);
+        let block = parse_module("(1)")?;
+        assert_eq!(int(tail_expr(&block.stmts[0])), 1);
+
+        // It's not a return from the enclosing function.
+        let block = parse_module("return 1; 2")?;
+        assert!(matches!(block.stmts[0], Stmt::Return(_)));
+        assert!(matches!(block.stmts[1], Stmt::Tail(_)));
+
+        // Only the last expression may omit the semicolon.
+        assert!(parse_module("x y").is_err());
+        assert!(parse_module("while x { 1 2 }").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_func_lit() -> Result<()> {
+        let expr = parse_expr("fn(x: Int) -> Int { x }")?;
+        let Expr::Func(func) = expr else {
+            panic!("expected function literal: {expr:?}");
+        };
+        assert_eq!(func.args.len(), 1);
+        assert_eq!(func.args[0].name.text, "x");
+        assert_eq!(func.args[0].ty_name.text, "Int");
+        assert!(matches!(&func.return_, Some(TypeDef::Alias(name)) if name.text.text == "Int"));
+        assert_eq!(func.body.stmts.len(), 1);
+        assert!(matches!(tail_expr(&func.body.stmts[0]), Expr::Name(name) if name.ident.text == "x"));
+
+        // The return type is a full type expression.
+        let expr = parse_expr("fn() -> [Int]? { }")?;
+        let Expr::Func(func) = expr else {
+            panic!("expected function literal: {expr:?}");
+        };
+        let Some(TypeDef::Optional(inner)) = &func.return_ else {
+            panic!("expected optional return type: {:?}", func.return_);
+        };
+        assert!(matches!(**inner, TypeDef::Lit(TypeLit::DynArray { .. })));
+
+        let expr = parse_expr("fn(a: Int, b: Float,) { let c = 1; }")?;
+        let Expr::Func(func) = expr else {
+            panic!("expected function literal: {expr:?}");
+        };
+        assert_eq!(func.args.len(), 2);
+        assert_eq!(func.args[1].name.text, "b");
+        assert!(func.return_.is_none());
+        assert_eq!(func.body.stmts.len(), 1);
+
+        // Missing body
+        assert!(parse_expr("fn() -> Int").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_precedence() -> Result<()> {
+        // 1 + (2 * 3)
+        let expr = parse_expr("1 + 2 * 3")?;
+        let add = binary(&expr);
+        assert!(matches!(add.op, BinaryOp::Add));
+        assert_eq!(int(&add.lhs), 1);
+
+        let mul = binary(&add.rhs);
+        assert!(matches!(mul.op, BinaryOp::Mul));
+        assert_eq!(int(&mul.lhs), 2);
+        assert_eq!(int(&mul.rhs), 3);
+
+        // (1 * 2) % 3
+        let expr = parse_expr("1 * 2 % 3")?;
+        let rem = binary(&expr);
+        assert!(matches!(rem.op, BinaryOp::Mod));
+        assert!(matches!(binary(&rem.lhs).op, BinaryOp::Mul));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_str_lit() -> Result<()> {
+        match parse_expr(r#""hello""#)? {
+            Expr::Lit(literal) => match *literal {
+                Literal::Str(value) => assert_eq!(value, "hello"),
+                _ => panic!("expected string literal: {literal:?}"),
+            },
+            expr => panic!("expected literal expression: {expr:?}"),
+        }
+
+        let block = parse_module(r#"let s = "hi";"#)?;
+        assert!(matches!(local_decl(&block.stmts[0]).rhs, Some(Expr::Lit(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_comparison() -> Result<()> {
+        // Comparison binds tighter than equality
+        let expr = parse_expr("a < b == c")?;
+        let eq = binary(&expr);
+        assert!(matches!(eq.op, BinaryOp::Eq));
+        assert_eq!(name(&eq.rhs), "c");
+        let lt = binary(&eq.lhs);
+        assert!(matches!(lt.op, BinaryOp::Lt));
+        assert_eq!(name(&lt.lhs), "a");
+        assert_eq!(name(&lt.rhs), "b");
+
+        // Arithmetic binds tighter than comparison
+        let expr = parse_expr("a + 1 >= b * 2")?;
+        let ge = binary(&expr);
+        assert!(matches!(ge.op, BinaryOp::Ge));
+        assert!(matches!(binary(&ge.lhs).op, BinaryOp::Add));
+        assert!(matches!(binary(&ge.rhs).op, BinaryOp::Mul));
+
+        for (source, expected) in [
+            ("a < b", "Lt"),
+            ("a <= b", "Le"),
+            ("a > b", "Gt"),
+            ("a >= b", "Ge"),
+            ("a == b", "Eq"),
+            ("a != b", "Ne"),
+        ] {
+            assert_eq!(format!("{:?}", binary(&parse_expr(source)?).op), expected);
+        }
+
+        // Comparison binds tighter than logical operators
+        let expr = parse_expr("a < b and c != d")?;
+        assert!(matches!(binary(&logical(&expr).lhs).op, BinaryOp::Lt));
+        assert!(matches!(binary(&logical(&expr).rhs).op, BinaryOp::Ne));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_number_raw_text() -> Result<()> {
+        for (source, value, raw) in [("0x1F", 31, "0x1F"), ("42", 42, "42")] {
+            let expr = parse_expr(source)?;
+            assert_eq!(int(&expr), value);
+            match &expr {
+                Expr::Lit(literal) => match &**literal {
+                    Literal::Num(_, Some(text)) => assert_eq!(text, raw),
+                    _ => panic!("expected raw number text: {literal:?}"),
+                },
+                _ => panic!("expected literal expression: {expr:?}"),
+            }
+        }
+
+        match parse_expr("1.5")? {
+            Expr::Lit(literal) => {
+                assert!(matches!(&*literal, Literal::Num(Number::Float(_), Some(raw)) if raw == "1.5"))
+            }
+            expr => panic!("expected literal expression: {expr:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bool_lit() -> Result<()> {
+        for (source, expected) in [("true", true), ("false", false)] {
+            match parse_expr(source)? {
+                Expr::Lit(literal) => match *literal {
+                    Literal::Bool(value) => assert_eq!(value, expected),
+                    _ => panic!("expected bool literal: {literal:?}"),
+                },
+                expr => panic!("expected literal expression: {expr:?}"),
+            }
+        }
+
+        let block = parse_module("let b = true;")?;
+        assert!(matches!(local_decl(&block.stmts[0]).rhs, Some(Expr::Lit(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_associativity() -> Result<()> {
+        // Left associative: (1 - 2) - 3
+        let expr = parse_expr("1 - 2 - 3")?;
+        let outer = binary(&expr);
+        assert!(matches!(outer.op, BinaryOp::Sub));
+        assert_eq!(int(&outer.rhs), 3);
+        let inner = binary(&outer.lhs);
+        assert_eq!(int(&inner.lhs), 1);
+        assert_eq!(int(&inner.rhs), 2);
+
+        // Right associative: 2 ** (3 ** 2)
+        let expr = parse_expr("2 ** 3 ** 2")?;
+        let outer = binary(&expr);
+        assert!(matches!(outer.op, BinaryOp::Exp));
+        assert_eq!(int(&outer.lhs), 2);
+        let inner = binary(&outer.rhs);
+        assert_eq!(int(&inner.lhs), 3);
+        assert_eq!(int(&inner.rhs), 2);
+
+        Ok(())
     }
 }
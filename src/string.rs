@@ -1,14 +1,165 @@
+use std::borrow::Borrow;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
 use std::rc::Rc;
 
+use fxhash::FxHashSet;
+
+/// Shared, immutable string.
 #[derive(Clone)]
 pub struct Str(Rc<String>);
 
 impl Str {
+    pub fn new(s: impl Into<String>) -> Self {
+        Self(Rc::new(s.into()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Checks whether both strings share the same allocation.
+    pub fn ptr_eq(&self, other: &Str) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Consume the string, leaking its strong reference into a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// The returned pointer owns one strong reference. It must be passed
+    /// back to [`Str::from_ptr`] exactly once, otherwise the string leaks.
+    #[allow(dead_code, clippy::wrong_self_convention)]
     pub unsafe fn as_ptr(self) -> *const String {
         Rc::into_raw(self.0)
     }
 
+    /// Reconstruct a string from a raw pointer, taking back its strong reference.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must have been returned by [`Str::as_ptr`], and must not
+    /// be used again after this call.
+    #[allow(dead_code)]
     pub unsafe fn from_ptr(string_rc: *const String) -> Self {
         Self(Rc::from_raw(string_rc))
     }
 }
+
+impl Deref for Str {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_str()
+    }
+}
+
+impl Borrow<str> for Str {
+    fn borrow(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+/// Strings are equal by content, with a fast path for shared allocations.
+impl PartialEq for Str {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Str {}
+
+/// Hashes like [`str`], so the set in [`StringInterner`] can be searched by `&str`.
+impl Hash for Str {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+/// Deduplicates strings, so equal strings share one allocation.
+///
+/// Interned strings can be compared with [`Str::ptr_eq`]
+/// instead of comparing their contents.
+#[derive(Default)]
+pub struct StringInterner {
+    strings: FxHashSet<Str>,
+}
+
+impl StringInterner {
+    /// Return the shared handle for the given text, allocating it on first use.
+    pub fn intern(&mut self, text: &str) -> Str {
+        if let Some(string) = self.strings.get(text) {
+            return string.clone();
+        }
+
+        let string = Str::new(text);
+        self.strings.insert(string.clone());
+        string
+    }
+
+    /// Number of unique strings.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_str_new() {
+        let string = Str::new("foobar");
+        assert_eq!(string.as_str(), "foobar");
+
+        let string = Str::new(String::from("foobar"));
+        assert_eq!(string.as_str(), "foobar");
+    }
+
+    #[test]
+    fn test_str_deref() {
+        let string = Str::new("foobar");
+        assert_eq!(string.len(), 6);
+        assert!(string.starts_with("foo"));
+        assert_eq!(&*string, "foobar");
+    }
+
+    #[test]
+    fn test_str_clone() {
+        let a = Str::new("foobar");
+        let b = a.clone();
+        assert!(a.ptr_eq(&b));
+        assert!(!a.ptr_eq(&Str::new("foobar")));
+    }
+
+    #[test]
+    fn test_string_interner() {
+        let mut interner = StringInterner::default();
+        let a = interner.intern("x");
+        let b = interner.intern("x");
+        let c = interner.intern("y");
+
+        assert!(a.ptr_eq(&b));
+        assert!(!a.ptr_eq(&c));
+        assert_eq!(interner.len(), 2);
+
+        // Equal by content, without being interned.
+        assert!(a == Str::new("x"));
+        assert!(a != c);
+    }
+
+    /// Experimental Miri test
+    #[test]
+    fn test_str_ptr() {
+        let string = Str::new("foobar");
+        let ptr = unsafe { string.as_ptr() };
+        let string = unsafe { Str::from_ptr(ptr) };
+        assert_eq!(string.as_str(), "foobar");
+    }
+}
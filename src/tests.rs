@@ -1,9 +1,18 @@
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
+use crate::disasm::disassemble;
 use crate::errors::Result;
-use crate::object::{Constants, CrowStr, Func, UpValueOrigin};
+use crate::limits::MAX_CHUNK_DEPTH;
+use crate::object::{Closure, Constants, CrowStr, Func, Native, Object, UpValueOrigin};
 use crate::op::{shorthand as op, Arg24, Op};
-use crate::vm::Vm;
+use crate::value::Value;
+use crate::vm::{Coroutine, CoroutineStatus, RunState, StepResult, TraceEvent, Vm};
+
+/// Function prototype without arguments, with only string constants.
+fn func(code: impl Into<Box<[Op]>>, stack_size: u32, strings: &[&str]) -> Rc<Func> {
+    Rc::new(Func::from_code(code, stack_size, strings))
+}
 
 #[test]
 fn test_basic_math() -> Result<()> {
@@ -17,6 +26,7 @@ fn test_basic_math() -> Result<()> {
     let func = Rc::new(Func {
         code: code.iter().cloned().collect(),
         stack_size: 3,
+        arity: 0,
         is_varg: true,
         constants: Constants {
             ints: Box::new([]),
@@ -38,10 +48,301 @@ fn test_basic_math() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_eval() -> Result<()> {
+    let func = |code: Box<[Op]>| {
+        Rc::new(Func {
+            code,
+            stack_size: 3,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+        })
+    };
+
+    let mut vm = Vm::new();
+
+    // Same as `test_basic_math`, but returning the sum.
+    let basic_math = func(Box::new([
+        op::push_int_inlined(7),
+        op::push_int_inlined(11),
+        Op::Int_Add,
+        op::return_(1),
+    ]));
+    assert_eq!(vm.eval(basic_math)?, Some(Value::Int(18)));
+    assert!(vm.stack.is_empty());
+
+    // The last of multiple results.
+    let multiple = func(Box::new([
+        op::push_int_inlined(1),
+        op::push_int_inlined(2),
+        op::return_(2),
+    ]));
+    assert_eq!(vm.eval(multiple)?, Some(Value::Int(2)));
+
+    let nothing = func(Box::new([op::push_int_inlined(1), Op::End]));
+    assert_eq!(vm.eval(nothing)?, None);
+    assert!(vm.stack.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_gas_limit() -> Result<()> {
+    let func = |code: Box<[Op]>| {
+        Rc::new(Func {
+            code,
+            stack_size: 2,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+        })
+    };
+
+    let mut vm = Vm::new();
+    vm.set_gas_limit(Some(100));
+
+    // Jumps back to itself forever.
+    let infinite_loop = func(Box::new([op::jump(-1), Op::End]));
+    let err = vm.run_function((), infinite_loop).unwrap_err();
+    assert_eq!(err.message, "instruction limit exceeded");
+    assert_eq!(vm.gas_used(), 101);
+
+    // The count starts over for each run, and the VM is still usable.
+    let finite = func(Box::new([op::push_int_inlined(1), op::return_(1)]));
+    vm.run_function((), finite.clone())?;
+    assert_eq!(vm.gas_used(), 2);
+
+    // A run that needs exactly the limit is allowed.
+    vm.set_gas_limit(Some(2));
+    vm.run_function((), finite.clone())?;
+    vm.set_gas_limit(Some(1));
+    assert!(vm.run_function((), finite).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_op_trace_hook() -> Result<()> {
+    let func = Rc::new(Func {
+        code: Box::new([op::push_int_inlined(7), op::push_int_inlined(11), Op::Int_Add, Op::End]),
+        stack_size: 3,
+        arity: 0,
+        is_varg: true,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+    });
+
+    let trace = Rc::new(RefCell::new(Vec::new()));
+    let mut vm = Vm::new();
+    vm.set_op_trace_hook(Box::new({
+        let trace = trace.clone();
+        move |ip, op, stack| trace.borrow_mut().push(format!("{ip} {op:?} {}", stack.len()))
+    }));
+    vm.run_function((), func.clone())?;
+
+    // Each instruction sees the frame's stack, including the callable, before it executes.
+    assert_eq!(
+        *trace.borrow(),
+        ["0 PushIntIn(7) 1", "1 PushIntIn(11) 2", "2 Int_Add 3", "3 End 2"]
+    );
+
+    vm.clear_op_trace_hook();
+    vm.run_function((), func)?;
+    assert_eq!(trace.borrow().len(), 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_step() -> Result<()> {
+    let func = Rc::new(Func {
+        code: Box::new([
+            op::push_int_inlined(7),
+            op::push_int_inlined(11),
+            Op::Int_Add,
+            op::return_(1),
+        ]),
+        stack_size: 3,
+        arity: 0,
+        is_varg: true,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+    });
+
+    let mut vm = Vm::new();
+    assert!(vm.step().is_err(), "nothing loaded to step through");
+
+    // Breakpoints don't interrupt stepping.
+    vm.set_breakpoint(&func, 1);
+    vm.load_function((), func)?;
+    assert_eq!(vm.current_locals().len(), 1);
+
+    assert_eq!(vm.step()?, StepResult::Running);
+    assert_eq!(vm.current_locals()[1], Value::Int(7));
+    assert_eq!(vm.step()?, StepResult::Running);
+    assert_eq!(vm.current_locals()[2], Value::Int(11));
+    assert_eq!(vm.step()?, StepResult::Running);
+    assert_eq!(vm.current_locals()[1], Value::Int(18));
+    assert_eq!(vm.step()?, StepResult::Returned(Some(Value::Int(18))));
+    assert_eq!(vm.results(), [Value::Int(18)]);
+
+    assert!(vm.step().is_err(), "function already returned");
+
+    Ok(())
+}
+
+#[test]
+fn test_step_into_call() -> Result<()> {
+    let no_constants = || Constants {
+        ints: Box::new([]),
+        floats: Box::new([]),
+        strings: Box::new([]),
+        funcs: Box::new([]),
+    };
+
+    // fn callee() { return 42; }
+    let callee = Rc::new(Func {
+        code: Box::new([op::push_int_inlined(42), op::return_(1)]),
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: no_constants(),
+        up_values: Box::new([]),
+    });
+
+    // return callee();
+    let func = Rc::new(Func {
+        code: Box::new([op::create_closure(0), op::call(1, 1), op::return_(1)]),
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            funcs: Box::new([callee]),
+            ..no_constants()
+        },
+        up_values: Box::new([]),
+    });
+
+    let mut vm = Vm::new();
+    vm.load_function((), func)?;
+
+    let mut steps = 0;
+    let result = loop {
+        steps += 1;
+        match vm.step()? {
+            StepResult::Running => continue,
+            StepResult::Returned(value) => break value,
+        }
+    };
+
+    // Every instruction of both functions is a step.
+    assert_eq!(steps, 5);
+    assert_eq!(result, Some(Value::Int(42)));
+
+    Ok(())
+}
+
+#[test]
+fn test_disassemble() -> Result<()> {
+    let empty_constants = || Constants {
+        ints: Box::new([]),
+        floats: Box::new([]),
+        strings: Box::new([]),
+        funcs: Box::new([]),
+    };
+
+    // Same chunk as `test_basic_math`.
+    let func = Func {
+        code: Box::new([op::push_int_inlined(7), op::push_int_inlined(11), Op::Int_Add, Op::End]),
+        stack_size: 3,
+        arity: 0,
+        is_varg: true,
+        constants: empty_constants(),
+        up_values: Box::new([]),
+    };
+    let text = disassemble(&func);
+    assert!(text.contains("0000  PushIntIn(7)"), "{text}");
+    assert!(text.contains("0001  PushIntIn(11)"), "{text}");
+    assert!(text.contains("0002  Int_Add"), "{text}");
+
+    // Inline integers and jump offsets are signed, while indices are unsigned.
+    let func = Func {
+        code: Box::new([op::push_int_inlined(-1), op::jump(-1), op::pop(1), Op::End]),
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: empty_constants(),
+        up_values: Box::new([]),
+    };
+    let text = disassemble(&func);
+    assert!(text.contains("0000  PushIntIn(-1)"), "{text}");
+    assert!(text.contains("0001  Jump { addr: -1 }"), "{text}");
+    assert!(text.contains("0002  Pop(1)"), "{text}");
+    assert_eq!(format!("{:?}", Arg24::from_u32(0xFF_FFFF)?), "16777215");
+
+    // Constants are annotated, and nested functions are indented.
+    let inner = Func {
+        code: Box::new([op::push_int(0), Op::End]),
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([1_000_000_000]),
+            ..empty_constants()
+        },
+        up_values: Box::new([]),
+    };
+    let outer = Func {
+        code: Box::new([op::push_func(0), op::push_string(0), Op::End]),
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            strings: Box::new([Rc::new(CrowStr::new("hello"))]),
+            funcs: Box::new([Rc::new(inner)]),
+            ..empty_constants()
+        },
+        up_values: Box::new([]),
+    };
+    let text = disassemble(&outer);
+    assert!(text.contains("0000  PushFunc(0)       ; func 0"), "{text}");
+    assert!(text.contains("0001  PushString(0)     ; \"hello\""), "{text}");
+    assert!(
+        text.contains("func 0:\n    0000  PushInt(0)        ; 1000000000"),
+        "{text}"
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_basic_branch() -> Result<()> {
     let func = Rc::new(Func {
         stack_size: 4,
+        arity: 0,
         is_varg: true,
         constants: Constants {
             ints: Box::new([]),
@@ -86,6 +387,7 @@ fn test_basic_branch() -> Result<()> {
 fn test_basic_call() -> Result<()> {
     let add_func = Rc::new(Func {
         stack_size: 3,
+        arity: 0,
         is_varg: false,
         constants: Constants {
             ints: Box::new([]),
@@ -99,6 +401,7 @@ fn test_basic_call() -> Result<()> {
 
     let top_func = Rc::new(Func {
         stack_size: 6,
+        arity: 0,
         is_varg: false,
         constants: Constants {
             ints: Box::new([]),
@@ -129,6 +432,264 @@ fn test_basic_call() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_varg_call() -> Result<()> {
+    // func(a, ...rest) { return a, rest; }
+    let varg_func = Rc::new(Func {
+        stack_size: 3,
+        arity: 1,
+        is_varg: true,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([op::get_local(1), op::get_local(2), op::return_(2)]),
+    });
+
+    let call_func = |args: &[i64]| {
+        let mut code = vec![op::create_closure(0), op::get_local(1)];
+        code.extend(args.iter().map(|arg| op::push_int_inlined(*arg as i32)));
+        code.extend([op::call(2, 2), op::return_(2)]);
+        Rc::new(Func {
+            stack_size: 6,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([varg_func.clone()]),
+            },
+            up_values: Box::new([]),
+            code: code.into_boxed_slice(),
+        })
+    };
+
+    let mut vm = Vm::new();
+    let mut run = |args: &[i64]| -> Result<(Value, Vec<Value>)> {
+        vm.run_function((), call_func(args))?;
+        let [first, rest] = vm.results() else {
+            panic!("expected two results: {:?}", vm.results());
+        };
+        let rest = rest.as_array().expect("varargs array").borrow();
+        let rest = (0..rest.len()).map(|index| rest.get(index).unwrap().clone()).collect();
+        Ok((first.clone(), rest))
+    };
+
+    // The arguments beyond the declared parameter are collected.
+    assert_eq!(run(&[1, 2, 3])?, (Value::Int(1), vec![Value::Int(2), Value::Int(3)]));
+    assert_eq!(run(&[1])?, (Value::Int(1), vec![]));
+    // A missing parameter is nil.
+    assert_eq!(run(&[])?, (Value::Nil, vec![]));
+
+    Ok(())
+}
+
+#[test]
+fn test_call_discards_extra_results() -> Result<()> {
+    // func() { local a = 5; return 10, 20; }
+    let pair_func = Rc::new(Func {
+        stack_size: 4,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            op::push_int_inlined(5),
+            op::push_int_inlined(10),
+            op::push_int_inlined(20),
+            op::return_(2),
+        ]),
+    });
+
+    let top_func = Rc::new(Func {
+        stack_size: 4,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([pair_func]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            op::create_closure(0),
+            op::get_local(1),
+            // Only the first result is wanted.
+            op::call(2, 1),
+            op::push_int_inlined(99),
+            op::return_(2),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.set_breakpoint(&top_func, 3);
+    assert_eq!(vm.run_function((), top_func)?, RunState::Paused);
+
+    // The callee, its local and the second result are gone.
+    let locals = vm.current_locals();
+    assert_eq!(locals.len(), 3);
+    assert_eq!(locals[2], Value::Int(10));
+
+    vm.resume()?;
+    assert_eq!(vm.results(), [Value::Int(10), Value::Int(99)]);
+    assert!(vm.stack.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_coroutine_generator() -> Result<()> {
+    let no_constants = || Constants {
+        ints: Box::new([]),
+        floats: Box::new([]),
+        strings: Box::new([]),
+        funcs: Box::new([]),
+    };
+
+    // func(start) { yield start; yield start + 1; yield start + 2; }
+    let generator = Rc::new(Func {
+        stack_size: 3,
+        arity: 1,
+        is_varg: false,
+        constants: no_constants(),
+        up_values: Box::new([]),
+        code: Box::new([
+            op::get_local(1),
+            op::yield_(1),
+            op::get_local(1),
+            op::push_int_inlined(1),
+            op::int_add(),
+            op::yield_(1),
+            op::get_local(1),
+            op::push_int_inlined(2),
+            op::int_add(),
+            op::yield_(1),
+            op::end(),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    let value = Value::from_coroutine(Coroutine::new(generator));
+    let coroutine = value.as_coroutine().unwrap();
+
+    assert_eq!(vm.resume_coroutine(coroutine, &[Value::Int(1)])?, [Value::Int(1)]);
+    assert_eq!(vm.resume_coroutine(coroutine, &[])?, [Value::Int(2)]);
+    assert_eq!(coroutine.borrow().status(), CoroutineStatus::Suspended);
+    assert_eq!(vm.resume_coroutine(coroutine, &[])?, [Value::Int(3)]);
+
+    // Running off the end finishes the coroutine.
+    assert!(vm.resume_coroutine(coroutine, &[])?.is_empty());
+    assert_eq!(coroutine.borrow().status(), CoroutineStatus::Finished);
+    assert!(vm.resume_coroutine(coroutine, &[]).is_err());
+
+    // The VM is unaffected by the coroutine's stack.
+    assert!(vm.stack.is_empty());
+
+    // Resume values are pushed in place of the yielded values.
+    let echo = Rc::new(Func {
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: no_constants(),
+        up_values: Box::new([]),
+        code: Box::new([op::push_nil(), op::yield_(1), op::yield_(1), op::end()]),
+    });
+    let value = Value::from_coroutine(Coroutine::new(echo));
+    let coroutine = value.as_coroutine().unwrap();
+    assert_eq!(vm.resume_coroutine(coroutine, &[])?, [Value::Nil]);
+    assert_eq!(vm.resume_coroutine(coroutine, &[Value::Int(7)])?, [Value::Int(7)]);
+
+    // Yielding from an ordinary function is an error.
+    let err = vm
+        .run_function(
+            (),
+            Rc::new(Func {
+                stack_size: 2,
+                arity: 0,
+                is_varg: false,
+                constants: no_constants(),
+                up_values: Box::new([]),
+                code: Box::new([op::push_nil(), op::yield_(1), op::end()]),
+            }),
+        )
+        .unwrap_err();
+    assert_eq!(err.message, "cannot yield outside of a coroutine");
+
+    Ok(())
+}
+
+#[test]
+fn test_trace_hook() -> Result<()> {
+    let add_func = Rc::new(Func {
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: vec![Op::Int_Add, Op::Return { results: 1 }, Op::End].into_boxed_slice(),
+    });
+
+    let top_func = Rc::new(Func {
+        stack_size: 6,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([add_func.clone()]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            op::create_closure(0),
+            op::get_local(1),
+            op::push_int_inlined(7),
+            op::push_int_inlined(11),
+            op::call(2, 1),
+            op::return_(1),
+            op::end(),
+        ]),
+    });
+
+    // (is_enter, is_add_func, results)
+    let events = Rc::new(RefCell::new(Vec::<(bool, bool, usize)>::new()));
+
+    let mut vm = Vm::new();
+    vm.set_trace_hook(Box::new({
+        let events = events.clone();
+        let add_func = add_func.clone();
+        move |event| match event {
+            TraceEvent::Enter { func } => events.borrow_mut().push((true, Rc::ptr_eq(&func, &add_func), 0)),
+            TraceEvent::Exit { func, results } => {
+                events.borrow_mut().push((false, Rc::ptr_eq(&func, &add_func), results))
+            }
+        }
+    }));
+    vm.run_function((), top_func)?;
+
+    assert_eq!(
+        events.borrow().as_slice(),
+        &[(true, false, 0), (true, true, 0), (false, true, 1), (false, false, 1)]
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_recursion() -> Result<()> {
     const INPUT: i32 = 20;
@@ -141,6 +702,7 @@ fn test_recursion() -> Result<()> {
     // TODO: Closures and up-values
     let fib_func = Rc::new(Func {
         stack_size: 7,
+        arity: 0,
         is_varg: false,
         constants: Constants {
             ints: Box::new([]),
@@ -180,6 +742,7 @@ fn test_recursion() -> Result<()> {
 
     let top_func = Rc::new(Func {
         stack_size: 6,
+        arity: 0,
         is_varg: false,
         constants: Constants {
             ints: Box::new([]),
@@ -210,6 +773,7 @@ fn test_recursion() -> Result<()> {
 fn test_table() -> Result<()> {
     let top_func = Rc::new(Func {
         stack_size: 6,
+        arity: 0,
         is_varg: false,
         constants: Constants {
             ints: Box::new([]),
@@ -232,12 +796,10 @@ fn test_table() -> Result<()> {
             op::get_local(2),
             op::push_string(0),
             op::table_get(),
-            op::pop(1),
             // "a" in t -> true
             op::get_local(2),
             op::push_string(0),
             op::table_contains(),
-            op::pop(1),
             // t.remove("a")
             op::get_local(2),
             op::push_string(0),
@@ -246,14 +808,1936 @@ fn test_table() -> Result<()> {
             op::get_local(2),
             op::push_string(0),
             op::table_contains(),
-            // op::pop(1),
-            op::return_(1),
+            op::return_(3),
             op::end(),
         ]),
     });
 
     let mut vm = Vm::new();
-    vm.run_function((), top_func)?;
+    vm.run_function((), top_func.clone())?;
+    assert_eq!(
+        vm.results(),
+        [Value::Int(42), Value::from_bool(true), Value::from_bool(false)]
+    );
+
+    // Getting a removed key results in nil.
+    let mut code = top_func.code.to_vec();
+    code.splice(
+        code.len() - 2..,
+        [
+            op::pop(1),
+            op::get_local(2),
+            op::push_string(0),
+            op::table_get(),
+            op::return_(1),
+        ],
+    );
+    let missing_func = Rc::new(Func {
+        stack_size: 7,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("a"))]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: code.into_boxed_slice(),
+    });
+    vm.run_function((), missing_func)?;
+    assert_eq!(vm.results(), [Value::Nil]);
+
+    Ok(())
+}
+
+#[test]
+fn test_globals() -> Result<()> {
+    let top_func = Rc::new(Func {
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([
+                Rc::new(CrowStr::new("x")),
+                Rc::new(CrowStr::new("y")),
+                Rc::new(CrowStr::new("z")),
+            ]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // x = 1; y = x;
+            op::push_int_inlined(1),
+            op::set_global(0),
+            op::get_global(0),
+            op::set_global(1),
+            // x = 2; z = x;
+            op::push_int_inlined(2),
+            op::set_global(0),
+            op::get_global(0),
+            op::set_global(2),
+            op::end(),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.run_function((), top_func)?;
+
+    // Reassigning a global must be reflected in subsequent cached lookups.
+    assert_eq!(vm.get_global("x").and_then(Value::as_int), Some(2));
+    assert_eq!(vm.get_global("y").and_then(Value::as_int), Some(1));
+    assert_eq!(vm.get_global("z").and_then(Value::as_int), Some(2));
+
+    Ok(())
+}
+
+#[test]
+fn test_globals_loop() -> Result<()> {
+    let top_func = Rc::new(Func {
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("x"))]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // x = 0;
+            op::push_int_inlined(0),
+            op::set_global(0),
+            // do { x = x + 1; } while x < 10;
+            op::get_global(0),
+            op::push_int_inlined(1),
+            op::int_add(),
+            op::set_global(0),
+            op::get_global(0),
+            op::push_int_inlined(10),
+            Op::JumpLt {
+                addr: Arg24::from_i64(-7)?,
+            },
+            op::end(),
+        ]),
+    });
+
+    for cached in [true, false] {
+        let mut vm = Vm::new();
+        vm.set_global_cache(cached);
+        vm.run_function((), top_func.clone())?;
+
+        assert_eq!(vm.get_global("x").and_then(Value::as_int), Some(10));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_global_cache_released() -> Result<()> {
+    let global_func = |name: Rc<CrowStr>, code: Box<[Op]>| {
+        Rc::new(Func {
+            stack_size: 2,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([name]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code,
+        })
+    };
+
+    let name = Rc::new(CrowStr::new("x"));
+    let mut vm = Vm::new();
+    let code = Box::new([op::push_int_inlined(1), op::set_global(0), op::end()]);
+    vm.run_function((), global_func(name.clone(), code))?;
+
+    // The next run doesn't keep the previous run's constants alive.
+    let code = Box::new([op::get_global(0), op::return_(1)]);
+    vm.run_function((), global_func(Rc::new(CrowStr::new("x")), code))?;
+    assert_eq!(vm.results(), [Value::Int(1)]);
+    assert_eq!(Rc::strong_count(&name), 1);
+
+    // A failed assignment doesn't define the global.
+    let code = Box::new([op::pop(1), op::set_global(0), op::end()]);
+    let result = vm.run_function((), global_func(Rc::new(CrowStr::new("y")), code));
+    assert!(result.is_err());
+    assert!(vm.get_global("y").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_global_undefined() -> Result<()> {
+    let top_func = Rc::new(Func {
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("x"))]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([op::get_global(0), op::return_(1)]),
+    });
+
+    // Reading an undefined global results in nil, without defining it.
+    let mut vm = Vm::new();
+    vm.run_function((), top_func)?;
+    assert_eq!(vm.results(), [Value::Nil]);
+    assert!(vm.get_global("x").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_closure_outer_upvalue_out_of_range() -> Result<()> {
+    let inner_func = Rc::new(Func {
+        stack_size: 1,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        // The top-level closure has no up-values to share.
+        up_values: Box::new([UpValueOrigin::Outer(5)]),
+        code: Box::new([op::end()]),
+    });
+
+    let top_func = Rc::new(Func {
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([inner_func]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([op::create_closure(0), op::end()]),
+    });
+
+    let mut vm = Vm::new();
+    assert!(vm.run_function((), top_func).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_breakpoint_locals() -> Result<()> {
+    let top_func = Rc::new(Func {
+        stack_size: 4,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // locals a, b
+            op::push_int_inlined(7),
+            op::push_int_inlined(11),
+            // a = a + b
+            op::get_local(1),
+            op::get_local(2),
+            op::int_add(),
+            op::set_local(1),
+            op::pop(1),
+            op::end(),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.set_breakpoint(&top_func, 2);
+    vm.set_breakpoint(&top_func, 7);
+
+    assert_eq!(vm.run_function((), top_func.clone())?, RunState::Paused);
+    let locals = vm.current_locals();
+    assert_eq!(locals.len(), 3);
+    assert!(locals[0].as_closure().is_some());
+    assert_eq!(locals[1].as_int(), Some(7));
+    assert_eq!(locals[2].as_int(), Some(11));
+
+    // Can't start another function while paused.
+    assert!(vm.run_function((), top_func).is_err());
+
+    assert_eq!(vm.resume()?, RunState::Paused);
+    let locals = vm.current_locals();
+    assert_eq!(locals[1].as_int(), Some(18));
+    assert_eq!(locals[2].as_int(), Some(11));
+
+    assert_eq!(vm.resume()?, RunState::Finished);
+    assert!(vm.current_locals().is_empty());
+    assert!(vm.resume().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_abs() -> Result<()> {
+    let top_func = Rc::new(Func {
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([
+                Rc::new(CrowStr::new("a")),
+                Rc::new(CrowStr::new("b")),
+                Rc::new(CrowStr::new("c")),
+                Rc::new(CrowStr::new("d")),
+            ]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // a = abs(5); b = abs(-5);
+            op::push_int_inlined(5),
+            op::int_abs(),
+            op::set_global(0),
+            op::push_int_inlined(-5),
+            op::int_abs(),
+            op::set_global(1),
+            // c = abs(c); d = abs(d);
+            op::get_global(2),
+            op::float_abs(),
+            op::set_global(2),
+            op::get_global(3),
+            op::float_abs(),
+            op::set_global(3),
+            op::end(),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.set_global("c", Value::Float(2.5));
+    vm.set_global("d", Value::Float(-2.5));
+    vm.run_function((), top_func)?;
+
+    assert_eq!(vm.get_global("a").and_then(Value::as_int), Some(5));
+    assert_eq!(vm.get_global("b").and_then(Value::as_int), Some(5));
+    assert_eq!(vm.get_global("c").and_then(Value::as_float), Some(2.5));
+    assert_eq!(vm.get_global("d").and_then(Value::as_float), Some(2.5));
+
+    Ok(())
+}
+
+#[test]
+fn test_int_abs_overflow() -> Result<()> {
+    let top_func = Rc::new(Func {
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([i64::MIN]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([Op::PushInt(Arg24::from_u32(0)?), op::int_abs(), op::end()]),
+    });
+
+    // i64::MIN has no positive counterpart.
+    let mut vm = Vm::new();
+    assert!(vm.run_function((), top_func).is_err());
+
+    Ok(())
+}
+
+/// Function that captures a local variable in a closure, and stores
+/// the closure in the global `get`, before returning with the given instruction.
+fn make_capturing_func(terminator: Op) -> Rc<Func> {
+    let get_func = Rc::new(Func {
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([UpValueOrigin::Parent(1)]),
+        code: Box::new([op::get_upvalue(0), op::return_(1)]),
+    });
+
+    Rc::new(Func {
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("get"))]),
+            funcs: Box::new([get_func]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // local x = 42;
+            op::push_int_inlined(42),
+            // get = fn() { return x; };
+            op::create_closure(0),
+            op::set_global(0),
+            terminator,
+        ]),
+    })
+}
+
+#[test]
+fn test_end_and_return() -> Result<()> {
+    for terminator in [op::end(), op::return_(0)] {
+        let top_func = Rc::new(Func {
+            stack_size: 3,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([Rc::new(CrowStr::new("get")), Rc::new(CrowStr::new("result"))]),
+                funcs: Box::new([make_capturing_func(terminator)]),
+            },
+            up_values: Box::new([]),
+            code: Box::new([
+                // make();
+                op::create_closure(0),
+                op::call(1, 0),
+                // result = get();
+                op::get_global(0),
+                op::call(1, 1),
+                op::set_global(1),
+                op::end(),
+            ]),
+        });
+
+        let mut vm = Vm::new();
+        vm.run_function((), top_func)?;
+
+        // Both must close the captured local when the frame is popped.
+        assert_eq!(
+            vm.get_global("result").and_then(Value::as_int),
+            Some(42),
+            "function ending with {terminator:?}"
+        );
+        assert!(vm.stack.is_empty(), "function ending with {terminator:?}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_call_base_relative() -> Result<()> {
+    // fn sub3(a, b, c) { return a - b - c; }
+    let sub3_func = func(
+        [
+            op::get_local(1),
+            op::get_local(2),
+            op::int_sub(),
+            op::get_local(3),
+            op::int_sub(),
+            op::return_(1),
+        ],
+        5,
+        &[],
+    );
+
+    // fn mid(x) { let y = 100; let z = 7; return y + sub3(y, x, z); }
+    let mid_func = func(
+        [
+            op::push_int_inlined(100),
+            op::push_int_inlined(7),
+            // Callee is above the locals, at slot 4
+            op::get_global(0),
+            op::get_local(2),
+            op::get_local(1),
+            op::get_local(3),
+            op::call(4, 1),
+            // Locals below the callee are untouched
+            op::get_local(2),
+            op::int_add(),
+            op::return_(1),
+        ],
+        8,
+        &["sub3"],
+    );
+
+    let top_func = func(
+        [
+            // let a = 1000; let b = 2;
+            op::push_int_inlined(1000),
+            op::push_int_inlined(2),
+            // result = mid(5);
+            op::get_global(0),
+            op::push_int_inlined(5),
+            op::call(3, 1),
+            op::set_global(1),
+            // locals = a + b;
+            op::get_local(1),
+            op::get_local(2),
+            op::int_add(),
+            op::set_global(2),
+            op::end(),
+        ],
+        6,
+        &["mid", "result", "locals"],
+    );
+
+    let mut vm = Vm::new();
+    vm.set_global("sub3", Value::from_closure(Rc::new(Closure::new(sub3_func))));
+    vm.set_global("mid", Value::from_closure(Rc::new(Closure::new(mid_func))));
+    vm.run_function((), top_func)?;
+
+    // 100 + (100 - 5 - 7)
+    assert_eq!(vm.get_global("result").and_then(Value::as_int), Some(188));
+    assert_eq!(vm.get_global("locals").and_then(Value::as_int), Some(1002));
+    assert!(vm.stack.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_call_base_invalid() -> Result<()> {
+    for code in [
+        // Base can't be the caller's own slot
+        [op::get_local(0), op::call(0, 0), op::end()],
+        // Base beyond the top of the stack
+        [op::push_int_inlined(1), op::call(4, 0), op::end()],
+    ] {
+        let mut vm = Vm::new();
+        assert!(vm.run_function((), func(code, 4, &[])).is_err());
+    }
+
+    Ok(())
+}
+
+fn char_at_func(string: &str, index: i32) -> Rc<Func> {
+    Rc::new(Func {
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new(string)), Rc::new(CrowStr::new("result"))]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            op::push_string(0),
+            op::push_int_inlined(index),
+            op::str_char_at(),
+            op::set_global(1),
+            op::end(),
+        ]),
+    })
+}
+
+#[test]
+fn test_str_char_at() -> Result<()> {
+    for (string, index, expected) in [
+        ("hello", 1, "e"),
+        ("hello", 4, "o"),
+        ("héllo", 2, "l"),
+        ("日本語", 2, "語"),
+    ] {
+        let mut vm = Vm::new();
+        vm.run_function((), char_at_func(string, index))?;
+        let result = vm
+            .get_global("result")
+            .and_then(Value::as_string)
+            .map(|s| s.to_string());
+        assert_eq!(result.as_deref(), Some(expected), "{string:?}[{index}]");
+    }
+
+    // Out of range, including the byte length of a multibyte string
+    for (string, index) in [("hello", 5), ("hello", -1), ("日本語", 3), ("", 0)] {
+        let mut vm = Vm::new();
+        assert!(
+            vm.run_function((), char_at_func(string, index)).is_err(),
+            "{string:?}[{index}]"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_new_container_capacity() -> Result<()> {
+    let top_func = Rc::new(Func {
+        stack_size: 1,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("array")), Rc::new(CrowStr::new("table"))]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            op::new_array(100),
+            op::set_global(0),
+            op::new_table(50),
+            op::set_global(1),
+            op::end(),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.run_function((), top_func)?;
+
+    let array = vm.get_global("array").and_then(Value::as_array).expect("array global");
+    assert!(array.borrow().is_empty());
+    assert!(array.borrow().capacity() >= 100);
+
+    let table = vm.get_global("table").and_then(Value::as_table).expect("table global");
+    assert!(table.borrow().is_empty());
+    assert!(table.borrow().capacity() >= 50);
+
+    Ok(())
+}
+
+/// Function that pops the value and argument in globals `x` and `arg`,
+/// and stores the result of the conversion in global `result`.
+fn to_str_func(convert: Op) -> Rc<Func> {
+    Rc::new(Func {
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([
+                Rc::new(CrowStr::new("x")),
+                Rc::new(CrowStr::new("arg")),
+                Rc::new(CrowStr::new("result")),
+            ]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            op::get_global(0),
+            op::get_global(1),
+            convert,
+            op::set_global(2),
+            op::end(),
+        ]),
+    })
+}
+
+fn run_to_str(convert: Op, x: Value, arg: i64) -> Result<String> {
+    let mut vm = Vm::new();
+    vm.set_global("x", x);
+    vm.set_global("arg", Value::Int(arg));
+    vm.run_function((), to_str_func(convert))?;
+    Ok(vm
+        .get_global("result")
+        .and_then(Value::as_string)
+        .map(|s| s.to_string())
+        .expect("result global"))
+}
+
+#[test]
+fn test_int_to_str() -> Result<()> {
+    for (value, radix, expected) in [
+        (255, 16, "ff"),
+        (255, 2, "11111111"),
+        (-255, 10, "-255"),
+        (0, 8, "0"),
+        (35, 36, "z"),
+        (i64::MIN, 16, "-8000000000000000"),
+    ] {
+        assert_eq!(run_to_str(op::int_to_str(), Value::Int(value), radix)?, expected);
+    }
+
+    for radix in [0, 1, 37, -16] {
+        let err = run_to_str(op::int_to_str(), Value::Int(255), radix).unwrap_err();
+        assert!(err.message.contains("radix"), "{err}");
+    }
+
+    Ok(())
+}
+
+fn str_value(string: &str) -> Value {
+    Value::Object(Object::String(Rc::new(CrowStr::new(string))))
+}
+
+fn run_str_predicate(predicate: Op, string: &str, arg: &str) -> Result<bool> {
+    let mut vm = Vm::new();
+    vm.set_global("x", str_value(string));
+    vm.set_global("arg", str_value(arg));
+    vm.run_function((), to_str_func(predicate))?;
+    Ok(vm.get_global("result").and_then(Value::as_bool).expect("result global"))
+}
+
+#[test]
+fn test_str_predicates() -> Result<()> {
+    for (string, needle, contains, starts_with, ends_with) in [
+        ("hello world", "lo w", true, false, false),
+        ("hello world", "hello", true, true, false),
+        ("hello world", "world", true, false, true),
+        ("hello world", "xyz", false, false, false),
+        ("hello", "hello world", false, false, false),
+        // The empty string is contained in every string.
+        ("hello", "", true, true, true),
+        ("", "", true, true, true),
+    ] {
+        assert_eq!(run_str_predicate(op::str_contains(), string, needle)?, contains);
+        assert_eq!(run_str_predicate(op::str_starts_with(), string, needle)?, starts_with);
+        assert_eq!(run_str_predicate(op::str_ends_with(), string, needle)?, ends_with);
+    }
+
+    let mut vm = Vm::new();
+    vm.set_global("x", str_value("hello"));
+    vm.set_global("arg", Value::Int(1));
+    let err = vm.run_function((), to_str_func(op::str_contains())).unwrap_err();
+    assert!(err.message.contains("string value expected"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn test_float_to_str() -> Result<()> {
+    for (value, precision, expected) in [(1.23456, 2, "1.23"), (12.0, 0, "12"), (-0.1, 3, "-0.100")] {
+        assert_eq!(
+            run_to_str(op::float_to_str(), Value::Float(value), precision)?,
+            expected
+        );
+    }
+
+    for precision in [-1, 33] {
+        let err = run_to_str(op::float_to_str(), Value::Float(1.0), precision).unwrap_err();
+        assert!(err.message.contains("precision"), "{err}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_closure_not_leaked() -> Result<()> {
+    let func = make_capturing_func(op::end());
+    let get_func = func.constants.funcs[0].clone();
+    assert_eq!(Rc::strong_count(&func), 1);
+
+    let mut vm = Vm::new();
+    vm.run_function((), func.clone())?;
+
+    // The entry closure was released, while the escaped
+    // closure is only kept alive by the global.
+    assert_eq!(Rc::strong_count(&func), 1);
+    let get = vm.get_global("get").and_then(Value::as_closure).expect("get closure");
+    assert_eq!(Rc::strong_count(get), 1);
+    // The returning frame released its handle to the captured local.
+    assert_eq!(get.up_values.borrow()[0].strong_count(), 1);
+
+    // Fails after popping the operands, while the entry closure and
+    // the captured local are still on the stack.
+    let failing = Rc::new(Func {
+        stack_size: 4,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("get"))]),
+            funcs: Box::new([get_func]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            op::push_int_inlined(7),
+            op::create_closure(0),
+            op::set_global(0),
+            op::push_int_inlined(1),
+            op::push_int_inlined(0),
+            op::str_char_at(),
+            op::end(),
+        ]),
+    });
+    assert!(vm.run_function((), failing.clone()).is_err());
+    assert_eq!(Rc::strong_count(&failing), 1);
+
+    let get = vm.get_global("get").and_then(Value::as_closure).expect("get closure");
+    assert_eq!(get.up_values.borrow()[0].strong_count(), 1);
+
+    // The escaped closure still works after the error.
+    let call_get = Rc::new(Func {
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("get")), Rc::new(CrowStr::new("result"))]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([op::get_global(0), op::call(1, 1), op::set_global(1), op::end()]),
+    });
+    vm.run_function((), call_get)?;
+    assert_eq!(vm.get_global("result").and_then(Value::as_int), Some(7));
+
+    Ok(())
+}
+
+/// Assigns 7 to local `x` with the given instruction, and saves
+/// the local and the value on top of the stack in globals.
+fn assign_local_func(assign: Op) -> Rc<Func> {
+    Rc::new(Func {
+        stack_size: 4,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("x")), Rc::new(CrowStr::new("top"))]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // let x = 0;
+            op::push_int_inlined(0),
+            // let y = 5;
+            op::push_int_inlined(5),
+            // x = 7;
+            op::push_int_inlined(7),
+            assign,
+            op::set_global(1),
+            op::get_local(1),
+            op::set_global(0),
+            op::end(),
+        ]),
+    })
+}
+
+#[test]
+fn test_set_and_store_local() -> Result<()> {
+    // SetLocal leaves the assigned value on the stack.
+    let mut vm = Vm::new();
+    vm.run_function((), assign_local_func(op::set_local(1)))?;
+    assert_eq!(vm.get_global("x").and_then(Value::as_int), Some(7));
+    assert_eq!(vm.get_global("top").and_then(Value::as_int), Some(7));
+
+    // StoreLocal consumes it, leaving local `y` on top.
+    let mut vm = Vm::new();
+    vm.run_function((), assign_local_func(op::store_local(1)))?;
+    assert_eq!(vm.get_global("x").and_then(Value::as_int), Some(7));
+    assert_eq!(vm.get_global("top").and_then(Value::as_int), Some(5));
+
+    Ok(())
+}
+
+#[test]
+fn test_call_zero_results() -> Result<()> {
+    // Called for its side effect, and returns values nobody asked for.
+    let effect_func = Rc::new(Func {
+        stack_size: 4,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("effect"))]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // effect = arg;
+            op::get_local(1),
+            op::set_global(0),
+            op::push_int_inlined(98),
+            op::push_int_inlined(99),
+            op::return_(2),
+        ]),
+    });
+
+    let top_func = Rc::new(Func {
+        stack_size: 4,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("effect")), Rc::new(CrowStr::new("top"))]),
+            funcs: Box::new([effect_func]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // let x = 1;
+            op::push_int_inlined(1),
+            // f(7);
+            op::create_closure(0),
+            op::push_int_inlined(7),
+            op::call(2, 0),
+            // The local is on top of the stack again.
+            op::set_global(1),
+            op::end(),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.run_function((), top_func)?;
+
+    assert_eq!(vm.get_global("effect").and_then(Value::as_int), Some(7));
+    assert_eq!(vm.get_global("top").and_then(Value::as_int), Some(1));
+
+    Ok(())
+}
+
+#[test]
+fn test_native_finalizer() -> Result<()> {
+    let finalized = Rc::new(Cell::new(0));
+    let native = {
+        let finalized = finalized.clone();
+        Native::new(42_u32).with_finalizer(move |data| {
+            assert_eq!(data.downcast_ref::<u32>(), Some(&42));
+            finalized.set(finalized.get() + 1);
+        })
+    };
+
+    let top_func = Rc::new(Func {
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([Rc::new(CrowStr::new("handle"))]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // let local = handle;
+            op::get_global(0),
+            // handle = 0;
+            op::push_int_inlined(0),
+            op::set_global(0),
+            op::end(),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.set_global("handle", Value::from_native(native));
+    let data = vm
+        .get_global("handle")
+        .and_then(Value::as_native)
+        .map(|native| native.downcast_ref::<u32>().copied());
+    assert_eq!(data, Some(Some(42)));
+    assert_eq!(finalized.get(), 0);
+
+    // The last reference is dropped when the local goes out of scope.
+    vm.run_function((), top_func)?;
+    assert_eq!(finalized.get(), 1);
+
+    drop(vm);
+    assert_eq!(finalized.get(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_entry_return_results() -> Result<()> {
+    let func = Rc::new(Func {
+        stack_size: 4,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // let x = 1;
+            op::push_int_inlined(1),
+            // return x + 2, 3;
+            op::get_local(1),
+            op::push_int_inlined(2),
+            op::int_add(),
+            op::push_int_inlined(3),
+            op::return_(2),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.run_function((), func.clone())?;
+
+    // The module's own closure is neither a result, nor kept alive.
+    let results: Vec<_> = vm.results().iter().map(Value::as_int).collect();
+    assert_eq!(results, [Some(3), Some(3)]);
+    assert!(vm.stack.is_empty());
+    assert_eq!(Rc::strong_count(&func), 1);
+
+    // Results are replaced by the next run.
+    vm.run_function((), make_capturing_func(op::end()))?;
+    assert!(vm.results().is_empty());
+    assert!(vm.stack.is_empty());
+
+    Ok(())
+}
+
+fn load_func(load: Op) -> Rc<Func> {
+    Rc::new(Func {
+        stack_size: 6,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // let a = 1, b = 2, c = 3;
+            op::push_int_inlined(1),
+            op::push_int_inlined(2),
+            op::push_int_inlined(3),
+            load,
+            op::return_(2),
+        ]),
+    })
+}
+
+#[test]
+fn test_load() -> Result<()> {
+    let mut vm = Vm::new();
+    vm.run_function((), load_func(op::load(1, 2)))?;
+    let results: Vec<_> = vm.results().iter().map(Value::as_int).collect();
+    assert_eq!(results, [Some(1), Some(2)]);
+
+    // Slots past the top of the stack.
+    let err = vm.run_function((), load_func(op::load(3, 2))).unwrap_err();
+    assert!(err.message.contains("overflows stack"), "{err}");
+    assert!(vm.stack.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_store() -> Result<()> {
+    let func = Rc::new(Func {
+        stack_size: 6,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // let a = 1, b = 2, c = 3;
+            op::push_int_inlined(1),
+            op::push_int_inlined(2),
+            op::push_int_inlined(3),
+            // b, c = 8, 9;
+            op::push_int_inlined(8),
+            op::push_int_inlined(9),
+            op::store(2, 2),
+            // The stored values were popped, so the locals are on top.
+            op::return_(3),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.run_function((), func)?;
+    let results: Vec<_> = vm.results().iter().map(Value::as_int).collect();
+    assert_eq!(results, [Some(1), Some(8), Some(9)]);
+
+    // Destination slots overlapping the stored values.
+    let err = vm.run_function((), load_func(op::store(2, 2))).unwrap_err();
+    assert!(err.message.contains("overflows stack"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn test_push_float() -> Result<()> {
+    let func = |code: Box<[Op]>| {
+        Rc::new(Func {
+            stack_size: 3,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([1.5, 2.25]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code,
+        })
+    };
+
+    let mut vm = Vm::new();
+    vm.run_function(
+        (),
+        func(Box::new([
+            op::push_float(0),
+            op::push_float(1),
+            op::float_add(),
+            op::return_(1),
+        ])),
+    )?;
+    assert_eq!(vm.results()[0].as_float(), Some(3.75));
+
+    let err = vm
+        .run_function((), func(Box::new([op::push_float(2), op::return_(1)])))
+        .unwrap_err();
+    assert!(err.message.contains("no float constant defined: 2"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn test_push_string() -> Result<()> {
+    let func = |code: Box<[Op]>| {
+        Rc::new(Func {
+            stack_size: 3,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([Rc::new(CrowStr::new("hello"))]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code,
+        })
+    };
+
+    let hello = func(Box::new([op::push_string(0), op::push_string(0), op::return_(2)]));
+    let mut vm = Vm::new();
+    vm.run_function((), hello.clone())?;
+    let results: Vec<_> = vm
+        .results()
+        .iter()
+        .map(|value| value.as_string().map(|s| s.as_str()))
+        .collect();
+    assert_eq!(results, [Some("hello"), Some("hello")]);
+
+    // The heap object shares the constant's string.
+    let string = vm.results()[0].as_string().expect("string result");
+    assert!(Rc::ptr_eq(string, &hello.constants.strings[0]));
+
+    let err = vm
+        .run_function((), func(Box::new([op::push_string(1), op::return_(1)])))
+        .unwrap_err();
+    assert!(err.message.contains("no string constant defined: 1"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn test_capture_parent_and_outer() -> Result<()> {
+    let no_constants = || Constants {
+        ints: Box::new([]),
+        floats: Box::new([]),
+        strings: Box::new([]),
+        funcs: Box::new([]),
+    };
+
+    // fn inner() { return a + b; }
+    let inner_func = Rc::new(Func {
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: no_constants(),
+        up_values: Box::new([UpValueOrigin::Parent(1), UpValueOrigin::Outer(0)]),
+        code: Box::new([op::get_upvalue(0), op::get_upvalue(1), op::int_add(), op::return_(1)]),
+    });
+
+    // fn middle() { let b = 2; return inner(); }
+    let middle_func = Rc::new(Func {
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            funcs: Box::new([inner_func]),
+            ..no_constants()
+        },
+        up_values: Box::new([UpValueOrigin::Parent(1)]),
+        code: Box::new([
+            op::push_int_inlined(2),
+            op::create_closure(0),
+            op::call(2, 1),
+            op::return_(1),
+        ]),
+    });
+
+    // let a = 40; return middle();
+    let top_func = Rc::new(Func {
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            funcs: Box::new([middle_func]),
+            ..no_constants()
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            op::push_int_inlined(40),
+            op::create_closure(0),
+            op::call(2, 1),
+            op::return_(1),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.run_function((), top_func)?;
+    assert_eq!(vm.results()[0].as_int(), Some(42));
+
+    Ok(())
+}
+
+#[test]
+fn test_func_serialization() -> Result<()> {
+    // fn inner() { return a + b + 1000000000; }
+    let inner_func = Rc::new(Func {
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([1_000_000_000]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([UpValueOrigin::Parent(1), UpValueOrigin::Outer(0)]),
+        code: Box::new([
+            op::get_upvalue(0),
+            op::get_upvalue(1),
+            op::int_add(),
+            op::push_int(0),
+            op::int_add(),
+            op::return_(1),
+        ]),
+    });
+
+    // fn middle() { let b = 2; return inner(); }
+    let middle_func = Rc::new(Func {
+        stack_size: 3,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([inner_func]),
+        },
+        up_values: Box::new([UpValueOrigin::Parent(1)]),
+        code: Box::new([
+            op::push_int_inlined(2),
+            op::create_closure(0),
+            op::call(2, 1),
+            op::return_(1),
+        ]),
+    });
+
+    // let a = -40; return middle(), 1.5, "done";
+    let top_func = Func {
+        stack_size: 5,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([1.5]),
+            strings: Box::new([Rc::new(CrowStr::new("done"))]),
+            funcs: Box::new([middle_func]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            op::push_int_inlined(-40),
+            op::create_closure(0),
+            op::call(2, 1),
+            op::push_float(0),
+            op::push_string(0),
+            op::return_(3),
+        ]),
+    };
+
+    let bytes = top_func.to_bytes();
+    let loaded = Func::from_bytes(&bytes)?;
+    assert_eq!(disassemble(&loaded), disassemble(&top_func));
+    assert_eq!(loaded.to_bytes(), bytes);
+
+    let mut vm = Vm::new();
+    vm.run_function((), Rc::new(top_func))?;
+    let expected = vm.results().to_vec();
+    assert_eq!(expected[0].as_int(), Some(999_999_962));
+
+    let mut vm = Vm::new();
+    vm.run_function((), loaded)?;
+    assert_eq!(vm.results(), expected.as_slice());
+
+    // Incompatible chunks are rejected.
+    assert!(Func::from_bytes(b"WORC\x01\x00").is_err());
+    let mut future = bytes.clone();
+    future[4] = 3;
+    let Err(err) = Func::from_bytes(&future) else {
+        panic!("expected version error");
+    };
+    assert_eq!(err.message, "incompatible bytecode version; expected 2, found 3");
+    assert!(Func::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+    // Instructions the VM doesn't implement are rejected instead of loaded.
+    for op in [Op::Str_Concat, Op::Str_Slice] {
+        let func = func([op, op::end()], 3, &[]);
+        let Err(err) = Func::from_bytes(&func.to_bytes()) else {
+            panic!("expected unsupported instruction error");
+        };
+        assert_eq!(err.message, format!("unsupported instruction: {op:?}"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_func_serialization_depth() {
+    let nested = |depth: usize| {
+        let mut func = Func {
+            stack_size: 1,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code: Box::new([op::end()]),
+        };
+        for _ in 1..depth {
+            func = Func {
+                stack_size: 1,
+                arity: 0,
+                is_varg: false,
+                constants: Constants {
+                    ints: Box::new([]),
+                    floats: Box::new([]),
+                    strings: Box::new([]),
+                    funcs: Box::new([Rc::new(func)]),
+                },
+                up_values: Box::new([]),
+                code: Box::new([op::end()]),
+            };
+        }
+        func.to_bytes()
+    };
+
+    assert!(Func::from_bytes(&nested(MAX_CHUNK_DEPTH)).is_ok());
+    let Err(err) = Func::from_bytes(&nested(MAX_CHUNK_DEPTH + 1)) else {
+        panic!("expected depth error");
+    };
+    assert_eq!(err.message, "function constants are nested deeper than 128");
+}
+
+#[test]
+fn test_int_overflow() -> Result<()> {
+    let run = |ints: Box<[i64]>, arith: Op| -> Result<Option<i64>> {
+        let func = Rc::new(Func {
+            stack_size: 3,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints,
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code: Box::new([
+                Op::PushInt(Arg24::from_u32(0)?),
+                Op::PushInt(Arg24::from_u32(1)?),
+                arith,
+                Op::Return { results: 1 },
+            ]),
+        });
+        let mut vm = Vm::new();
+        vm.run_function((), func)?;
+        Ok(vm.results()[0].as_int())
+    };
+
+    for (ints, arith) in [
+        ([i64::MAX, 2], Op::Int_Mul),
+        ([i64::MAX, 1], Op::Int_Add),
+        ([i64::MIN, 1], Op::Int_Sub),
+        ([i64::MIN, -1], Op::Int_Div),
+        ([i64::MIN, -1], Op::Int_Mod),
+    ] {
+        let err = run(Box::new(ints), arith).unwrap_err();
+        assert_eq!(err.message, "integer overflow", "{arith:?}");
+    }
+
+    for arith in [Op::Int_Div, Op::Int_Mod] {
+        let err = run(Box::new([1, 0]), arith).unwrap_err();
+        assert_eq!(err.message, "division by zero", "{arith:?}");
+    }
+
+    assert_eq!(run(Box::new([i64::MAX / 2, 2]), Op::Int_Mul)?, Some(i64::MAX - 1));
+
+    Ok(())
+}
+
+#[test]
+fn test_native_fn() -> Result<()> {
+    fn add(_vm: &mut Vm, args: &[Value]) -> Result<Vec<Value>> {
+        match args {
+            [Value::Int(a), Value::Int(b)] => Ok(vec![Value::Int(a + b)]),
+            _ => crate::errors::runtime_err("add expects two integers").into(),
+        }
+    }
+
+    let func = |code: Box<[Op]>| {
+        Rc::new(Func {
+            stack_size: 5,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([Rc::new(CrowStr::new("add"))]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code,
+        })
+    };
+
+    let mut vm = Vm::new();
+    vm.register_native("add", add);
+
+    // let x = 1; return x, add(x, 41);
+    vm.run_function(
+        (),
+        func(Box::new([
+            op::push_int_inlined(1),
+            op::get_global(0),
+            op::get_local(1),
+            op::push_int_inlined(41),
+            op::call(2, 1),
+            op::return_(2),
+        ])),
+    )?;
+    let results: Vec<_> = vm.results().iter().map(Value::as_int).collect();
+    assert_eq!(results, [Some(1), Some(42)]);
+
+    // Errors from the native function propagate.
+    let err = vm
+        .run_function(
+            (),
+            func(Box::new([
+                op::get_global(0),
+                op::push_int_inlined(1),
+                op::call(1, 1),
+                op::end(),
+            ])),
+        )
+        .unwrap_err();
+    assert_eq!(err.message, "add expects two integers");
+    assert!(vm.stack.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_native_fn_reentry() -> Result<()> {
+    fn reenter(vm: &mut Vm, _args: &[Value]) -> Result<Vec<Value>> {
+        assert!(vm.run_function((), func([op::end()], 1, &[])).is_err());
+        assert!(vm.load_function((), func([op::end()], 1, &[])).is_err());
+        vm.eval(func([op::end()], 1, &[]))?;
+        Ok(vec![])
+    }
+
+    let mut vm = Vm::new();
+    vm.register_native("reenter", reenter);
+
+    let call_reenter = func([op::get_global(0), op::call(1, 0), op::end()], 2, &["reenter"]);
+    let err = vm.run_function((), call_reenter).unwrap_err();
+    assert_eq!(err.message, "cannot run bytecode from inside a native function");
+
+    // The VM can run again once the native function has returned.
+    assert_eq!(vm.eval(func([op::end()], 1, &[]))?, None);
+
+    Ok(())
+}
+
+/// fn countdown(n: Int) -> Int { if n <= 0 { return n; } return countdown(n - 1); }
+fn countdown_func(recurse: &[Op]) -> Rc<Func> {
+    let mut code = vec![
+        op::get_local(1),
+        op::push_int_inlined(0),
+        op::jump_gt(1),
+        op::return_(1),
+        op::get_upvalue(0),
+        op::get_local(1),
+        op::push_int_inlined(1),
+        op::int_sub(),
+    ];
+    code.extend_from_slice(recurse);
+
+    let countdown = Rc::new(Func {
+        stack_size: 6,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([UpValueOrigin::Parent(1)]),
+        code: code.into_boxed_slice(),
+    });
+
+    Rc::new(Func {
+        stack_size: 4,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([countdown]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            op::create_closure(0),
+            op::get_local(1),
+            op::push_int_inlined(crate::limits::MAX_CALL_DEPTH as i32 * 4),
+            op::call(2, 1),
+            op::return_(1),
+        ]),
+    })
+}
+
+#[test]
+fn test_tail_call() -> Result<()> {
+    let mut vm = Vm::new();
+    let depth = Rc::new(Cell::new(0_usize));
+    let max_depth = Rc::new(Cell::new(0_usize));
+    {
+        let (depth, max_depth) = (depth.clone(), max_depth.clone());
+        vm.set_trace_hook(Box::new(move |event| match event {
+            TraceEvent::Enter { .. } => {
+                depth.set(depth.get() + 1);
+                max_depth.set(max_depth.get().max(depth.get()));
+            }
+            TraceEvent::Exit { .. } => depth.set(depth.get() - 1),
+        }));
+    }
+
+    vm.run_function((), countdown_func(&[op::tail_call(2)]))?;
+    assert_eq!(vm.results()[0].as_int(), Some(0));
+    // The entry function and the first call to countdown.
+    assert_eq!(max_depth.get(), 2);
+
+    // The same recursion without tail calls exhausts the call stack.
+    let err = vm
+        .run_function((), countdown_func(&[op::call(2, 1), op::return_(1)]))
+        .unwrap_err();
+    assert_eq!(err.message, "call stack overflow");
+
+    Ok(())
+}
+
+#[test]
+fn test_bitwise() -> Result<()> {
+    let eval = |code: Box<[Op]>| -> Result<Option<Value>> {
+        let func = Rc::new(Func {
+            stack_size: 3,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([i64::MIN]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code,
+        });
+        Vm::new().eval(func)
+    };
+    let binary = |a: i32, b: i32, op: Op| {
+        eval(Box::new([
+            op::push_int_inlined(a),
+            op::push_int_inlined(b),
+            op,
+            op::return_(1),
+        ]))
+    };
+
+    assert_eq!(binary(0b1100, 0b1010, op::int_and())?, Some(Value::Int(0b1000)));
+    assert_eq!(binary(0b1100, 0b1010, op::int_or())?, Some(Value::Int(0b1110)));
+    assert_eq!(binary(0b1100, 0b1010, op::int_xor())?, Some(Value::Int(0b0110)));
+    assert_eq!(binary(-1, 0xFF, op::int_and())?, Some(Value::Int(0xFF)));
+
+    let not = |a: i32| eval(Box::new([op::push_int_inlined(a), op::int_not(), op::return_(1)]));
+    assert_eq!(not(0)?, Some(Value::Int(-1)));
+    assert_eq!(not(5)?, Some(Value::Int(-6)));
+
+    assert_eq!(binary(1, 4, op::int_shl())?, Some(Value::Int(16)));
+    assert_eq!(binary(1, 63, op::int_shl())?, Some(Value::Int(i64::MIN)));
+    assert_eq!(binary(-16, 2, op::int_shr())?, Some(Value::Int(-4)), "arithmetic shift");
+    assert_eq!(binary(16, 4, op::int_shr())?, Some(Value::Int(1)));
+
+    // Shifting out every bit is defined.
+    assert_eq!(binary(1, 64, op::int_shl())?, Some(Value::Int(0)));
+    assert_eq!(binary(1, 1_000_000, op::int_shl())?, Some(Value::Int(0)));
+    assert_eq!(binary(16, 64, op::int_shr())?, Some(Value::Int(0)));
+    let min_shr = eval(Box::new([
+        op::push_int(0),
+        op::push_int_inlined(100),
+        op::int_shr(),
+        op::return_(1),
+    ]))?;
+    assert_eq!(min_shr, Some(Value::Int(0)));
+
+    let err = binary(1, -1, op::int_shl()).unwrap_err();
+    assert_eq!(err.message, "negative shift amount: -1");
+    assert!(binary(1, -1, op::int_shr()).is_err());
+
+    // Operands must be integers, and the entry closure isn't one.
+    assert!(eval(Box::new([op::int_not(), Op::End])).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_int_float_conversion() -> Result<()> {
+    let convert = |push: Op, convert: Op| -> Result<Option<Value>> {
+        let func = Rc::new(Func {
+            stack_size: 2,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([3.9, -3.9, f64::NAN, 1e19]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code: Box::new([push, convert, op::return_(1)]),
+        });
+        Vm::new().eval(func)
+    };
+
+    assert_eq!(
+        convert(op::push_int_inlined(3), op::int_to_float())?,
+        Some(Value::Float(3.0))
+    );
+    assert_eq!(
+        convert(op::push_int_inlined(-3), op::int_to_float())?,
+        Some(Value::Float(-3.0))
+    );
+
+    // Truncated toward zero.
+    assert_eq!(convert(op::push_float(0), op::float_to_int())?, Some(Value::Int(3)));
+    assert_eq!(convert(op::push_float(1), op::float_to_int())?, Some(Value::Int(-3)));
+
+    let err = convert(op::push_float(2), op::float_to_int()).unwrap_err();
+    assert_eq!(err.message, "float out of integer range: NaN");
+    assert!(convert(op::push_float(3), op::float_to_int()).is_err());
+
+    // Operands must be of the converted kind.
+    assert!(convert(op::push_float(0), op::int_to_float()).is_err());
+    assert!(convert(op::push_int_inlined(3), op::float_to_int()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_dup_swap() -> Result<()> {
+    let func = |code: Box<[Op]>| {
+        Rc::new(Func {
+            stack_size: 4,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code,
+        })
+    };
+
+    let mut vm = Vm::new();
+    vm.run_function(
+        (),
+        func(Box::new([
+            op::push_int_inlined(1),
+            op::push_int_inlined(2),
+            op::swap(),
+            op::dup(),
+            op::return_(3),
+        ])),
+    )?;
+    assert_eq!(vm.results(), [Value::Int(2), Value::Int(1), Value::Int(1)]);
+
+    // `x - 1` with the operands pushed in the wrong order.
+    let result = vm.eval(func(Box::new([
+        op::push_int_inlined(1),
+        op::push_int_inlined(10),
+        op::swap(),
+        op::int_sub(),
+        op::return_(1),
+    ])))?;
+    assert_eq!(result, Some(Value::Int(9)));
+
+    // Underflow
+    vm.run_function((), func(Box::new([op::pop(1), op::dup(), Op::End])))
+        .unwrap_err();
+    let err = vm.run_function((), func(Box::new([op::swap(), Op::End]))).unwrap_err();
+    assert_eq!(err.message, "stack underflow");
+
+    Ok(())
+}
+
+#[test]
+fn test_push_literals() -> Result<()> {
+    let func = |code: Box<[Op]>| {
+        Rc::new(Func {
+            stack_size: 4,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code,
+        })
+    };
+
+    let mut vm = Vm::new();
+    vm.run_function(
+        (),
+        func(Box::new([
+            op::push_true(),
+            op::push_false(),
+            op::push_nil(),
+            op::return_(3),
+        ])),
+    )?;
+    assert_eq!(vm.results(), [Value::Bool(true), Value::Bool(false), Value::Nil]);
+
+    // Booleans are accepted as conditions.
+    let result = vm.eval(func(Box::new([
+        op::push_false(),
+        Op::JumpZero {
+            addr: Arg24::from_i64(2)?,
+        },
+        op::push_int_inlined(1),
+        op::return_(1),
+        op::push_int_inlined(2),
+        op::return_(1),
+    ])))?;
+    assert_eq!(result, Some(Value::Int(2)));
+
+    Ok(())
+}
+
+#[test]
+fn test_nil_round_trip() -> Result<()> {
+    let func = Rc::new(Func {
+        stack_size: 2,
+        arity: 0,
+        is_varg: false,
+        constants: Constants {
+            ints: Box::new([]),
+            floats: Box::new([]),
+            strings: Box::new([]),
+            funcs: Box::new([]),
+        },
+        up_values: Box::new([]),
+        code: Box::new([
+            // local a = nil
+            op::push_nil(),
+            op::get_local(1),
+            op::dup(),
+            op::return_(2),
+        ]),
+    });
+
+    let mut vm = Vm::new();
+    vm.run_function((), func)?;
+    assert_eq!(vm.results(), [Value::Nil, Value::Nil]);
+    assert!(vm.results()[0].is_nil());
+    assert_ne!(vm.results()[0], Value::Int(0));
+    assert_ne!(vm.results()[0], Value::Bool(false));
+
+    Ok(())
+}
+
+#[test]
+fn test_neg() -> Result<()> {
+    let func = |code: Box<[Op]>| {
+        Rc::new(Func {
+            stack_size: 4,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([i64::MIN]),
+                floats: Box::new([2.5]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code,
+        })
+    };
+
+    // Locals below the operand are left untouched.
+    let mut vm = Vm::new();
+    vm.run_function(
+        (),
+        func(Box::new([
+            op::push_int_inlined(7),
+            op::push_int_inlined(5),
+            op::int_neg(),
+            op::push_float(0),
+            op::float_neg(),
+            op::return_(3),
+        ])),
+    )?;
+    assert_eq!(vm.results()[0].as_int(), Some(7));
+    assert_eq!(vm.results()[1].as_int(), Some(-5));
+    assert_eq!(vm.results()[2].as_float(), Some(-2.5));
+
+    let err = vm
+        .run_function(
+            (),
+            func(Box::new([Op::PushInt(Arg24::from_u32(0)?), op::int_neg(), op::end()])),
+        )
+        .unwrap_err();
+    assert_eq!(err.message, "integer overflow");
+
+    Ok(())
+}
+
+#[test]
+fn test_local_out_of_bounds() -> Result<()> {
+    for access in [op::get_local(3), op::set_local(3), op::store_local(3)] {
+        let func = Rc::new(Func {
+            stack_size: 3,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code: Box::new([op::push_int_inlined(1), op::push_int_inlined(2), access, op::end()]),
+        });
+
+        let mut vm = Vm::new();
+        let err = vm.run_function((), func).unwrap_err();
+        assert_eq!(err.message, "stack index out of bounds", "{access:?}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_array() -> Result<()> {
+    let func = |tail: &[Op]| {
+        let mut code = vec![
+            // let a = [];
+            op::new_array(0),
+            // a.push(10); a.push(20);
+            op::get_local(1),
+            op::push_int_inlined(10),
+            op::array_push(),
+            op::get_local(1),
+            op::push_int_inlined(20),
+            op::array_push(),
+        ];
+        code.extend_from_slice(tail);
+        Rc::new(Func {
+            stack_size: 6,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+            code: code.into_boxed_slice(),
+        })
+    };
+
+    let mut vm = Vm::new();
+    vm.run_function(
+        (),
+        func(&[
+            // a[1]
+            op::get_local(1),
+            op::push_int_inlined(1),
+            op::array_get(),
+            // a[0] = 30;
+            op::get_local(1),
+            op::push_int_inlined(0),
+            op::push_int_inlined(30),
+            op::array_set(),
+            op::get_local(1),
+            op::push_int_inlined(0),
+            op::array_get(),
+            op::return_(2),
+        ]),
+    )?;
+    assert_eq!(vm.results(), [Value::Int(20), Value::Int(30)]);
+
+    for index in [2, -1] {
+        for access in [
+            &[
+                op::get_local(1),
+                op::push_int_inlined(index),
+                op::array_get(),
+                op::end(),
+            ][..],
+            &[
+                op::get_local(1),
+                op::push_int_inlined(index),
+                op::push_int_inlined(0),
+                op::array_set(),
+                op::end(),
+            ],
+        ] {
+            let err = vm.run_function((), func(access)).unwrap_err();
+            assert_eq!(err.message, format!("array index out of bounds: {index} (length 2)"));
+        }
+    }
 
     Ok(())
 }
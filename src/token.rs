@@ -50,6 +50,9 @@ impl fmt::Display for LitValue {
     }
 }
 
+/// Default number of columns between tab stops.
+pub const DEFAULT_TAB_WIDTH: u32 = 4;
+
 /// Span of text.
 ///
 /// Stores index and count.
@@ -75,6 +78,88 @@ impl Span {
     pub fn count(&self) -> u32 {
         self.1
     }
+
+    /// One-based line and column where the span starts.
+    ///
+    /// A tab advances the column to the next multiple of `tab_width`,
+    /// so columns match how the source is displayed in an editor.
+    pub fn line_column(&self, text: &str, tab_width: u32) -> (u32, u32) {
+        let start = (self.0 as usize).min(text.len());
+        let before = &text[..start];
+        let line = before.matches('\n').count() as u32 + 1;
+        let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(text_start(text));
+        let column = display_width(&before[line_start..], 0, tab_width) + 1;
+        (line, column)
+    }
+
+    /// Render the source line where the span starts, with a caret
+    /// underline beneath the spanned fragment.
+    ///
+    /// ```text
+    ///     let x = y;
+    ///         ^
+    /// ```
+    ///
+    /// Tabs are expanded to spaces, so the carets line up regardless of
+    /// how the output is displayed. A span that continues onto the
+    /// next line is underlined up to the end of its first line.
+    pub fn caret_snippet(&self, text: &str, tab_width: u32) -> String {
+        let start = (self.0 as usize).min(text.len());
+        let end = (start + self.1 as usize).min(text.len());
+        let line_start = text[..start]
+            .rfind('\n')
+            .map(|index| index + 1)
+            .unwrap_or(text_start(text).min(start));
+        let line_end = text[start..]
+            .find('\n')
+            .map(|index| start + index)
+            .unwrap_or(text.len());
+        let line = text[line_start..line_end].trim_end_matches('\r');
+
+        let mut buf = String::new();
+        let mut column = 0;
+        for ch in line.chars() {
+            if ch == '\t' {
+                let next = display_width("\t", column, tab_width);
+                buf.extend(std::iter::repeat_n(' ', (next - column) as usize));
+                column = next;
+            } else {
+                buf.push(ch);
+                column += 1;
+            }
+        }
+
+        let caret_start = display_width(&text[line_start..start], 0, tab_width);
+        let caret_end = display_width(&text[start..end.min(line_end)], caret_start, tab_width);
+
+        buf.push('\n');
+        buf.extend(std::iter::repeat_n(' ', caret_start as usize));
+        buf.extend(std::iter::repeat_n('^', (caret_end - caret_start).max(1) as usize));
+        buf
+    }
+}
+
+/// Offset of the first line, after the byte-order mark if there is one.
+fn text_start(text: &str) -> usize {
+    if text.starts_with('\u{FEFF}') {
+        '\u{FEFF}'.len_utf8()
+    } else {
+        0
+    }
+}
+
+/// Column after displaying the given text, starting at column `start`.
+///
+/// A tab width of zero is treated as one.
+fn display_width(text: &str, start: u32, tab_width: u32) -> u32 {
+    let tab_width = tab_width.max(1);
+    text.chars().fold(start, |column, ch| {
+        if ch == '\t' {
+            column + tab_width - column % tab_width
+        } else {
+            column + 1
+        }
+    })
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -89,6 +174,8 @@ pub enum TokenKind {
     Colon,    // :
     Semi,     // ;
     Perc,     // %
+    Arrow,    // ->
+    Question, // ?
 
     Plus,     // +
     Minus,    // -
@@ -109,7 +196,8 @@ pub enum TokenKind {
     GreatEq,     // >=
 
     Ident,   // identifier
-    Num,     // integer literal
+    Int,     // integer literal
+    Float,   // float literal
     Str,     // string literal
     Doc,     // document comment
 
@@ -121,13 +209,17 @@ pub enum TokenKind {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Keyword {
     And,
+    False,
     Fn,
     For,
     Let,
     If,
     Import,
+    Nil,
     Or,
+    Return,
     Struct,
+    True,
     Type,
     While,
 }
@@ -171,12 +263,15 @@ impl Precedence {
         use self::TokenKind::*;
 
         match kind {
-            Num | Ident => Precedence::Lowest,
+            Int | Float | Ident => Precedence::Lowest,
             Plus | Minus => Precedence::Term,
-            Star | Slash => Precedence::Factor,
+            Star | Slash | Perc => Precedence::Factor,
             StarStar => Precedence::Exponent,
             Eq => Precedence::Assignment,
-            EqEq => Precedence::Equality,
+            EqEq | NotEq => Precedence::Equality,
+            Less | LessEq | Great | GreatEq => Precedence::Comparison,
+            Kw(Keyword::Or) => Precedence::LogicalOr,
+            Kw(Keyword::And) => Precedence::LogicalAnd,
             Dot | ParenLeft | BracketLeft => Precedence::Call,
             // ------------------------------------------------
             // Terminators
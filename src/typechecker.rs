@@ -1,7 +1,8 @@
 use std::collections::HashMap;
+use std::fmt::Write;
 
 use crate::ast::*;
-use crate::errors::{typecheck_err, Result};
+use crate::errors::{typecheck_err, Error, Result};
 use crate::types::*;
 
 pub struct TypeChecker {
@@ -9,6 +10,14 @@ pub struct TypeChecker {
     aliases: HashMap<String, TypeId>,
     scope: Scope,
     scopes: Vec<Scope>,
+    /// Type returned so far by the body being checked.
+    return_ty: Option<TypeId>,
+    /// Variables provided by the host to every module, outside of all scopes.
+    builtins: Scope,
+    /// Errors recovered from while checking statements.
+    errors: Vec<Error>,
+    /// Non-fatal diagnostics found while checking.
+    warnings: Vec<Error>,
 }
 
 struct Scope {
@@ -19,16 +28,51 @@ struct Scope {
 struct Local {
     name: String,
     ty: TypeId,
+    /// Number of times the variable is read.
+    reads: u32,
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
-        Self {
+        let mut checker = Self {
             types: init_type_table(),
             aliases: init_type_aliases(),
             scope: Scope { locals: vec![] },
             scopes: vec![],
-        }
+            return_ty: None,
+            builtins: Scope { locals: vec![] },
+            errors: vec![],
+            warnings: vec![],
+        };
+        checker.declare_builtins();
+        checker
+    }
+
+    /// Declare the variables the host provides to every module.
+    ///
+    /// ```text
+    /// System: struct { Print: fn(String) }
+    /// ```
+    fn declare_builtins(&mut self) {
+        let print = self.intern_type(Type::Func {
+            args: vec![TYPE_STRING_ID],
+            arg_names: vec!["text".to_string()],
+            retunr_: TYPE_VOID_ID,
+        });
+        let system = self.intern_type(Type::Struct {
+            fields: vec![("Print".to_string(), print)],
+        });
+
+        self.builtins.locals.push(Local {
+            name: "System".to_string(),
+            ty: system,
+            reads: 0,
+        });
+    }
+
+    /// Remove the warnings found so far, to hand them to the caller.
+    pub fn take_warnings(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.warnings)
     }
 
     /// Resolve a type from a type definition syntax node.
@@ -48,34 +92,384 @@ impl TypeChecker {
                 .get(name.text.text.as_str())
                 .cloned()
                 .ok_or_else(|| typecheck_err(format!("unknown type alias: {}", name.text.text))),
-            TypeDef::Lit(Array { .. }) => todo!(),
-            TypeDef::Lit(DynArray { .. }) => todo!(),
-            TypeDef::Lit(Table { .. }) => todo!(),
-            TypeDef::Lit(Struct { .. }) => todo!(),
+            TypeDef::Lit(Array { element, size }) => {
+                let element = self.resolve_type(element)?;
+                Ok(self.intern_type(Type::Array(element, *size)))
+            }
+            TypeDef::Lit(DynArray { element }) => {
+                let element = self.resolve_type(element)?;
+                Ok(self.intern_type(Type::DynArray(element)))
+            }
+            TypeDef::Lit(Table { key, value }) => {
+                let key = self.resolve_type(key)?;
+                let value = self.resolve_type(value)?;
+                Ok(self.intern_type(Type::Table(key, value)))
+            }
+            TypeDef::Lit(Struct { fields: field_defs }) => {
+                let mut fields: Vec<(String, TypeId)> = Vec::with_capacity(field_defs.len());
+
+                for field_def in field_defs {
+                    let name = &field_def.name.text;
+                    if fields.iter().any(|(other, _)| other == name) {
+                        return typecheck_err(format!("duplicate field in struct type: {name}")).into();
+                    }
+
+                    let ty = self.resolve_type(&field_def.ty)?;
+                    fields.push((name.clone(), ty));
+                }
+
+                Ok(self.intern_type(Type::Struct { fields }))
+            }
+            TypeDef::Optional(inner) => {
+                let inner = self.resolve_type(inner)?;
+                Ok(self.intern_type(Type::Optional(inner)))
+            }
+        }
+    }
+
+    /// Check whether a value of type `found` can be stored in a place of type `expected`.
+    ///
+    /// An optional type accepts `nil` and values of its inner type.
+    /// The reverse is not true; an optional value must be unwrapped
+    /// before it can be used as its inner type.
+    fn is_assignable(&self, expected: TypeId, found: TypeId) -> bool {
+        if expected == found || expected == TYPE_ERROR_ID || found == TYPE_ERROR_ID {
+            return true;
+        }
+
+        match self.types.get(expected.0 as usize) {
+            Some(Type::Optional(inner)) => found == TYPE_NIL_ID || found == *inner,
+            _ => false,
+        }
+    }
+
+    /// Lookup an existing type in the type table, or define it if it doesn't exist yet.
+    fn intern_type(&mut self, ty: Type) -> TypeId {
+        match self.types.iter().position(|other| *other == ty) {
+            Some(index) => TypeId(index as u32),
+            None => {
+                self.types.push(ty);
+                TypeId((self.types.len() - 1) as u32)
+            }
+        }
+    }
+
+    /// Render the given type, including the types nested inside it.
+    ///
+    /// ```text
+    /// [Int; 3]
+    /// [Int]
+    /// Int?
+    /// {String: Int}
+    /// struct { x: Int, y: Float }
+    /// (Int, Float)
+    /// fn(Int) -> Float
+    /// ```
+    pub fn display_type(&self, type_id: TypeId) -> String {
+        let mut buf = String::new();
+        self.write_type(&mut buf, type_id);
+        buf
+    }
+
+    fn write_type(&self, buf: &mut String, type_id: TypeId) {
+        let ty = match self.types.get(type_id.0 as usize) {
+            Some(ty) => ty,
+            None => {
+                let _ = write!(buf, "<unknown type {}>", type_id.0);
+                return;
+            }
+        };
+
+        match ty {
+            Type::Tuple(items) => {
+                buf.push('(');
+                self.write_type_list(buf, items);
+                buf.push(')');
+            }
+            Type::Array(element, size) => {
+                buf.push('[');
+                self.write_type(buf, *element);
+                let _ = write!(buf, "; {size}]");
+            }
+            Type::DynArray(element) => {
+                buf.push('[');
+                self.write_type(buf, *element);
+                buf.push(']');
+            }
+            Type::Table(key, value) => {
+                buf.push('{');
+                self.write_type(buf, *key);
+                buf.push_str(": ");
+                self.write_type(buf, *value);
+                buf.push('}');
+            }
+            Type::Optional(inner) => {
+                self.write_type(buf, *inner);
+                buf.push('?');
+            }
+            Type::Func { args, retunr_, .. } => {
+                buf.push_str("fn(");
+                self.write_type_list(buf, args);
+                buf.push(')');
+                if *retunr_ != TYPE_VOID_ID {
+                    buf.push_str(" -> ");
+                    self.write_type(buf, *retunr_);
+                }
+            }
+            Type::Struct { fields } => {
+                buf.push_str("struct {");
+                for (index, (name, ty)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        buf.push(',');
+                    }
+                    let _ = write!(buf, " {name}: ");
+                    self.write_type(buf, *ty);
+                }
+                buf.push_str(" }");
+            }
+            Type::Void | Type::Int | Type::Float | Type::String | Type::Nil | Type::Bool | Type::Error => {
+                let _ = write!(buf, "{ty}");
+            }
+        }
+    }
+
+    fn write_type_list(&self, buf: &mut String, type_ids: &[TypeId]) {
+        for (index, type_id) in type_ids.iter().enumerate() {
+            if index > 0 {
+                buf.push_str(", ");
+            }
+            self.write_type(buf, *type_id);
         }
     }
 
-    /// Type check the given block.
-    pub fn check_block(&mut self, block: &Block) -> Result<TypeId> {
-        // TODO: Collect all the return types to determin the block's return type.
-        for stmt in &block.stmts {
-            // The resulting type of a statement is discarded.
-            self.check_stmt(stmt)?;
+    /// Type check a top-level module.
+    ///
+    /// Unlike a nested block, the module's scope is exited afterwards.
+    ///
+    /// The checker recovers from type errors in statements, so all
+    /// errors in the module are returned together.
+    pub fn check_module(&mut self, block: &mut Block) -> std::result::Result<TypeId, Vec<Error>> {
+        let ty = self.check_block(block);
+        self.exit_module_scope();
+
+        if self.errors.is_empty() {
+            Ok(ty)
+        } else {
+            Err(std::mem::take(&mut self.errors))
         }
+    }
+
+    /// Report the unused locals of the module scope, and clear it.
+    fn exit_module_scope(&mut self) {
+        for local in std::mem::take(&mut self.scope.locals) {
+            self.check_unused_local(&local);
+        }
+    }
+
+    /// Start a new scope for a nested block.
+    ///
+    /// Locals of enclosing scopes stay visible, and can be shadowed.
+    fn enter_scope(&mut self) {
+        let outer = std::mem::replace(&mut self.scope, Scope { locals: vec![] });
+        self.scopes.push(outer);
+    }
+
+    /// Restore the enclosing scope, dropping the locals of the nested block.
+    fn exit_scope(&mut self) {
+        let outer = self.scopes.pop().expect("exited more scopes than were entered");
+        let inner = std::mem::replace(&mut self.scope, outer);
+        for local in &inner.locals {
+            self.check_unused_local(local);
+        }
+    }
+
+    /// Warn when a local variable was declared but never read.
+    ///
+    /// Variables that start with an underscore are intentionally unused.
+    fn check_unused_local(&mut self, local: &Local) {
+        if local.reads == 0 && !local.name.starts_with('_') {
+            self.warnings
+                .push(typecheck_err(format!("unused variable: {}", local.name)));
+        }
+    }
+
+    /// Type check the given block as the body of a module or function.
+    ///
+    /// The block's return type is determined by its return statements,
+    /// including those nested in loops, and its trailing expression, which
+    /// must all be the same type. The value of `{ let x = 1; x }` is an `Int`.
+    ///
+    /// A block that doesn't end with a return or trailing expression can
+    /// run off its end, which returns nothing, so it's void like an empty
+    /// block. Any returns nested in its loops must then be void as well.
+    ///
+    /// A statement with a type error is recorded and skipped, so checking
+    /// can continue with the next statement. The recorded errors are
+    /// returned by [`TypeChecker::check_module`].
+    fn check_block(&mut self, block: &mut Block) -> TypeId {
+        // An enclosing body doesn't share its return type.
+        let outer_return_ty = self.return_ty.take();
+
+        let value_ty = self.check_stmts(block);
+        let ends_with_return = matches!(block.stmts.last(), Some(Stmt::Return(_)));
+
+        if let Some(end_ty) = value_ty.or((!ends_with_return).then_some(TYPE_VOID_ID)) {
+            if let Err(err) = self.unify_return_ty(end_ty) {
+                self.errors.push(err);
+            }
+        }
+
+        let return_ty = std::mem::replace(&mut self.return_ty, outer_return_ty);
 
         // Block with no return will return void.
-        Ok(TYPE_VOID_ID)
+        return_ty.unwrap_or(TYPE_VOID_ID)
     }
 
-    /// Type check all the given statements.
-    pub fn check_stmt(&mut self, stmt: &Stmt) -> Result<TypeId> {
+    /// Type check the statements of a block, returning the type of its
+    /// trailing expression, if it has one.
+    fn check_stmts(&mut self, block: &mut Block) -> Option<TypeId> {
+        let mut value_ty = None;
+
+        for stmt in &mut block.stmts {
+            let stmt_ty = match self.check_stmt(stmt) {
+                Ok(ty) => ty,
+                Err(err) => {
+                    self.errors.push(err);
+                    TYPE_ERROR_ID
+                }
+            };
+
+            // The resulting type of a statement is discarded,
+            // unless it's the block's value.
+            if let Stmt::Tail(_) = stmt {
+                value_ty = Some(stmt_ty);
+            }
+        }
+
+        value_ty
+    }
+
+    /// Check that the given type agrees with the other values
+    /// returned from the body being checked.
+    fn unify_return_ty(&mut self, ty: TypeId) -> Result<()> {
+        match self.return_ty {
+            None => self.return_ty = Some(ty),
+            Some(_) if ty == TYPE_ERROR_ID => {}
+            Some(TYPE_ERROR_ID) => self.return_ty = Some(ty),
+            Some(expected_ty) if expected_ty != ty => {
+                return typecheck_err(format!(
+                    "inconsistent return types; expected {}, found {}",
+                    self.display_type(expected_ty),
+                    self.display_type(ty)
+                ))
+                .into()
+            }
+            Some(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Type check the given statement.
+    ///
+    /// Errors in the statements of a nested block are recorded rather than
+    /// returned, like those of [`TypeChecker::check_block`].
+    fn check_stmt(&mut self, stmt: &mut Stmt) -> Result<TypeId> {
         match stmt {
             Stmt::Local(local_decl) => self.check_local_decl(local_decl),
-            Stmt::Return => todo!(),
-            Stmt::Expr(_) => todo!(),
+            Stmt::Return(return_stmt) => self.check_return_stmt(return_stmt),
+            Stmt::While(while_stmt) => self.check_while_stmt(while_stmt),
+            Stmt::Expr(expr) => self.check_expr_stmt(expr),
+            Stmt::TypeDecl(type_decl) => self.check_type_decl(type_decl),
+            Stmt::Tail(expr) => self.check_expr(expr),
         }
     }
 
+    /// Type check an expression statement.
+    ///
+    /// The expression's value is discarded, so the statement is void.
+    fn check_expr_stmt(&mut self, expr: &mut Expr) -> Result<TypeId> {
+        match expr {
+            Expr::Binary(binary_expr) if matches!(binary_expr.op, BinaryOp::Assign) => self.check_assign(binary_expr),
+            _ => self.check_expr(expr).map(|_| TYPE_VOID_ID),
+        }
+    }
+
+    /// Type check an assignment statement.
+    ///
+    /// The value must be assignable to the target, and the assignment
+    /// itself has no value.
+    fn check_assign(&mut self, assign: &mut BinaryExpr) -> Result<TypeId> {
+        let lhs_ty = match &assign.lhs {
+            // Assigning to a variable doesn't count as reading it.
+            Expr::Name(name_expr) => self.lookup_local(&name_expr.ident.text)?.ty,
+            _ => self.check_expr(&mut assign.lhs)?,
+        };
+        let rhs_ty = self.check_expr(&mut assign.rhs)?;
+
+        // The operand's error was already reported.
+        if lhs_ty == TYPE_ERROR_ID || rhs_ty == TYPE_ERROR_ID {
+            return Ok(TYPE_ERROR_ID);
+        }
+
+        if !self.is_assignable(lhs_ty, rhs_ty) {
+            return typecheck_err(format!(
+                "mismatched types in assignment; expected {}, found {}",
+                self.display_type(lhs_ty),
+                self.display_type(rhs_ty)
+            ))
+            .into();
+        }
+
+        assign.operand_ty = lhs_ty;
+        Ok(TYPE_VOID_ID)
+    }
+
+    /// Register the type alias declared by the given statement.
+    ///
+    /// A declaration is a statement, so its resulting type is void.
+    fn check_type_decl(&mut self, type_decl: &TypeDeclStmt) -> Result<TypeId> {
+        let ty = self.resolve_type(&type_decl.rhs)?;
+        self.aliases.insert(type_decl.name.text.clone(), ty);
+        Ok(TYPE_VOID_ID)
+    }
+
+    /// Type check the given return statement.
+    ///
+    /// Returning nothing is [`Type::Void`], returning a single value is that
+    /// value's type, and returning multiple values is a [`Type::Tuple`].
+    fn check_return_stmt(&mut self, return_stmt: &mut ReturnStmt) -> Result<TypeId> {
+        let mut item_tys = Vec::with_capacity(return_stmt.value.items.len());
+
+        for item in &mut return_stmt.value.items {
+            item_tys.push(self.check_expr(&mut item.expr)?);
+        }
+
+        let ty = match item_tys.as_slice() {
+            [] => TYPE_VOID_ID,
+            [ty] => *ty,
+            _ => self.intern_type(Type::Tuple(item_tys)),
+        };
+        self.unify_return_ty(ty)?;
+
+        Ok(ty)
+    }
+
+    /// Type check the given while loop.
+    ///
+    /// A loop is a statement, so its resulting type is void. The value
+    /// of its body is discarded, but a return in the body returns from
+    /// the enclosing function.
+    fn check_while_stmt(&mut self, while_stmt: &mut WhileStmt) -> Result<TypeId> {
+        self.check_expr(&mut while_stmt.cond)?;
+
+        self.enter_scope();
+        self.check_stmts(&mut while_stmt.body);
+        self.exit_scope();
+
+        Ok(TYPE_VOID_ID)
+    }
+
     /// Type check the given local variable declaration.
     ///
     /// Variable declaration has three forms:
@@ -85,7 +479,15 @@ impl TypeChecker {
     /// 3. Type and RHS expression
     ///
     /// A local variable declaration with no type and no right hand side expression is invalid.
-    fn check_local_decl(&mut self, local_decl: &LocalDecl) -> Result<TypeId> {
+    fn check_local_decl(&mut self, local_decl: &mut LocalDecl) -> Result<TypeId> {
+        self.check_local_decl_inner(local_decl).map_err(|err| {
+            // Later uses of the variable shouldn't be reported as unknown.
+            self.declare_local(local_decl.name.text.clone(), TYPE_ERROR_ID);
+            spanned(err, &local_decl.name)
+        })
+    }
+
+    fn check_local_decl_inner(&mut self, local_decl: &mut LocalDecl) -> Result<TypeId> {
         // Type is explicitly user defined.
         let maybe_ty = match &local_decl.ty {
             Some(type_lit) => Some(self.resolve_type(type_lit)?),
@@ -93,19 +495,22 @@ impl TypeChecker {
         };
 
         // Initial value is defined with an expression.
-        let maybe_rhs_ty = match &local_decl.rhs {
-            Some(expr) => Some(self.check_expr(&expr)?),
+        let maybe_rhs_ty = match &mut local_decl.rhs {
+            Some(expr) => Some(self.check_expr(expr)?),
             None => None,
         };
 
         match (maybe_ty, maybe_rhs_ty) {
             // No type nor expression defined.
-            (None, None) => typecheck_err(format!(
-                "local variable declaration needs an explicit type, or an initial value"
-            ))
-            .into(),
+            (None, None) => {
+                typecheck_err("local variable declaration needs an explicit type, or an initial value").into()
+            }
             // Void cannot be used as a value.
-            (_, Some(TYPE_VOID_ID)) => typecheck_err(format!("Void cannot be assigned to a variable")).into(),
+            (_, Some(TYPE_VOID_ID)) => typecheck_err("Void cannot be assigned to a variable").into(),
+            // The type of nil can't be inferred.
+            (None, Some(TYPE_NIL_ID)) => {
+                typecheck_err("nil needs an explicit optional type to be assigned to a variable").into()
+            }
             // Type inference.
             (None, Some(ty)) => {
                 self.declare_local(local_decl.name.text.clone(), ty);
@@ -119,99 +524,1233 @@ impl TypeChecker {
             // Expression must be assignable to the defined type.
             (Some(ty), Some(expr_ty)) => {
                 // TODO: Upcasting to interfaces.
-                if ty == expr_ty {
+                if self.is_assignable(ty, expr_ty) {
                     self.declare_local(local_decl.name.text.clone(), ty);
                     Ok(ty)
                 } else {
-                    typecheck_err(format!("mismatched types; expected {:?}, found {:?}", ty, expr_ty)).into()
+                    typecheck_err(format!(
+                        "mismatched types; expected {}, found {}",
+                        self.display_type(ty),
+                        self.display_type(expr_ty)
+                    ))
+                    .into()
                 }
             }
         }
     }
 
     /// Type check the given expression node.
-    pub fn check_expr(&mut self, expr: &Expr) -> Result<TypeId> {
+    pub fn check_expr(&mut self, expr: &mut Expr) -> Result<TypeId> {
         match expr {
-            Expr::Name(_) => todo!(),
+            Expr::Name(name_expr) => {
+                let local = self
+                    .lookup_local(&name_expr.ident.text)
+                    .map_err(|err| spanned(err, &name_expr.ident))?;
+                local.reads += 1;
+                Ok(local.ty)
+            }
             Expr::Binary(binary_expr) => self.check_binary_expr(binary_expr),
-            Expr::Lit(literal) => Ok(literal.type_id()),
-            Expr::Func(_) => todo!(),
-            Expr::Call(_) => todo!(),
+            Expr::Logical(logical_expr) => self.check_logical_expr(logical_expr),
+            Expr::Lit(literal) => self.check_literal(literal),
+            Expr::Func(func_lit) => self.check_func_lit(func_lit),
+            Expr::Call(call_expr) => self.check_call(call_expr),
+            Expr::ArrayLit(array_lit) => self.check_array_lit(array_lit),
+            Expr::Index(index_expr) => self.check_index_expr(index_expr),
+            Expr::Field(field_expr) => self.check_field(field_expr),
+        }
+    }
+
+    /// Type check an index expression.
+    ///
+    /// Arrays are indexed by integer, and tables by their key type.
+    fn check_index_expr(&mut self, index_expr: &mut IndexExpr) -> Result<TypeId> {
+        let target_ty = self.check_expr(&mut index_expr.target)?;
+        let index_ty = self.check_expr(&mut index_expr.index)?;
+
+        let (expected_index_ty, element_ty) = match self.types.get(target_ty.0 as usize) {
+            Some(Type::Array(element_ty, _) | Type::DynArray(element_ty)) => (TYPE_INT_ID, *element_ty),
+            Some(Type::Table(key_ty, value_ty)) => (*key_ty, *value_ty),
+            _ => {
+                return typecheck_err(format!("type {} cannot be indexed", self.display_type(target_ty))).into();
+            }
+        };
+
+        if index_ty != expected_index_ty {
+            return typecheck_err(format!(
+                "mismatched index type; expected {}, found {}",
+                self.display_type(expected_index_ty),
+                self.display_type(index_ty)
+            ))
+            .into();
+        }
+
+        Ok(element_ty)
+    }
+
+    /// Type check access to a struct field.
+    fn check_field(&mut self, field_expr: &mut FieldExpr) -> Result<TypeId> {
+        let target_ty = self.check_expr(&mut field_expr.target)?;
+        let name = field_expr.field.text.as_str();
+
+        match self.types.get(target_ty.0 as usize) {
+            Some(Type::Struct { fields }) => match fields.iter().find(|(field, _)| field == name) {
+                Some((_, ty)) => Ok(*ty),
+                None => typecheck_err(format!("no field '{name}' on type {}", self.display_type(target_ty))).into(),
+            },
+            _ => typecheck_err(format!(
+                "type {} has no fields; cannot access '{name}'",
+                self.display_type(target_ty)
+            ))
+            .into(),
+        }
+    }
+
+    /// Type check a call, resulting in the callee's return type.
+    ///
+    /// Arguments are matched to parameters by [`order_call_args`],
+    /// and each must be assignable to its parameter.
+    fn check_call(&mut self, call_expr: &mut CallExpr) -> Result<TypeId> {
+        let callee_ty = self.check_expr(&mut call_expr.callee)?;
+
+        // The callee's error was already reported.
+        if callee_ty == TYPE_ERROR_ID {
+            for arg in &mut call_expr.args {
+                self.check_expr(arg)?;
+            }
+            for named_arg in &mut call_expr.named_args {
+                self.check_expr(&mut named_arg.value)?;
+            }
+            return Ok(TYPE_ERROR_ID);
+        }
+
+        let (param_tys, param_names, return_ty) = match self.types.get(callee_ty.0 as usize) {
+            Some(Type::Func {
+                args,
+                arg_names,
+                retunr_,
+            }) => (args.clone(), arg_names.clone(), *retunr_),
+            _ => return typecheck_err(format!("type {} is not callable", self.display_type(callee_ty))).into(),
+        };
+
+        let args = order_call_args(&param_names, call_expr)?;
+
+        for (param_ty, arg) in param_tys.into_iter().zip(args) {
+            let arg_ty = self.check_expr(arg)?;
+            if !self.is_assignable(param_ty, arg_ty) {
+                return typecheck_err(format!(
+                    "mismatched argument types; expected {}, found {}",
+                    self.display_type(param_ty),
+                    self.display_type(arg_ty)
+                ))
+                .into();
+            }
         }
+
+        Ok(return_ty)
     }
 
-    fn check_binary_expr(&mut self, binary_expr: &BinaryExpr) -> Result<TypeId> {
-        let lhs_ty = self.check_expr(&binary_expr.lhs)?;
-        let rhs_ty = self.check_expr(&binary_expr.rhs)?;
+    /// Type check a function literal.
+    ///
+    /// The arguments are locals of the body, which can also read the locals
+    /// of enclosing scopes. The body must return the annotated return type,
+    /// or nothing when the return type isn't annotated.
+    fn check_func_lit(&mut self, func_lit: &mut FuncLit) -> Result<TypeId> {
+        let mut arg_tys = Vec::with_capacity(func_lit.args.len());
+        for arg in &func_lit.args {
+            let ty = self.aliases.get(arg.ty_name.text.as_str()).cloned().ok_or_else(|| {
+                let err = typecheck_err(format!("unknown type alias: {}", arg.ty_name.text));
+                spanned(err, &arg.ty_name)
+            })?;
+            arg_tys.push(ty);
+        }
+
+        let return_ty = match &func_lit.return_ {
+            Some(type_def) => self.resolve_type(type_def)?,
+            None => TYPE_VOID_ID,
+        };
 
-        match (lhs_ty, binary_expr.op, rhs_ty) {
-            (TYPE_INT_ID, _, TYPE_INT_ID) => Ok(TYPE_INT_ID),
-            (TYPE_FLOAT_ID, _, TYPE_FLOAT_ID) => Ok(TYPE_FLOAT_ID),
-            (TYPE_STRING_ID, BinaryOp::Add, TYPE_STRING_ID) => Ok(TYPE_STRING_ID),
-            _ => typecheck_err(format!("{:?} {:?} {:?}", lhs_ty, binary_expr.op, rhs_ty)).into(),
+        self.enter_scope();
+        for (arg, ty) in func_lit.args.iter().zip(&arg_tys) {
+            self.declare_local(arg.name.text.clone(), *ty);
         }
+        let body_ty = self.check_block(&mut func_lit.body);
+        self.exit_scope();
+
+        if !self.is_assignable(return_ty, body_ty) {
+            return typecheck_err(format!(
+                "mismatched return types; expected {}, found {}",
+                self.display_type(return_ty),
+                self.display_type(body_ty)
+            ))
+            .into();
+        }
+
+        Ok(self.intern_type(Type::Func {
+            args: arg_tys,
+            arg_names: func_lit.args.iter().map(|arg| arg.name.text.clone()).collect(),
+            retunr_: return_ty,
+        }))
+    }
+
+    /// Type check a literal.
+    ///
+    /// Float literals too large to represent would silently become infinity,
+    /// so they're reported with their source spelling.
+    fn check_literal(&self, literal: &Literal) -> Result<TypeId> {
+        if let Literal::Num(Number::Float(value), raw) = literal {
+            if value.is_infinite() {
+                let text = raw.clone().unwrap_or_else(|| value.to_string());
+                return typecheck_err(format!("float literal out of range: {text}")).into();
+            }
+        }
+
+        Ok(literal.type_id())
+    }
+
+    /// Type check an array literal.
+    ///
+    /// All elements must be the same type.
+    fn check_array_lit(&mut self, array_lit: &mut ArrayLit) -> Result<TypeId> {
+        let mut element_ty: Option<TypeId> = None;
+
+        for element in &mut array_lit.elements {
+            let ty = self.check_expr(element)?;
+
+            match element_ty {
+                None => element_ty = Some(ty),
+                Some(expected_ty) if expected_ty != ty => {
+                    return typecheck_err(format!(
+                        "mismatched array element types; expected {}, found {}",
+                        self.display_type(expected_ty),
+                        self.display_type(ty)
+                    ))
+                    .into()
+                }
+                Some(_) => {}
+            }
+        }
+
+        match element_ty {
+            Some(element_ty) => Ok(self.intern_type(Type::Array(element_ty, array_lit.elements.len()))),
+            // TODO: Infer the element type of an empty array from its usage.
+            None => typecheck_err("cannot infer the element type of an empty array").into(),
+        }
+    }
+
+    fn check_binary_expr(&mut self, binary_expr: &mut BinaryExpr) -> Result<TypeId> {
+        if matches!(binary_expr.op, BinaryOp::Assign) {
+            return typecheck_err("assignment is only valid as a statement").into();
+        }
+
+        let lhs_ty = self.check_expr(&mut binary_expr.lhs)?;
+        let rhs_ty = self.check_expr(&mut binary_expr.rhs)?;
+
+        // The operand's error was already reported.
+        if lhs_ty == TYPE_ERROR_ID || rhs_ty == TYPE_ERROR_ID {
+            return Ok(TYPE_ERROR_ID);
+        }
+
+        let ty = if binary_expr.op.is_comparison() {
+            // Comparisons result in a boolean regardless of the operand types.
+            match (lhs_ty, binary_expr.op, rhs_ty) {
+                (TYPE_INT_ID, _, TYPE_INT_ID) | (TYPE_FLOAT_ID, _, TYPE_FLOAT_ID) => Some(TYPE_BOOL_ID),
+                (TYPE_STRING_ID, BinaryOp::Eq | BinaryOp::Ne, TYPE_STRING_ID)
+                | (TYPE_BOOL_ID, BinaryOp::Eq | BinaryOp::Ne, TYPE_BOOL_ID) => Some(TYPE_BOOL_ID),
+                _ => None,
+            }
+        } else {
+            // Arithmetic results in the operand type.
+            match (lhs_ty, binary_expr.op, rhs_ty) {
+                (TYPE_INT_ID, _, TYPE_INT_ID) => Some(TYPE_INT_ID),
+                (TYPE_FLOAT_ID, _, TYPE_FLOAT_ID) => Some(TYPE_FLOAT_ID),
+                (TYPE_STRING_ID, BinaryOp::Add, TYPE_STRING_ID) => Some(TYPE_STRING_ID),
+                _ => None,
+            }
+        };
+
+        match ty {
+            Some(ty) => {
+                // Supported operands are always the same type.
+                binary_expr.operand_ty = lhs_ty;
+                Ok(ty)
+            }
+            None => typecheck_err(format!(
+                "unsupported operand types for {:?}: {} and {}",
+                binary_expr.op,
+                self.display_type(lhs_ty),
+                self.display_type(rhs_ty)
+            ))
+            .into(),
+        }
+    }
+
+    /// Type check a short-circuiting `and` or `or`.
+    ///
+    /// Both operands must be booleans, and so is the result.
+    fn check_logical_expr(&mut self, logical_expr: &mut LogicalExpr) -> Result<TypeId> {
+        let lhs_ty = self.check_expr(&mut logical_expr.lhs)?;
+        let rhs_ty = self.check_expr(&mut logical_expr.rhs)?;
+
+        for ty in [lhs_ty, rhs_ty] {
+            if !self.is_assignable(TYPE_BOOL_ID, ty) {
+                return typecheck_err(format!(
+                    "unsupported operand types for {:?}: {} and {}",
+                    logical_expr.op,
+                    self.display_type(lhs_ty),
+                    self.display_type(rhs_ty)
+                ))
+                .into();
+            }
+        }
+
+        Ok(TYPE_BOOL_ID)
     }
 
     /// Declare a local variable in the current scope.
     fn declare_local(&mut self, name: String, ty: TypeId) {
-        match self.scope.locals.iter().position(|l| l.name == name) {
+        let local = Local { name, ty, reads: 0 };
+
+        match self.scope.locals.iter().position(|l| l.name == local.name) {
             // Existing local is shadowed.
             Some(index) => {
-                self.scope.locals[index] = Local { name, ty };
+                let shadowed = std::mem::replace(&mut self.scope.locals[index], local);
+                self.check_unused_local(&shadowed);
             }
             // New variable declared.
             None => {
-                self.scope.locals.push(Local { name, ty });
+                self.scope.locals.push(local);
             }
         }
     }
+
+    /// Find a local variable by name, searching from the innermost scope outwards,
+    /// and finally the builtins.
+    fn lookup_local(&mut self, name: &str) -> Result<&mut Local> {
+        std::iter::once(&mut self.scope)
+            .chain(self.scopes.iter_mut().rev())
+            .chain(std::iter::once(&mut self.builtins))
+            // Later declarations shadow earlier ones.
+            .flat_map(|scope| scope.locals.iter_mut().rev())
+            .find(|local| local.name == name)
+            .ok_or_else(|| typecheck_err(format!("unknown variable: {name}")))
+    }
+}
+
+/// Point the error at the given identifier, if it has a location in the source.
+fn spanned(err: Error, ident: &Ident) -> Error {
+    match &ident.span {
+        Some(span) => err.with_span(span.clone()),
+        None => err,
+    }
+}
+
+/// Match the arguments of a call to the callee's parameters,
+/// returning the argument expressions in positional order.
+///
+/// Positional arguments fill the leading parameters, and named arguments
+/// the rest. Every parameter must be supplied exactly once.
+fn order_call_args<'a>(param_names: &[String], call_expr: &'a mut CallExpr) -> Result<Vec<&'a mut Expr>> {
+    let too_many = call_expr.args.len() > param_names.len();
+    let too_few = call_expr.named_args.is_empty() && call_expr.args.len() < param_names.len();
+    if too_many || too_few {
+        return typecheck_err(format!(
+            "function takes {} arguments, but {} were given",
+            param_names.len(),
+            call_expr.args.len()
+        ))
+        .into();
+    }
+
+    let mut ordered: Vec<Option<&mut Expr>> = param_names.iter().map(|_| None).collect();
+    for (slot, arg) in ordered.iter_mut().zip(&mut call_expr.args) {
+        *slot = Some(arg);
+    }
+
+    for named_arg in &mut call_expr.named_args {
+        let name = &named_arg.name.text;
+        let index = param_names
+            .iter()
+            .position(|param_name| param_name == name)
+            .ok_or_else(|| spanned(typecheck_err(format!("unknown parameter: {name}")), &named_arg.name))?;
+
+        if ordered[index].is_some() {
+            return Err(spanned(
+                typecheck_err(format!("parameter supplied more than once: {name}")),
+                &named_arg.name,
+            ));
+        }
+        ordered[index] = Some(&mut named_arg.value);
+    }
+
+    ordered
+        .into_iter()
+        .zip(param_names)
+        .map(|(arg, name)| arg.ok_or_else(|| typecheck_err(format!("missing argument for parameter: {name}"))))
+        .collect()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn check_source(source: &str) -> Result<TypeId> {
+        let mut block = crate::parser::Parser::new(crate::lexer::Lexer::from_source(source))
+            .parse_module()
+            .map_err(|mut errors| errors.remove(0))?;
+        TypeChecker::new()
+            .check_module(&mut block)
+            .map_err(|mut errors| errors.remove(0))
+    }
+
+    #[test]
+    fn test_typecheck_comparison() {
+        assert_eq!(check_source("1 < 2").unwrap(), TYPE_BOOL_ID);
+        assert_eq!(check_source("1.0 >= 2.0").unwrap(), TYPE_BOOL_ID);
+        assert_eq!(check_source("1 + 2").unwrap(), TYPE_INT_ID);
+        assert_eq!(check_source("1.0 * 2.0").unwrap(), TYPE_FLOAT_ID);
+        assert_eq!(check_source("\"a\" == \"b\"").unwrap(), TYPE_BOOL_ID);
+        assert_eq!(check_source("true != false").unwrap(), TYPE_BOOL_ID);
+
+        // Strings and booleans are only compared for equality.
+        assert!(check_source("\"a\" < \"b\"").is_err());
+        assert!(check_source("true < false").is_err());
+        assert!(check_source("1 < 2.0").is_err());
+    }
+
+    #[test]
+    fn test_typecheck_func_lit() {
+        let mut block = crate::parser::Parser::new(crate::lexer::Lexer::from_source(
+            "let y = 2; let f = fn(x: Int) -> Int { x + y }; f",
+        ))
+        .parse_module()
+        .expect("parse module");
+        let mut typechecker = TypeChecker::new();
+        let ty = typechecker.check_module(&mut block).expect("check module");
+        assert_eq!(typechecker.display_type(ty), "fn(Int) -> Int");
+
+        assert_eq!(
+            check_source("let f = fn(x: Int) -> Int { x }; f(1)").unwrap(),
+            TYPE_INT_ID
+        );
+        assert_eq!(check_source("let f = fn() { }; f()").unwrap(), TYPE_VOID_ID);
+        // Arguments can be passed by the literal's parameter names.
+        let source = "let f = fn(x: Int, y: Float) -> Float { y }; f(y: 2.0, x: 1)";
+        assert_eq!(check_source(source).unwrap(), TYPE_FLOAT_ID);
+
+        let err = check_source("fn(x: Int) -> String { x }").unwrap_err();
+        assert_eq!(err.message, "mismatched return types; expected String, found Int");
+        let err = check_source("fn(x: Foo) { }").unwrap_err();
+        assert_eq!(err.message, "unknown type alias: Foo");
+    }
+
+    #[test]
+    fn test_typecheck_float_lit_range() {
+        assert_eq!(check_source("1.5e308").unwrap(), TYPE_FLOAT_ID);
+
+        let err = check_source("1e400").unwrap_err();
+        assert_eq!(err.message, "float literal out of range: 1e400");
+    }
+
+    #[test]
+    fn test_typecheck_multiple_errors() {
+        let source = "let x: Float = 1; let y = x + 2; let z = w; let a = 1 < 2.0; y";
+        let mut block = crate::parser::Parser::new(crate::lexer::Lexer::from_source(source))
+            .parse_module()
+            .expect("parse module");
+
+        let errors = TypeChecker::new().check_module(&mut block).unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+        // Using `x` after its failed declaration isn't reported again.
+        assert_eq!(
+            messages,
+            [
+                "mismatched types; expected Float, found Int",
+                "unknown variable: w",
+                "unsupported operand types for Lt: Int and Float",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_error() {
+        let source = "let x = 1;\nlet y: Float = x;\nlet z = w;";
+
+        let err = check_source(source).unwrap_err();
+        assert_eq!(err.message, "mismatched types; expected Float, found Int");
+        assert_eq!(
+            err.render_file("main.crow", source),
+            "main.crow:2:5: type error: mismatched types; expected Float, found Int\nlet y: Float = x;\n    ^"
+        );
+
+        let source = "let x = 1;\nlet y = x +\n\tw;";
+        let rendered = check_source(source).unwrap_err().render(source);
+        assert!(
+            rendered.starts_with("3:5: type error: unknown variable: w\n"),
+            "{rendered}"
+        );
+        assert!(rendered.ends_with("\n    ^"), "{rendered}");
+    }
+
+    #[test]
+    fn test_typecheck_nested_scope() {
+        // Locals of the enclosing scope are visible.
+        assert_eq!(
+            check_source("let x = 1; while x < 2 { let y = x; y } x").unwrap(),
+            TYPE_INT_ID
+        );
+
+        let err = check_source("let x = 1; while x < 2 { let y = x; y } y").unwrap_err();
+        assert_eq!(err.message, "unknown variable: y");
+
+        // Shadowing in an inner block doesn't replace the outer local.
+        let source = "let x = 1; while x < 2 { let x = 2.0; x } x";
+        assert_eq!(check_source(source).unwrap(), TYPE_INT_ID);
+    }
+
+    /// Declare `f: fn(a: Int, b: Float) -> String` in the checker.
+    fn declare_f(typechecker: &mut TypeChecker) {
+        let f = typechecker.intern_type(Type::Func {
+            args: vec![TYPE_INT_ID, TYPE_FLOAT_ID],
+            arg_names: vec!["a".to_string(), "b".to_string()],
+            retunr_: TYPE_STRING_ID,
+        });
+        typechecker.declare_local("f".to_string(), f);
+    }
+
+    /// Type check a single expression from source.
+    fn check_expr_source(typechecker: &mut TypeChecker, source: &str) -> Result<TypeId> {
+        let block = crate::parser::Parser::new(crate::lexer::Lexer::from_source(source))
+            .parse_module()
+            .map_err(|mut errors| errors.remove(0))?;
+        match block.stmts.into_iter().next() {
+            Some(Stmt::Tail(mut expr)) => typechecker.check_expr(&mut expr),
+            stmt => panic!("expected trailing expression: {stmt:?}"),
+        }
+    }
+
+    #[test]
+    fn test_typecheck_call() {
+        let mut typechecker = TypeChecker::new();
+        declare_f(&mut typechecker);
+        let mut check = |source: &str| check_expr_source(&mut typechecker, source);
+
+        assert_eq!(check("f(1, 2.0)").unwrap(), TYPE_STRING_ID);
+
+        let err = check("f(1, 2)").unwrap_err();
+        assert_eq!(err.message, "mismatched argument types; expected Float, found Int");
+        let err = check("f(1)").unwrap_err();
+        assert_eq!(err.message, "function takes 2 arguments, but 1 were given");
+        let err = check("f(1, 2.0, 3)").unwrap_err();
+        assert_eq!(err.message, "function takes 2 arguments, but 3 were given");
+
+        let err = check("1(2)").unwrap_err();
+        assert_eq!(err.message, "type Int is not callable");
+    }
+
+    #[test]
+    fn test_typecheck_named_args() {
+        let mut typechecker = TypeChecker::new();
+        declare_f(&mut typechecker);
+        let mut check = |source: &str| check_expr_source(&mut typechecker, source);
+
+        // Named arguments are matched to parameters by name, in any order.
+        assert_eq!(check("f(b: 2.0, a: 1)").unwrap(), TYPE_STRING_ID);
+        assert_eq!(check("f(1, b: 2.0)").unwrap(), TYPE_STRING_ID);
+
+        // Each argument is checked against the parameter it's passed to.
+        let err = check("f(b: 1, a: 2.0)").unwrap_err();
+        assert_eq!(err.message, "mismatched argument types; expected Int, found Float");
+
+        let err = check("f(a: 1)").unwrap_err();
+        assert_eq!(err.message, "missing argument for parameter: b");
+    }
+
+    #[test]
+    fn test_typecheck_duplicate_named_arg() {
+        let mut typechecker = TypeChecker::new();
+        declare_f(&mut typechecker);
+        let mut check = |source: &str| check_expr_source(&mut typechecker, source);
+
+        let err = check("f(a: 1, b: 2.0, a: 3)").unwrap_err();
+        assert_eq!(err.message, "parameter supplied more than once: a");
+
+        // Also when the parameter was already filled by position.
+        let err = check("f(1, a: 2)").unwrap_err();
+        assert_eq!(err.message, "parameter supplied more than once: a");
+    }
+
+    #[test]
+    fn test_typecheck_unknown_named_arg() {
+        let mut typechecker = TypeChecker::new();
+        declare_f(&mut typechecker);
+
+        let err = check_expr_source(&mut typechecker, "f(1, c: 2.0)").unwrap_err();
+        assert_eq!(err.message, "unknown parameter: c");
+        assert!(err.span.is_some());
+    }
+
+    #[test]
+    fn test_typecheck_expr_stmt() {
+        assert_eq!(check_source("System.Print(\"Hello, world!\");").unwrap(), TYPE_VOID_ID);
+        assert_eq!(check_source("let x = 1; x = 2;").unwrap(), TYPE_VOID_ID);
+
+        let err = check_source("System.Print(1);").unwrap_err();
+        assert_eq!(err.message, "mismatched argument types; expected String, found Int");
+        let err = check_source("let x = 1; x = 2.0;").unwrap_err();
+        assert_eq!(err.message, "mismatched types in assignment; expected Int, found Float");
+    }
+
+    #[test]
+    fn test_typecheck_assign() {
+        assert_eq!(check_source("let s = \"a\"; s = \"b\";").unwrap(), TYPE_VOID_ID);
+        assert_eq!(check_source("let b = true; b = false;").unwrap(), TYPE_VOID_ID);
+        assert_eq!(check_source("let x: Int? = nil; x = 1;").unwrap(), TYPE_VOID_ID);
+        assert_eq!(check_source("let x: Int? = 1; x = nil;").unwrap(), TYPE_VOID_ID);
+
+        let err = check_source("let b = true; b = 1;").unwrap_err();
+        assert_eq!(err.message, "mismatched types in assignment; expected Bool, found Int");
+
+        // An assignment has no value to use.
+        let mut expr = Expr::Binary(Box::new(BinaryExpr {
+            operand_ty: TypeId::default(),
+            op: BinaryOp::Assign,
+            lhs: Expr::Lit(Box::new(Literal::Num(Number::Int(1), None))),
+            rhs: Expr::Lit(Box::new(Literal::Num(Number::Int(2), None))),
+        }));
+        let err = TypeChecker::new().check_expr(&mut expr).unwrap_err();
+        assert_eq!(err.message, "assignment is only valid as a statement");
+    }
+
+    #[test]
+    fn test_typecheck_trailing_expr() {
+        assert_eq!(check_source("let x = 1; x").unwrap(), TYPE_INT_ID);
+        assert_eq!(
+            check_source("let x = 1; x * 2.0").unwrap_err().kind,
+            crate::errors::ErrorKind::Type
+        );
+        assert_eq!(check_source("let s = \"a\"; s").unwrap(), TYPE_STRING_ID);
+
+        // Empty block
+        assert_eq!(check_source("").unwrap(), TYPE_VOID_ID);
+
+        // Trailing declaration
+        assert_eq!(check_source("let x = 1;").unwrap(), TYPE_VOID_ID);
+
+        // Must agree with explicit returns
+        assert_eq!(check_source("let x = 1; return x; 2").unwrap(), TYPE_INT_ID);
+        assert!(check_source("let x = 1; return x; 2.0").is_err());
+
+        // The value of a loop body doesn't return from the enclosing block.
+        assert_eq!(check_source("let x = 1; while x < 2 { 2.0 } x").unwrap(), TYPE_INT_ID);
+    }
+
+    #[test]
+    fn test_typecheck_nested_returns() {
+        let source = "let x = 1; while x < 2 { while x < 3 { return x; } } return 2;";
+        assert_eq!(check_source(source).unwrap(), TYPE_INT_ID);
+        // The loop may not run, so the body can also run off its end.
+        let err = check_source("let x = 1; while x < 2 { return x; }").unwrap_err();
+        assert_eq!(err.message, "inconsistent return types; expected Int, found Void");
+
+        let err = check_source("let x = 1; while x < 2 { return 1.0; } x").unwrap_err();
+        assert_eq!(err.message, "inconsistent return types; expected Float, found Int");
+        let err = check_source("let x = 1; while x < 2 { return x; } return \"a\";").unwrap_err();
+        assert_eq!(err.message, "inconsistent return types; expected Int, found String");
+    }
+
     #[test]
     fn test_typecheck_block() {
-        let block = Block {
-            ty: TYPE_VOID_ID,
+        let mut block = Block {
             stmts: vec![
                 // Type inference case
                 Stmt::Local(Box::new(LocalDecl {
-                    name: Ident::from_string("x"),
+                    name: ident("x"),
                     ty: None,
                     rhs: Some(Expr::Binary(Box::new(BinaryExpr {
+                        operand_ty: TypeId::default(),
                         op: BinaryOp::Add,
-                        lhs: Expr::Lit(Box::new(Literal::Num(Number::Int(7)))),
-                        rhs: Expr::Lit(Box::new(Literal::Num(Number::Int(11)))),
+                        lhs: Expr::Lit(Box::new(Literal::Num(Number::Int(7), None))),
+                        rhs: Expr::Lit(Box::new(Literal::Num(Number::Int(11), None))),
                     }))),
                 })),
                 // Both type and initial value
                 Stmt::Local(Box::new(LocalDecl {
-                    name: Ident::from_string("x"),
-                    ty: Some(TypeDef::Alias(TypeName {
-                        text: Ident::from_string("Int"),
-                    })),
-                    rhs: Some(Expr::Lit(Box::new(Literal::Num(Number::Int(42))))),
+                    name: ident("x"),
+                    ty: Some(TypeDef::Alias(TypeName { text: ident("Int") })),
+                    rhs: Some(Expr::Lit(Box::new(Literal::Num(Number::Int(42), None)))),
                 })),
             ],
         };
 
         let mut typechecker = TypeChecker::new();
 
-        typechecker.check_block(&block).expect("typechecking block");
+        assert_eq!(typechecker.check_block(&mut block), TYPE_VOID_ID);
+        assert!(typechecker.errors.is_empty());
+    }
+
+    fn ident(text: &str) -> Ident {
+        Ident {
+            text: text.to_string(),
+            span: None,
+        }
+    }
+
+    fn int_lit(value: i64) -> Expr {
+        Expr::Lit(Box::new(Literal::Num(Number::Int(value), None)))
+    }
+
+    fn return_stmt(exprs: Vec<Expr>) -> Stmt {
+        Stmt::Return(Box::new(ReturnStmt {
+            value: Tuple {
+                items: exprs.into_iter().map(|expr| TupleItem { expr }).collect(),
+            },
+        }))
     }
 
     #[test]
-    fn test_typecheck_expression() {
+    fn test_typecheck_block_returns() {
+        let mut typechecker = TypeChecker::new();
+
+        // Consistent returns
+        let mut block = Block {
+            stmts: vec![return_stmt(vec![int_lit(1)]), return_stmt(vec![int_lit(2)])],
+        };
+        assert_eq!(typechecker.check_block(&mut block), TYPE_INT_ID);
+
+        // Multiple return values
+        let mut block = Block {
+            stmts: vec![
+                return_stmt(vec![int_lit(1), int_lit(2)]),
+                return_stmt(vec![int_lit(3), int_lit(4)]),
+            ],
+        };
+        let tuple_ty = typechecker.check_block(&mut block);
+        assert_eq!(
+            typechecker.types[tuple_ty.0 as usize],
+            Type::Tuple(vec![TYPE_INT_ID, TYPE_INT_ID])
+        );
+
+        // Inconsistent returns
+        let mut block = Block {
+            stmts: vec![
+                return_stmt(vec![int_lit(1)]),
+                return_stmt(vec![Expr::Lit(Box::new(Literal::Str("a".to_string())))]),
+            ],
+        };
+        let errors = typechecker.check_module(&mut block).unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "inconsistent return types; expected Int, found String"
+        );
+
+        // No returns
+        let mut block = Block { stmts: vec![] };
+        assert_eq!(typechecker.check_block(&mut block), TYPE_VOID_ID);
+    }
+
+    fn nil_lit() -> Expr {
+        Expr::Lit(Box::new(Literal::Nil))
+    }
+
+    fn local_decl(name: &str, ty: Option<TypeDef>, rhs: Option<Expr>) -> Stmt {
+        Stmt::Local(Box::new(LocalDecl {
+            name: ident(name),
+            ty,
+            rhs,
+        }))
+    }
+
+    fn alias(name: &str) -> TypeDef {
+        TypeDef::Alias(TypeName { text: ident(name) })
+    }
+
+    fn optional(type_def: TypeDef) -> TypeDef {
+        TypeDef::Optional(Box::new(type_def))
+    }
+
+    #[test]
+    fn test_typecheck_optional() {
+        let mut typechecker = TypeChecker::new();
+
+        // nil is assignable to an optional
+        let mut stmt = local_decl("x", Some(optional(alias("Int"))), Some(nil_lit()));
+        let optional_int = typechecker.check_stmt(&mut stmt).unwrap();
+        assert_eq!(typechecker.display_type(optional_int), "Int?");
+
+        // The inner type is assignable to an optional
+        let mut stmt = local_decl("x", Some(optional(alias("Int"))), Some(int_lit(1)));
+        assert_eq!(typechecker.check_stmt(&mut stmt).unwrap(), optional_int);
+
+        // nil is not assignable to a non-optional
+        let mut stmt = local_decl("x", Some(alias("Int")), Some(nil_lit()));
+        assert!(typechecker.check_stmt(&mut stmt).unwrap_err().is_typecheck_err());
+
+        // nil without a type can't be inferred
+        let mut stmt = local_decl("x", None, Some(nil_lit()));
+        assert!(typechecker.check_stmt(&mut stmt).unwrap_err().is_typecheck_err());
+
+        // Optional must be unwrapped before it can be used as the inner type
+        assert!(typechecker.is_assignable(optional_int, TYPE_INT_ID));
+        assert!(!typechecker.is_assignable(TYPE_INT_ID, optional_int));
+        assert!(!typechecker.is_assignable(TYPE_INT_ID, TYPE_NIL_ID));
+        assert!(!typechecker.is_assignable(optional_int, TYPE_FLOAT_ID));
+    }
+
+    #[test]
+    fn test_typecheck_type_decl() {
+        let mut typechecker = TypeChecker::new();
+
+        let mut stmt = Stmt::TypeDecl(Box::new(TypeDeclStmt {
+            name: ident("Id"),
+            rhs: optional(alias("Int")),
+        }));
+        assert_eq!(typechecker.check_stmt(&mut stmt).unwrap(), TYPE_VOID_ID);
+
+        let mut stmt = local_decl("x", Some(alias("Id")), Some(nil_lit()));
+        let ty = typechecker.check_stmt(&mut stmt).unwrap();
+        assert_eq!(typechecker.display_type(ty), "Int?");
+
+        let mut stmt = local_decl("x", Some(alias("Unknown")), Some(int_lit(1)));
+        assert!(typechecker.check_stmt(&mut stmt).unwrap_err().is_typecheck_err());
+    }
+
+    fn name_expr(name: &str) -> Expr {
+        Expr::Name(Box::new(NameAccessExpr { ident: ident(name) }))
+    }
+
+    #[test]
+    fn test_typecheck_name() {
+        let mut typechecker = TypeChecker::new();
+
+        // let x = 1; let y = x + 1;
+        let mut stmt = local_decl("x", None, Some(int_lit(1)));
+        assert_eq!(typechecker.check_stmt(&mut stmt).unwrap(), TYPE_INT_ID);
+        let rhs = Expr::Binary(Box::new(BinaryExpr {
+            operand_ty: TypeId::default(),
+            op: BinaryOp::Add,
+            lhs: name_expr("x"),
+            rhs: int_lit(1),
+        }));
+        let mut stmt = local_decl("y", None, Some(rhs));
+        assert_eq!(typechecker.check_stmt(&mut stmt).unwrap(), TYPE_INT_ID);
+        assert_eq!(typechecker.check_expr(&mut name_expr("y")).unwrap(), TYPE_INT_ID);
+
+        // The latest declaration shadows earlier ones
+        let mut stmt = local_decl("x", None, Some(Expr::Lit(Box::new(Literal::Str("a".to_string())))));
+        typechecker.check_stmt(&mut stmt).unwrap();
+        assert_eq!(typechecker.check_expr(&mut name_expr("x")).unwrap(), TYPE_STRING_ID);
+
+        let err = typechecker.check_expr(&mut name_expr("z")).unwrap_err();
+        assert!(err.is_typecheck_err());
+        assert_eq!(err.message, "unknown variable: z");
+    }
+
+    fn warnings(typechecker: &TypeChecker) -> Vec<String> {
+        typechecker
+            .warnings
+            .iter()
+            .map(|err| err.message.clone())
+            .collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn test_typecheck_unused_local() {
+        // Unused local
+        let mut block = Block {
+            stmts: vec![local_decl("x", None, Some(int_lit(1)))],
+        };
+        let mut typechecker = TypeChecker::new();
+        typechecker.check_module(&mut block).unwrap();
+        assert_eq!(warnings(&typechecker), vec!["unused variable: x"]);
+
+        // Used local
+        let mut block = Block {
+            stmts: vec![
+                local_decl("x", None, Some(int_lit(1))),
+                return_stmt(vec![name_expr("x")]),
+            ],
+        };
+        let mut typechecker = TypeChecker::new();
+        assert_eq!(typechecker.check_module(&mut block).unwrap(), TYPE_INT_ID);
+        assert!(typechecker.warnings.is_empty());
+
+        // Intentionally unused local
+        let mut block = Block {
+            stmts: vec![local_decl("_unused", None, Some(int_lit(1)))],
+        };
+        let mut typechecker = TypeChecker::new();
+        typechecker.check_module(&mut block).unwrap();
+        assert!(typechecker.warnings.is_empty());
+
+        // Local shadowed before it's read
+        let mut block = Block {
+            stmts: vec![
+                local_decl("x", None, Some(int_lit(1))),
+                local_decl("x", None, Some(int_lit(2))),
+                return_stmt(vec![name_expr("x")]),
+            ],
+        };
+        let mut typechecker = TypeChecker::new();
+        typechecker.check_module(&mut block).unwrap();
+        assert_eq!(warnings(&typechecker), vec!["unused variable: x"]);
+    }
+
+    fn array_lit(elements: Vec<Expr>) -> Expr {
+        Expr::ArrayLit(Box::new(ArrayLit { elements }))
+    }
+
+    #[test]
+    fn test_typecheck_array_lit() {
+        let mut typechecker = TypeChecker::new();
+
+        let ty = typechecker
+            .check_expr(&mut array_lit(vec![int_lit(1), int_lit(2)]))
+            .unwrap();
+        assert_eq!(typechecker.display_type(ty), "[Int; 2]");
+
+        let mut expr = array_lit(vec![int_lit(1), Expr::Lit(Box::new(Literal::Str("a".to_string())))]);
+        assert!(typechecker.check_expr(&mut expr).unwrap_err().is_typecheck_err());
+
+        assert!(typechecker.check_expr(&mut array_lit(vec![])).is_err());
+    }
+
+    fn index_expr(target: Expr, index: Expr) -> Expr {
+        Expr::Index(Box::new(IndexExpr { target, index }))
+    }
+
+    #[test]
+    fn test_typecheck_index() {
+        let mut typechecker = TypeChecker::new();
+
+        let mut expr = index_expr(array_lit(vec![int_lit(1)]), int_lit(0));
+        assert_eq!(typechecker.check_expr(&mut expr).unwrap(), TYPE_INT_ID);
+
+        let mut expr = index_expr(
+            array_lit(vec![int_lit(1)]),
+            Expr::Lit(Box::new(Literal::Num(Number::Float(0.0), None))),
+        );
+        assert!(typechecker.check_expr(&mut expr).unwrap_err().is_typecheck_err());
+
+        let mut expr = index_expr(int_lit(1), int_lit(0));
+        assert!(typechecker.check_expr(&mut expr).unwrap_err().is_typecheck_err());
+    }
+
+    fn array_type(element: TypeDef, size: usize) -> TypeDef {
+        TypeDef::Lit(TypeLit::Array {
+            element: Box::new(element),
+            size,
+        })
+    }
+
+    #[test]
+    fn test_resolve_array_type() {
+        let mut typechecker = TypeChecker::new();
+
+        let array = typechecker.resolve_type(&array_type(alias("Int"), 4)).unwrap();
+        assert_eq!(typechecker.display_type(array), "[Int; 4]");
+
+        // Equivalent annotations resolve to the same type
+        assert_eq!(typechecker.resolve_type(&array_type(alias("Int"), 4)).unwrap(), array);
+
+        // The size is part of the type
+        let other = typechecker.resolve_type(&array_type(alias("Int"), 3)).unwrap();
+        assert_ne!(other, array);
+
+        let nested = typechecker
+            .resolve_type(&array_type(optional(array_type(alias("Float"), 2)), 3))
+            .unwrap();
+        assert_eq!(typechecker.display_type(nested), "[[Float; 2]?; 3]");
+
+        // An array literal has the type of an array of its length
+        let mut stmt = local_decl(
+            "x",
+            Some(array_type(alias("Int"), 2)),
+            Some(array_lit(vec![int_lit(1), int_lit(2)])),
+        );
+        typechecker.check_stmt(&mut stmt).unwrap();
+        let mut stmt = local_decl(
+            "y",
+            Some(array_type(alias("Int"), 4)),
+            Some(array_lit(vec![int_lit(1)])),
+        );
+        assert!(typechecker.check_stmt(&mut stmt).unwrap_err().is_typecheck_err());
+
+        assert!(typechecker.resolve_type(&array_type(alias("Unknown"), 1)).is_err());
+    }
+
+    fn struct_type(fields: Vec<(&str, TypeDef)>) -> TypeDef {
+        TypeDef::Lit(TypeLit::Struct {
+            fields: fields
+                .into_iter()
+                .map(|(name, ty)| FieldDef {
+                    name: ident(name),
+                    ty: Box::new(ty),
+                })
+                .collect(),
+        })
+    }
+
+    fn dyn_array_type(element: TypeDef) -> TypeDef {
+        TypeDef::Lit(TypeLit::DynArray {
+            element: Box::new(element),
+        })
+    }
+
+    #[test]
+    fn test_resolve_dyn_array_type() {
+        let mut typechecker = TypeChecker::new();
+
+        let dyn_array = typechecker.resolve_type(&dyn_array_type(alias("Int"))).unwrap();
+        assert_eq!(typechecker.display_type(dyn_array), "[Int]");
+        assert_eq!(
+            typechecker.resolve_type(&dyn_array_type(alias("Int"))).unwrap(),
+            dyn_array
+        );
+
+        // Distinct from a fixed size array of the same element
+        let array = typechecker.resolve_type(&array_type(alias("Int"), 3)).unwrap();
+        assert_ne!(dyn_array, array);
+
+        let nested = typechecker
+            .resolve_type(&dyn_array_type(array_type(alias("String"), 2)))
+            .unwrap();
+        assert_eq!(typechecker.display_type(nested), "[[String; 2]]");
+
+        // Indexed by integers
+        let mut stmt = local_decl("xs", Some(dyn_array_type(alias("Float"))), None);
+        typechecker.check_stmt(&mut stmt).unwrap();
+        let mut expr = index_expr(name_expr("xs"), int_lit(0));
+        assert_eq!(typechecker.check_expr(&mut expr).unwrap(), TYPE_FLOAT_ID);
+    }
+
+    #[test]
+    fn test_resolve_table_type() {
+        let mut typechecker = TypeChecker::new();
+
+        let table_type = |key: &str, value: &str| {
+            TypeDef::Lit(TypeLit::Table {
+                key: Box::new(alias(key)),
+                value: Box::new(alias(value)),
+            })
+        };
+        let table = typechecker.resolve_type(&table_type("String", "Int")).unwrap();
+        assert_eq!(typechecker.display_type(table), "{String: Int}");
+        assert_eq!(typechecker.resolve_type(&table_type("String", "Int")).unwrap(), table);
+        assert_ne!(typechecker.resolve_type(&table_type("Int", "String")).unwrap(), table);
+
+        // Indexed by keys
+        assert_eq!(check_source("let t: {String: Float}; t[\"a\"]").unwrap(), TYPE_FLOAT_ID);
+        assert!(check_source("let t: {String: Float}; t[1]").is_err());
+    }
+
+    #[test]
+    fn test_resolve_struct_type() {
+        let mut typechecker = TypeChecker::new();
+
+        let point = typechecker
+            .resolve_type(&struct_type(vec![("x", alias("Int")), ("y", alias("Float"))]))
+            .unwrap();
+        match &typechecker.types[point.0 as usize] {
+            Type::Struct { fields } => {
+                assert_eq!(
+                    fields,
+                    &[("x".to_string(), TYPE_INT_ID), ("y".to_string(), TYPE_FLOAT_ID)]
+                );
+            }
+            ty => panic!("expected struct type: {ty:?}"),
+        }
+        assert_eq!(typechecker.display_type(point), "struct { x: Int, y: Float }");
+
+        // Equivalent annotations resolve to the same type
+        let other = typechecker
+            .resolve_type(&struct_type(vec![("x", alias("Int")), ("y", alias("Float"))]))
+            .unwrap();
+        assert_eq!(other, point);
+
+        let err = typechecker
+            .resolve_type(&struct_type(vec![("x", alias("Int")), ("x", alias("Float"))]))
+            .unwrap_err();
+        assert_eq!(err.message, "duplicate field in struct type: x");
+
+        assert!(typechecker
+            .resolve_type(&struct_type(vec![("x", alias("Unknown"))]))
+            .is_err());
+    }
+
+    fn field_expr(target: Expr, field: &str) -> Expr {
+        Expr::Field(Box::new(FieldExpr {
+            target,
+            field: ident(field),
+        }))
+    }
+
+    #[test]
+    fn test_typecheck_field() {
+        let mut typechecker = TypeChecker::new();
+
+        // let line: struct { start: struct { x: Int, y: Float }, name: String };
+        let point = struct_type(vec![("x", alias("Int")), ("y", alias("Float"))]);
+        let line = struct_type(vec![("start", point), ("name", alias("String"))]);
+        typechecker
+            .check_stmt(&mut local_decl("line", Some(line), None))
+            .unwrap();
+
+        let mut expr = field_expr(name_expr("line"), "name");
+        assert_eq!(typechecker.check_expr(&mut expr).unwrap(), TYPE_STRING_ID);
+
+        // Nested access
+        let mut expr = field_expr(field_expr(name_expr("line"), "start"), "y");
+        assert_eq!(typechecker.check_expr(&mut expr).unwrap(), TYPE_FLOAT_ID);
+
+        let mut expr = field_expr(field_expr(name_expr("line"), "start"), "z");
+        let err = typechecker.check_expr(&mut expr).unwrap_err();
+        assert_eq!(err.message, "no field 'z' on type struct { x: Int, y: Float }");
+
+        // Field on a non-struct type
+        let mut expr = field_expr(field_expr(field_expr(name_expr("line"), "start"), "x"), "x");
+        let err = typechecker.check_expr(&mut expr).unwrap_err();
+        assert_eq!(err.message, "type Int has no fields; cannot access 'x'");
+    }
+
+    #[test]
+    fn test_same_type_across_checkers() {
+        let mut first = TypeChecker::new();
+        let mut second = TypeChecker::new();
+
+        // Intern types in a different order, so the ids differ.
+        let first_table = first.intern_type(Type::Table(TYPE_STRING_ID, TYPE_INT_ID));
+        let first_array = first.resolve_type(&array_type(optional(alias("Int")), 3)).unwrap();
+        let second_array = second.resolve_type(&array_type(optional(alias("Int")), 3)).unwrap();
+        let second_table = second.intern_type(Type::Table(TYPE_STRING_ID, TYPE_INT_ID));
+        assert_ne!(first_array, second_array);
+
+        // Rendered types are structural, so they compare across checkers.
+        assert_eq!(first.display_type(first_array), second.display_type(second_array));
+        assert_eq!(first.display_type(first_table), second.display_type(second_table));
+        assert_eq!(first.display_type(TYPE_INT_ID), second.display_type(TYPE_INT_ID));
+
+        assert_ne!(first.display_type(first_array), second.display_type(second_table));
+        let second_other = second.resolve_type(&array_type(optional(alias("Int")), 4)).unwrap();
+        assert_ne!(first.display_type(first_array), second.display_type(second_other));
+    }
+
+    #[test]
+    fn test_display_type() {
+        let mut typechecker = TypeChecker::new();
+
+        assert_eq!(typechecker.display_type(TYPE_INT_ID), "Int");
+
+        let array = typechecker.intern_type(Type::Array(TYPE_INT_ID, 3));
+        assert_eq!(typechecker.display_type(array), "[Int; 3]");
+
+        let nested = typechecker.intern_type(Type::Array(array, 2));
+        assert_eq!(typechecker.display_type(nested), "[[Int; 3]; 2]");
+
+        let table = typechecker.intern_type(Type::Table(TYPE_STRING_ID, TYPE_INT_ID));
+        assert_eq!(typechecker.display_type(table), "{String: Int}");
+
+        let tuple = typechecker.intern_type(Type::Tuple(vec![TYPE_INT_ID, TYPE_FLOAT_ID]));
+        assert_eq!(typechecker.display_type(tuple), "(Int, Float)");
+
+        let func = typechecker.intern_type(Type::Func {
+            args: vec![TYPE_INT_ID],
+            arg_names: vec!["a".to_string()],
+            retunr_: TYPE_FLOAT_ID,
+        });
+        assert_eq!(typechecker.display_type(func), "fn(Int) -> Float");
+
+        let func = typechecker.intern_type(Type::Func {
+            args: vec![table, array],
+            arg_names: vec!["a".to_string(), "b".to_string()],
+            retunr_: TYPE_VOID_ID,
+        });
+        assert_eq!(typechecker.display_type(func), "fn({String: Int}, [Int; 3])");
+    }
+
+    #[test]
+    fn test_typecheck_bool() {
+        let mut typechecker = TypeChecker::new();
+
+        let mut bool_lit = Expr::Lit(Box::new(Literal::Bool(true)));
+        assert_eq!(typechecker.check_expr(&mut bool_lit).unwrap(), TYPE_BOOL_ID);
+
+        let mut stmt = local_decl(
+            "b",
+            Some(alias("Bool")),
+            Some(Expr::Lit(Box::new(Literal::Bool(false)))),
+        );
+        assert_eq!(typechecker.check_stmt(&mut stmt).unwrap(), TYPE_BOOL_ID);
+        assert_eq!(typechecker.display_type(TYPE_BOOL_ID), "Bool");
+
+        for op in [
+            BinaryOp::Lt,
+            BinaryOp::Le,
+            BinaryOp::Gt,
+            BinaryOp::Ge,
+            BinaryOp::Eq,
+            BinaryOp::Ne,
+        ] {
+            let mut expr = Expr::Binary(Box::new(BinaryExpr {
+                operand_ty: TypeId::default(),
+                op,
+                lhs: int_lit(1),
+                rhs: int_lit(2),
+            }));
+            assert_eq!(typechecker.check_expr(&mut expr).unwrap(), TYPE_BOOL_ID, "{op:?}");
+
+            let mut expr = Expr::Binary(Box::new(BinaryExpr {
+                operand_ty: TypeId::default(),
+                op,
+                lhs: Expr::Lit(Box::new(Literal::Num(Number::Float(1.0), None))),
+                rhs: Expr::Lit(Box::new(Literal::Num(Number::Float(2.0), None))),
+            }));
+            assert_eq!(typechecker.check_expr(&mut expr).unwrap(), TYPE_BOOL_ID, "{op:?}");
+        }
+
+        // A comparison can't be assigned to an Int
         let expr = Expr::Binary(Box::new(BinaryExpr {
+            operand_ty: TypeId::default(),
+            op: BinaryOp::Lt,
+            lhs: int_lit(1),
+            rhs: int_lit(2),
+        }));
+        let mut stmt = local_decl("x", Some(alias("Int")), Some(expr));
+        assert!(typechecker.check_stmt(&mut stmt).unwrap_err().is_typecheck_err());
+    }
+
+    #[test]
+    fn test_typecheck_logical() {
+        assert_eq!(check_source("1 < 2 and true").unwrap(), TYPE_BOOL_ID);
+        assert_eq!(check_source("false or 1.0 == 2.0 and true").unwrap(), TYPE_BOOL_ID);
+
+        let err = check_source("1 and true").unwrap_err();
+        assert_eq!(err.message, "unsupported operand types for And: Int and Bool");
+        let err = check_source("true or \"a\"").unwrap_err();
+        assert_eq!(err.message, "unsupported operand types for Or: Bool and String");
+    }
+
+    #[test]
+    fn test_typecheck_expression() {
+        let mut expr = Expr::Binary(Box::new(BinaryExpr {
+            operand_ty: TypeId::default(),
             op: BinaryOp::Add,
-            lhs: Expr::Lit(Box::new(Literal::Num(Number::Int(1)))),
-            rhs: Expr::Lit(Box::new(Literal::Num(Number::Float(2.0)))),
+            lhs: Expr::Lit(Box::new(Literal::Num(Number::Int(1), None))),
+            rhs: Expr::Lit(Box::new(Literal::Num(Number::Float(2.0), None))),
         }));
 
         let mut typechecker = TypeChecker::new();
 
-        assert!(typechecker.check_expr(&expr).is_err());
+        let err = typechecker.check_expr(&mut expr).unwrap_err();
+        assert!(err.message.contains("Int and Float"), "{}", err.message);
+
+        let mut stmt = local_decl(
+            "x",
+            Some(alias("Int")),
+            Some(Expr::Lit(Box::new(Literal::Num(Number::Float(1.0), None)))),
+        );
+        let err = typechecker.check_stmt(&mut stmt).unwrap_err();
+        assert_eq!(err.message, "mismatched types; expected Int, found Float");
+
+        assert_eq!(typechecker.display_type(TYPE_FLOAT_ID), "Float");
     }
 }
@@ -6,7 +6,15 @@ use std::{
 
 /// Initialiase the table of types, with the built in types in their proper positions.
 pub fn init_type_table() -> Vec<Type> {
-    vec![Type::Void, Type::Int, Type::Float, Type::String]
+    vec![
+        Type::Void,
+        Type::Int,
+        Type::Float,
+        Type::String,
+        Type::Nil,
+        Type::Bool,
+        Type::Error,
+    ]
 }
 
 pub fn init_type_aliases() -> HashMap<String, TypeId> {
@@ -15,9 +23,18 @@ pub fn init_type_aliases() -> HashMap<String, TypeId> {
     aliases.insert("Int".to_string(), TYPE_INT_ID);
     aliases.insert("Float".to_string(), TYPE_FLOAT_ID);
     aliases.insert("String".to_string(), TYPE_STRING_ID);
+    aliases.insert("Bool".to_string(), TYPE_BOOL_ID);
     aliases
 }
 
+/// Index of a type in a type checker's type table.
+///
+/// The built-in types have fixed identifiers, but other types are assigned
+/// identifiers in the order a [`crate::typechecker::TypeChecker`] first
+/// encounters them. The same type may have a different identifier in
+/// another checker, so identifiers must not be serialized or compared
+/// across checker instances. Compare the types rendered by
+/// [`crate::typechecker::TypeChecker::display_type`] instead.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TypeId(pub(crate) u32);
 
@@ -33,6 +50,9 @@ pub const TYPE_VOID_ID: TypeId = TypeId(0);
 pub const TYPE_INT_ID: TypeId = TypeId(1);
 pub const TYPE_FLOAT_ID: TypeId = TypeId(2);
 pub const TYPE_STRING_ID: TypeId = TypeId(3);
+pub const TYPE_NIL_ID: TypeId = TypeId(4);
+pub const TYPE_BOOL_ID: TypeId = TypeId(5);
+pub const TYPE_ERROR_ID: TypeId = TypeId(6);
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Type {
@@ -40,23 +60,59 @@ pub enum Type {
     ///
     /// It is a type error to assign [`Type::Void`] to a variable.
     /// A block or function that returns void must have its value discarded.
+    /// At runtime it is represented by [`Value::Nil`](crate::value::Value::Nil).
     Void,
     Int,
     Float,
     String,
+    /// Type of the `nil` literal.
+    ///
+    /// Only assignable to optional types.
+    Nil,
+    /// Boolean `true` or `false`.
+    Bool,
+    /// Stand-in for an expression that failed to type check.
+    ///
+    /// It's compatible with every type, so a single mistake
+    /// isn't reported again wherever its result is used.
+    Error,
+    /// A value of the inner type, or `nil`.
+    ///
+    /// ```text
+    /// Int?
+    /// ```
+    Optional(TypeId),
     /// List of types for when multiple values are returned from a block,
     /// or function.
     Tuple(Vec<TypeId>),
-    Array(TypeId),
-    /// Hash table.
+    /// Fixed size array of the element type.
+    ///
+    /// ```text
+    /// [Int; 3]
+    /// ```
+    Array(TypeId, usize),
+    /// Growable array of the element type.
+    ///
+    /// ```text
+    /// [Int]
+    /// ```
+    DynArray(TypeId),
+    /// Hash table from the key type to the value type.
+    ///
+    /// ```text
+    /// {String: Int}
+    /// ```
     Table(TypeId, TypeId),
     /// Type of both the [`crate::object::Closure`] value and [`crate::object::Func`]` prototype.
     Func {
         args: Vec<TypeId>,
+        /// Parameter names, in the same order as `args`, for passing arguments by name.
+        arg_names: Vec<String>,
         retunr_: TypeId,
     },
+    /// Structure with named fields, in declaration order.
     Struct {
-        fields: Vec<()>,
+        fields: Vec<(String, TypeId)>,
     },
 }
 
@@ -67,8 +123,13 @@ impl fmt::Display for Type {
             Type::Int => "Int",
             Type::Float => "Float",
             Type::String => "String",
+            Type::Nil => "Nil",
+            Type::Bool => "Bool",
+            Type::Error => "{error}",
+            Type::Optional(_) => "Optional",
             Type::Tuple(_) => "Tuple",
-            Type::Array(_) => "Array",
+            Type::Array(_, _) => "Array",
+            Type::DynArray(_) => "DynArray",
             Type::Table(_, _) => "Table",
             Type::Func { .. } => "Func",
             Type::Struct { .. } => "Struct",
@@ -89,5 +150,8 @@ mod test {
         assert_eq!(types[TYPE_INT_ID.0 as usize], Type::Int);
         assert_eq!(types[TYPE_FLOAT_ID.0 as usize], Type::Float);
         assert_eq!(types[TYPE_STRING_ID.0 as usize], Type::String);
+        assert_eq!(types[TYPE_NIL_ID.0 as usize], Type::Nil);
+        assert_eq!(types[TYPE_BOOL_ID.0 as usize], Type::Bool);
+        assert_eq!(types[TYPE_ERROR_ID.0 as usize], Type::Error);
     }
 }
@@ -2,12 +2,22 @@ use std::fmt::{self, Formatter};
 use std::ptr::NonNull;
 use std::rc::Rc;
 
+use crate::array::Array;
+use crate::errors::{runtime_err, Error, Result};
 use crate::handle::Handle;
+use crate::limits::MAX_SERIALIZE_DEPTH;
 use crate::object::*;
+use crate::vm::Coroutine;
 
 /// Value is a typed, safe value.
 #[derive(Debug, Clone)]
 pub enum Value {
+    /// Absence of a value.
+    ///
+    /// Produced by functions without a return value, which the type checker
+    /// types as `Void`, and by reads of undefined globals or missing table keys.
+    Nil,
+    Bool(bool),
     Int(i64),
     UInt(u64),
     Float(f64),
@@ -16,7 +26,18 @@ pub enum Value {
 
 impl Value {
     pub fn from_bool(val: bool) -> Self {
-        Value::Int(if val { 1 } else { 0 })
+        Value::Bool(val)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Nil)
     }
 
     pub fn as_int(&self) -> Option<i64> {
@@ -44,7 +65,7 @@ impl Value {
         }
     }
 
-    pub fn to_func(self) -> Option<Rc<Func>> {
+    pub fn to_func(&self) -> Option<Rc<Func>> {
         match self {
             Value::Object(Object::Func(func_rc)) => Some(func_rc.clone()),
             _ => None,
@@ -58,6 +79,13 @@ impl Value {
         }
     }
 
+    pub fn as_array(&self) -> Option<&Handle<Array>> {
+        match self {
+            Value::Object(Object::Array(ref array_handle)) => Some(array_handle),
+            _ => None,
+        }
+    }
+
     pub fn as_string(&self) -> Option<&Rc<CrowStr>> {
         match self {
             Value::Object(Object::String(ref table_handle)) => Some(table_handle),
@@ -65,6 +93,17 @@ impl Value {
         }
     }
 
+    pub fn from_native(native: Native) -> Self {
+        Value::Object(Object::Native(Rc::new(native)))
+    }
+
+    pub fn as_native(&self) -> Option<&Rc<Native>> {
+        match self {
+            Value::Object(Object::Native(ref rc)) => Some(rc),
+            _ => None,
+        }
+    }
+
     pub fn from_closure(closure: Rc<Closure>) -> Self {
         Value::Object(Object::Closure(closure))
     }
@@ -75,14 +114,363 @@ impl Value {
             _ => None,
         }
     }
+
+    pub fn from_coroutine(coroutine: Coroutine) -> Self {
+        Value::Object(Object::Coroutine(Handle::new(coroutine)))
+    }
+
+    pub fn as_coroutine(&self) -> Option<&Handle<Coroutine>> {
+        match self {
+            Value::Object(Object::Coroutine(ref handle)) => Some(handle),
+            _ => None,
+        }
+    }
+}
+
+/// Format the value as a script would print it.
+///
+/// Strings are written without quotes, and floats always include a
+/// fractional part so they're distinguishable from integers.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(val) => write!(f, "{val}"),
+            Value::Int(int) => write!(f, "{int}"),
+            Value::UInt(uint) => write!(f, "{uint}"),
+            Value::Float(float) => write!(f, "{float:?}"),
+            Value::Object(object) => match object {
+                Object::String(string) => write!(f, "{}", string.as_str()),
+                Object::Closure(rc) => write!(f, "<closure {:?}>", Rc::as_ptr(rc)),
+                Object::Func(rc) => write!(f, "<func {:?}>", Rc::as_ptr(rc)),
+                Object::Table(handle) => write!(f, "<table {:?}>", handle.as_ptr()),
+                Object::Array(handle) => write!(f, "<array {:?}>", handle.as_ptr()),
+                Object::Native(rc) => write!(f, "<native {:?}>", Rc::as_ptr(rc)),
+                Object::NativeFn(native_fn) => write!(f, "<native fn {:?}>", *native_fn as *const ()),
+                Object::Coroutine(handle) => write!(f, "<coroutine {:?}>", handle.as_ptr()),
+            },
+        }
+    }
+}
+
+/// Values of different kinds are never equal, so `1` and `1.0` differ.
+///
+/// Floats follow IEEE 754 like [`crate::op::Op::Float_Eq`], so `NaN` is not
+/// equal to itself, and `Value` is therefore not [`Eq`]. Strings are compared
+/// by content, and every other object by identity.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::UInt(a), Value::UInt(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Object(a), Value::Object(b)) => match (a, b) {
+                (Object::String(a), Object::String(b)) => a.as_str() == b.as_str(),
+                (Object::Closure(a), Object::Closure(b)) => Rc::ptr_eq(a, b),
+                (Object::Func(a), Object::Func(b)) => Rc::ptr_eq(a, b),
+                (Object::Table(a), Object::Table(b)) => a.ptr_eq(b),
+                (Object::Array(a), Object::Array(b)) => a.ptr_eq(b),
+                (Object::Native(a), Object::Native(b)) => Rc::ptr_eq(a, b),
+                (Object::NativeFn(a), Object::NativeFn(b)) => std::ptr::fn_addr_eq(*a, *b),
+                (Object::Coroutine(a), Object::Coroutine(b)) => a.ptr_eq(b),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Hashable form of a [`Value`], used as a table key.
+///
+/// Float keys are canonicalised, so `-0.0` and `0.0` are the same key,
+/// and all `NaN` values are the same key. This diverges from the
+/// [`crate::op::Op::Float_Eq`] operator, which follows IEEE 754 where
+/// `NaN` is not equal to itself. Without canonicalisation a value
+/// inserted with a `NaN` key could never be retrieved.
+///
+/// Arithmetic is not affected. A `-0.0` value stored in a table
+/// remains `-0.0`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ValueKey {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    /// Canonical bit pattern of a float.
+    Float(u64),
+    String(String),
+}
+
+impl ValueKey {
+    /// Create a key from the given value.
+    ///
+    /// Returns `None` if the value cannot be used as a key.
+    /// Like a missing entry, `nil` is not a key.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Nil => None,
+            Value::Bool(val) => Some(ValueKey::Bool(*val)),
+            Value::Int(val) => Some(ValueKey::Int(*val)),
+            Value::UInt(val) => Some(ValueKey::UInt(*val)),
+            Value::Float(val) => Some(ValueKey::Float(canonical_float_bits(*val))),
+            Value::Object(Object::String(string)) => Some(ValueKey::String(string.to_string())),
+            Value::Object(_) => None,
+        }
+    }
+}
+
+/// Bit pattern of the given float, with all zeroes and all `NaN`s collapsed.
+fn canonical_float_bits(value: f64) -> u64 {
+    if value.is_nan() {
+        f64::NAN.to_bits()
+    } else if value == 0.0 {
+        // Both -0.0 and 0.0
+        0.0_f64.to_bits()
+    } else {
+        value.to_bits()
+    }
+}
+
+// ============================================================================ //
+// Serialization                                                                //
+// ============================================================================ //
+
+/// Type tags of the serialized value format.
+///
+/// Each value is a tag byte followed by its payload. Nil has no payload,
+/// and a boolean is a single `0` or `1` byte. Numbers are 8 bytes
+/// little-endian. Strings are a `u32` byte length followed by UTF-8 bytes.
+/// Tables are a `u32` entry count followed by alternating key and value,
+/// and arrays are a `u32` length followed by their elements.
+mod tag {
+    pub const INT: u8 = 0;
+    pub const UINT: u8 = 1;
+    pub const FLOAT: u8 = 2;
+    pub const STRING: u8 = 3;
+    pub const TABLE: u8 = 4;
+    pub const NIL: u8 = 5;
+    pub const BOOL: u8 = 6;
+    pub const ARRAY: u8 = 7;
+}
+
+impl Value {
+    /// Serialize the value into a self-describing byte format.
+    ///
+    /// Functions and closures can't be serialized. Tables and arrays are
+    /// serialized by content, so one shared in several places is duplicated,
+    /// and one that contains itself is an error. They can be nested at most
+    /// [`MAX_SERIALIZE_DEPTH`] deep.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        write_value(&mut buf, self, &mut Vec::new())?;
+        Ok(buf)
+    }
+
+    /// Deserialize a value from bytes created by [`Value::to_bytes()`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Value> {
+        let mut cursor = bytes;
+        let value = read_value(&mut cursor, 0)?;
+
+        if !cursor.is_empty() {
+            return runtime_err("trailing bytes after serialized value").into();
+        }
+
+        Ok(value)
+    }
+}
+
+/// Write the value to the buffer.
+///
+/// `containers` holds the tables and arrays currently being serialized, to detect cycles.
+fn write_value(buf: &mut Vec<u8>, value: &Value, containers: &mut Vec<*const ()>) -> Result<()> {
+    match value {
+        Value::Nil => buf.push(tag::NIL),
+        Value::Bool(val) => {
+            buf.push(tag::BOOL);
+            buf.push(*val as u8);
+        }
+        Value::Int(val) => {
+            buf.push(tag::INT);
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        Value::UInt(val) => {
+            buf.push(tag::UINT);
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        Value::Float(val) => {
+            buf.push(tag::FLOAT);
+            buf.extend_from_slice(&val.to_bits().to_le_bytes());
+        }
+        Value::Object(Object::String(string)) => write_string(buf, string.as_str())?,
+        Value::Object(Object::Table(table)) => {
+            enter_container(containers, table.as_ptr().cast(), "a table")?;
+
+            let table = table.borrow();
+            buf.push(tag::TABLE);
+            write_len(buf, table.len())?;
+            for (key, value) in table.iter() {
+                write_key(buf, key)?;
+                write_value(buf, value, containers)?;
+            }
+
+            containers.pop();
+        }
+        Value::Object(Object::Array(array)) => {
+            enter_container(containers, array.as_ptr().cast(), "an array")?;
+
+            let array = array.borrow();
+            buf.push(tag::ARRAY);
+            write_len(buf, array.len())?;
+            for value in array.iter() {
+                write_value(buf, value, containers)?;
+            }
+
+            containers.pop();
+        }
+        Value::Object(Object::Func(_)) => return runtime_err("cannot serialize a function").into(),
+        Value::Object(Object::Closure(_)) => return runtime_err("cannot serialize a closure").into(),
+        Value::Object(Object::Native(_)) => return runtime_err("cannot serialize a native object").into(),
+        Value::Object(Object::NativeFn(_)) => return runtime_err("cannot serialize a native function").into(),
+        Value::Object(Object::Coroutine(_)) => return runtime_err("cannot serialize a coroutine").into(),
+    }
+
+    Ok(())
+}
+
+/// Push a table or array onto the containers being serialized.
+fn enter_container(containers: &mut Vec<*const ()>, ptr: *const (), kind: &str) -> Result<()> {
+    if containers.contains(&ptr) {
+        return runtime_err(format!("cannot serialize {kind} that contains itself")).into();
+    }
+    if containers.len() >= MAX_SERIALIZE_DEPTH {
+        return runtime_err(format!(
+            "cannot serialize values nested deeper than {MAX_SERIALIZE_DEPTH}"
+        ))
+        .into();
+    }
+    containers.push(ptr);
+    Ok(())
+}
+
+/// Keys are written in the same format as their values.
+fn write_key(buf: &mut Vec<u8>, key: &ValueKey) -> Result<()> {
+    match key {
+        ValueKey::Bool(val) => {
+            buf.push(tag::BOOL);
+            buf.push(*val as u8);
+        }
+        ValueKey::Int(val) => {
+            buf.push(tag::INT);
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        ValueKey::UInt(val) => {
+            buf.push(tag::UINT);
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        ValueKey::Float(bits) => {
+            buf.push(tag::FLOAT);
+            buf.extend_from_slice(&bits.to_le_bytes());
+        }
+        ValueKey::String(string) => write_string(buf, string)?,
+    }
+
+    Ok(())
+}
+
+fn write_string(buf: &mut Vec<u8>, string: &str) -> Result<()> {
+    buf.push(tag::STRING);
+    write_len(buf, string.len())?;
+    buf.extend_from_slice(string.as_bytes());
+    Ok(())
+}
+
+pub(crate) fn write_len(buf: &mut Vec<u8>, len: usize) -> Result<()> {
+    let len = u32::try_from(len).map_err(|_| runtime_err("value too large to serialize"))?;
+    buf.extend_from_slice(&len.to_le_bytes());
+    Ok(())
+}
+
+/// Read a value from the cursor.
+///
+/// `depth` is the number of tables and arrays the value is nested in.
+fn read_value(cursor: &mut &[u8], depth: usize) -> Result<Value> {
+    let [tag] = read_bytes::<1>(cursor)?;
+
+    if matches!(tag, tag::TABLE | tag::ARRAY) && depth >= MAX_SERIALIZE_DEPTH {
+        return runtime_err(format!("serialized value is nested deeper than {MAX_SERIALIZE_DEPTH}")).into();
+    }
+
+    match tag {
+        tag::NIL => Ok(Value::Nil),
+        tag::BOOL => match read_bytes::<1>(cursor)? {
+            [0] => Ok(Value::Bool(false)),
+            [1] => Ok(Value::Bool(true)),
+            [byte] => runtime_err(format!("invalid serialized boolean: {byte}")).into(),
+        },
+        tag::INT => Ok(Value::Int(i64::from_le_bytes(read_bytes(cursor)?))),
+        tag::UINT => Ok(Value::UInt(u64::from_le_bytes(read_bytes(cursor)?))),
+        tag::FLOAT => Ok(Value::Float(f64::from_bits(u64::from_le_bytes(read_bytes(cursor)?)))),
+        tag::STRING => {
+            let len = read_len(cursor)?;
+            if cursor.len() < len {
+                return Err(err_truncated());
+            }
+            let (bytes, rest) = cursor.split_at(len);
+            *cursor = rest;
+            let string = std::str::from_utf8(bytes).map_err(|_| runtime_err("serialized string is not valid UTF-8"))?;
+            Ok(Value::Object(Object::String(Rc::new(CrowStr::new(string)))))
+        }
+        tag::TABLE => {
+            let len = read_len(cursor)?;
+            let mut table = Table::new();
+            for _ in 0..len {
+                let key = read_value(cursor, depth + 1)?;
+                let key =
+                    ValueKey::from_value(&key).ok_or_else(|| runtime_err("serialized table key is not a valid key"))?;
+                let value = read_value(cursor, depth + 1)?;
+                table.insert(key, value);
+            }
+            Ok(Value::Object(Object::Table(Handle::new(table))))
+        }
+        tag::ARRAY => {
+            let len = read_len(cursor)?;
+            // The length isn't trusted for the allocation, since every element takes at least a byte.
+            let mut array = Array::with_capacity(len.min(cursor.len()));
+            for _ in 0..len {
+                array.push(read_value(cursor, depth + 1)?);
+            }
+            Ok(Value::Object(Object::Array(Handle::new(array))))
+        }
+        _ => runtime_err(format!("unknown serialized value tag: {tag}")).into(),
+    }
+}
+
+pub(crate) fn read_len(cursor: &mut &[u8]) -> Result<usize> {
+    Ok(u32::from_le_bytes(read_bytes(cursor)?) as usize)
+}
+
+pub(crate) fn read_bytes<const N: usize>(cursor: &mut &[u8]) -> Result<[u8; N]> {
+    if cursor.len() < N {
+        return Err(err_truncated());
+    }
+    let (bytes, rest) = cursor.split_at(N);
+    *cursor = rest;
+    Ok(bytes.try_into().unwrap())
+}
+
+pub(crate) fn err_truncated() -> Error {
+    runtime_err("serialized value is truncated")
 }
 
 /// TODO: Unsafe memory management.
 #[derive(Clone, Copy)]
+#[allow(dead_code)]
 pub struct ObjPtr(NonNull<()>);
 
 /// Slot is an untyped, unsafe value.
 #[derive(Clone, Copy)]
+#[allow(dead_code)]
 pub union Slot {
     pub(crate) int: i64,
     pub(crate) uint: u64,
@@ -90,6 +478,7 @@ pub union Slot {
     pub(crate) object: ObjPtr,
 }
 
+#[allow(dead_code)]
 impl Slot {
     /// A slot that's considered empty.
     pub(crate) const fn empty() -> Self {
@@ -120,9 +509,300 @@ mod test {
     use crate::errors::Result;
     use crate::{
         object::Constants,
-        op::{Arg24, Op},
+        op::{shorthand, Arg24, Op},
+        vm::Vm,
     };
 
+    fn key(value: Value) -> ValueKey {
+        ValueKey::from_value(&value).unwrap()
+    }
+
+    #[test]
+    fn test_value_key_float() {
+        // Signed zeroes are the same key.
+        assert_eq!(key(Value::Float(-0.0)), key(Value::Float(0.0)));
+
+        // All NaNs are the same key.
+        let other_nan = f64::from_bits(f64::NAN.to_bits() ^ 1);
+        assert!(other_nan.is_nan());
+        assert_eq!(key(Value::Float(f64::NAN)), key(Value::Float(other_nan)));
+        assert_eq!(key(Value::Float(-f64::NAN)), key(Value::Float(f64::NAN)));
+
+        assert_ne!(key(Value::Float(1.0)), key(Value::Float(-1.0)));
+        assert_ne!(key(Value::Float(1.0)), key(Value::Int(1)));
+    }
+
+    #[test]
+    fn test_value_key_table() {
+        let mut table = Table::new();
+
+        table.insert(key(Value::Float(-0.0)), Value::Float(-0.0));
+        let value = table.get(&key(Value::Float(0.0))).and_then(Value::as_float).unwrap();
+        // The stored value keeps its sign.
+        assert!(value.is_sign_negative());
+
+        table.insert(key(Value::Float(f64::NAN)), Value::Int(7));
+        assert_eq!(table.get(&key(Value::Float(f64::NAN))).and_then(Value::as_int), Some(7));
+    }
+
+    #[test]
+    fn test_value_float_eq() -> Result<()> {
+        // Compare two float constants with the given instruction.
+        let compare = |op: Op, a: f64, b: f64| -> Result<Option<bool>> {
+            let func = Rc::new(Func {
+                code: Box::new([
+                    shorthand::push_float(0),
+                    shorthand::push_float(1),
+                    op,
+                    shorthand::return_(1),
+                ]),
+                stack_size: 3,
+                arity: 0,
+                is_varg: false,
+                constants: Constants {
+                    ints: Box::new([]),
+                    floats: Box::new([a, b]),
+                    strings: Box::new([]),
+                    funcs: Box::new([]),
+                },
+                up_values: Box::new([]),
+            });
+            let mut vm = Vm::new();
+            vm.run_function((), func)?;
+            Ok(vm.results()[0].as_bool())
+        };
+
+        // Equality follows IEEE 754, unlike keys, so signed zeroes are
+        // equal while NaN is not equal to itself.
+        assert_eq!(compare(Op::Float_Eq, -0.0, 0.0)?, Some(true));
+        assert_eq!(compare(Op::Float_Ne, -0.0, 0.0)?, Some(false));
+        assert_eq!(compare(Op::Float_Eq, f64::NAN, f64::NAN)?, Some(false));
+        assert_eq!(compare(Op::Float_Ne, f64::NAN, f64::NAN)?, Some(true));
+
+        Ok(())
+    }
+
+    fn round_trip(value: &Value) -> Value {
+        Value::from_bytes(&value.to_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_value_bytes_scalar() {
+        assert_eq!(round_trip(&Value::Int(-42)).as_int(), Some(-42));
+        assert_eq!(round_trip(&Value::Int(i64::MIN)).as_int(), Some(i64::MIN));
+        assert!(matches!(round_trip(&Value::UInt(u64::MAX)), Value::UInt(u64::MAX)));
+        assert_eq!(round_trip(&Value::Float(-2.5)).as_float(), Some(-2.5));
+        assert!(round_trip(&Value::Float(f64::NAN)).as_float().unwrap().is_nan());
+        assert!(round_trip(&Value::Nil).is_nil());
+        assert_eq!(round_trip(&Value::Bool(true)).as_bool(), Some(true));
+        assert_eq!(round_trip(&Value::Bool(false)).as_bool(), Some(false));
+
+        let string = Value::Object(Object::String(Rc::new(CrowStr::new("foobar"))));
+        assert_eq!(round_trip(&string).as_string().unwrap().as_str(), "foobar");
+    }
+
+    #[test]
+    fn test_value_bytes_table() {
+        let mut inner = Table::new();
+        inner.insert(key(Value::Float(1.5)), Value::Int(2));
+
+        let mut table = Table::new();
+        table.insert(key(Value::Int(1)), Value::Float(3.0));
+        table.insert(
+            ValueKey::String("inner".to_string()),
+            Value::Object(Object::Table(Handle::new(inner))),
+        );
+
+        let value = round_trip(&Value::Object(Object::Table(Handle::new(table))));
+        let table = value.as_table().unwrap().borrow();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(&key(Value::Int(1))).and_then(Value::as_float), Some(3.0));
+
+        let inner = table.get(&ValueKey::String("inner".to_string())).unwrap();
+        let inner = inner.as_table().unwrap().borrow();
+        assert_eq!(inner.get(&key(Value::Float(1.5))).and_then(Value::as_int), Some(2));
+    }
+
+    #[test]
+    fn test_value_bytes_array() {
+        let mut inner = Array::new();
+        inner.push(Value::Bool(true));
+
+        let mut array = Array::new();
+        array.push(Value::Int(1));
+        array.push(Value::Object(Object::String(Rc::new(CrowStr::new("two")))));
+        array.push(Value::Object(Object::Array(Handle::new(inner))));
+        array.push(Value::Object(Object::Array(Handle::new(Array::new()))));
+
+        let value = round_trip(&Value::Object(Object::Array(Handle::new(array))));
+        let array = value.as_array().unwrap().borrow();
+        assert_eq!(array.len(), 4);
+        assert_eq!(array.get(0).and_then(Value::as_int), Some(1));
+        assert_eq!(array.get(1).and_then(Value::as_string).unwrap().as_str(), "two");
+        let inner = array.get(2).and_then(Value::as_array).unwrap().borrow();
+        assert_eq!(inner.get(0).and_then(Value::as_bool), Some(true));
+        assert!(array.get(3).and_then(Value::as_array).unwrap().borrow().is_empty());
+    }
+
+    #[test]
+    fn test_value_bytes_depth() {
+        let nested = |depth: usize| {
+            let mut value = Value::Nil;
+            for _ in 0..depth {
+                let mut array = Array::new();
+                array.push(value);
+                value = Value::Object(Object::Array(Handle::new(array)));
+            }
+            value
+        };
+
+        assert!(round_trip(&nested(MAX_SERIALIZE_DEPTH)).as_array().is_some());
+        assert!(nested(MAX_SERIALIZE_DEPTH + 1).to_bytes().is_err());
+
+        // Hand written bytes can't nest deeper than the writer allows.
+        let mut bytes = Vec::new();
+        for _ in 0..=MAX_SERIALIZE_DEPTH {
+            bytes.push(tag::ARRAY);
+            bytes.extend_from_slice(&1_u32.to_le_bytes());
+        }
+        bytes.push(tag::NIL);
+        assert!(Value::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_value_bytes_error() {
+        let func = Rc::new(Func {
+            code: Box::new([Op::End]),
+            stack_size: 1,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+        });
+
+        assert!(Value::from_func(func.clone()).to_bytes().is_err());
+        assert!(Value::from_closure(Rc::new(Closure::new(func))).to_bytes().is_err());
+
+        // Cycles
+        let table = Handle::new(Table::new());
+        let value = Value::Object(Object::Table(table.clone()));
+        table.borrow_mut().insert(key(Value::Int(1)), value.clone());
+        assert!(value.to_bytes().is_err());
+        // Break the cycle so the table isn't leaked.
+        table.borrow_mut().remove(&key(Value::Int(1)));
+
+        let array = Handle::new(Array::new());
+        let value = Value::Object(Object::Array(array.clone()));
+        array.borrow_mut().push(value.clone());
+        assert!(value.to_bytes().is_err());
+        *array.borrow_mut() = Array::new();
+
+        // Malformed bytes
+        let bytes = Value::Int(1).to_bytes().unwrap();
+        assert!(Value::from_bytes(&bytes[..4]).is_err());
+        assert!(Value::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+        assert!(Value::from_bytes(&[255]).is_err());
+        assert!(Value::from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn test_value_eq() {
+        let string = |s: &str| Value::Object(Object::String(Rc::new(CrowStr::new(s))));
+        let table = Value::Object(Object::Table(Handle::new(Table::new())));
+        let other_table = Value::Object(Object::Table(Handle::new(Table::new())));
+
+        let same = [
+            (Value::Int(1), Value::Int(1)),
+            (Value::UInt(1), Value::UInt(1)),
+            (Value::Float(1.5), Value::Float(1.5)),
+            (Value::Float(-0.0), Value::Float(0.0)),
+            (string("crow"), string("crow")),
+            (table.clone(), table.clone()),
+        ];
+        for (a, b) in &same {
+            assert_eq!(a, b);
+        }
+
+        let different = [
+            (Value::Int(1), Value::Int(2)),
+            (Value::Float(f64::NAN), Value::Float(f64::NAN)),
+            (string("crow"), string("raven")),
+            (table.clone(), other_table),
+            // Different kinds
+            (Value::Int(1), Value::UInt(1)),
+            (Value::Int(1), Value::Float(1.0)),
+            (Value::Int(1), string("1")),
+            (string("crow"), table),
+        ];
+        for (a, b) in &different {
+            assert_ne!(a, b);
+        }
+    }
+
+    #[test]
+    fn test_value_display() {
+        assert_eq!(Value::Int(-42).to_string(), "-42");
+        assert_eq!(Value::UInt(u64::MAX).to_string(), "18446744073709551615");
+        assert_eq!(Value::Float(1.0).to_string(), "1.0");
+        assert_eq!(Value::Float(-2.5).to_string(), "-2.5");
+        assert_eq!(Value::Float(f64::NAN).to_string(), "NaN");
+        assert_eq!(Value::Float(f64::INFINITY).to_string(), "inf");
+        assert_eq!(
+            Value::Object(Object::String(Rc::new(CrowStr::new("caw \"caw\"")))).to_string(),
+            "caw \"caw\""
+        );
+
+        let func = Rc::new(Func {
+            code: Box::new([Op::End]),
+            stack_size: 1,
+            arity: 0,
+            is_varg: false,
+            constants: Constants {
+                ints: Box::new([]),
+                floats: Box::new([]),
+                strings: Box::new([]),
+                funcs: Box::new([]),
+            },
+            up_values: Box::new([]),
+        });
+        let closure = Rc::new(Closure::new(func.clone()));
+        let table = Handle::new(Table::new());
+        let array = Handle::new(Array::new());
+        let native = Rc::new(Native::new(()));
+
+        let objects = [
+            (
+                Value::from_func(func.clone()),
+                format!("<func {:?}>", Rc::as_ptr(&func)),
+            ),
+            (
+                Value::from_closure(closure.clone()),
+                format!("<closure {:?}>", Rc::as_ptr(&closure)),
+            ),
+            (
+                Value::Object(Object::Table(table.clone())),
+                format!("<table {:?}>", table.as_ptr()),
+            ),
+            (
+                Value::Object(Object::Array(array.clone())),
+                format!("<array {:?}>", array.as_ptr()),
+            ),
+            (
+                Value::Object(Object::Native(native.clone())),
+                format!("<native {:?}>", Rc::as_ptr(&native)),
+            ),
+        ];
+        for (value, expected) in objects {
+            assert!(expected.starts_with('<') && expected.contains("0x"), "{expected}");
+            assert_eq!(value.to_string(), expected);
+        }
+    }
+
     #[test]
     fn test_value_size() {
         assert!(
@@ -134,7 +814,7 @@ mod test {
     /// Experimental Miri test
     #[test]
     fn test_slot() -> Result<()> {
-        let func = Rc::new(Func {
+        let _func = Rc::new(Func {
             code: Box::new([
                 Op::PushIntIn(Arg24::from_i64(7)?),
                 Op::PushIntIn(Arg24::from_i64(11)?),
@@ -142,6 +822,7 @@ mod test {
                 Op::End,
             ]),
             stack_size: 3,
+            arity: 0,
             is_varg: true,
             constants: Constants {
                 ints: Box::new([]),
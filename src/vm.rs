@@ -1,11 +1,15 @@
 use std::fmt::{self, Formatter};
 use std::rc::Rc;
 
+use fxhash::FxHashMap;
+
+use crate::array::Array;
 use crate::errors::{runtime_err, Error, Result};
 use crate::handle::Handle;
+use crate::limits::{MAX_CALL_DEPTH, MAX_FLOAT_PRECISION, MAX_GLOBAL_CACHE_SIZE};
 use crate::object::*;
 use crate::op::Op;
-use crate::value::Value;
+use crate::value::{Value, ValueKey};
 
 pub struct Vm {
     /// Operand stack.
@@ -13,8 +17,173 @@ pub struct Vm {
 
     /// Callstack.
     calls: Vec<CallFrame>,
+
+    /// The active frame, while execution is paused at a breakpoint.
+    ///
+    /// During execution the active frame is owned by the interpreter loop,
+    /// and only parked here when the interpreter yields control back to the host.
+    frame: Option<CallFrame>,
+
+    /// Instruction locations where execution is paused.
+    breakpoints: Vec<Breakpoint>,
+
+    /// Set when resuming from a breakpoint, so the instruction that
+    /// triggered the pause is executed instead of pausing again.
+    skip_breakpoint: bool,
+
+    /// Set while executing a single instruction with [`Vm::step()`].
+    single_step: bool,
+
+    /// Set while a coroutine is running, so it can yield.
+    in_coroutine: bool,
+
+    /// Set while a native function is running, so it can't
+    /// re-enter the interpreter through the VM it's given.
+    in_native: bool,
+
+    /// Values returned by the entry function of the last run.
+    results: Vec<Value>,
+
+    /// Global variables.
+    globals: Globals,
+
+    /// Optional callback notified when functions are entered and exited.
+    trace_hook: Option<Box<dyn FnMut(TraceEvent)>>,
+
+    /// Optional callback notified before each instruction is executed.
+    op_trace_hook: Option<OpTraceHook>,
+
+    /// Maximum number of instructions a run may execute.
+    ///
+    /// Unlimited when `None`.
+    gas_limit: Option<u64>,
+
+    /// Number of instructions executed since the last run was started.
+    gas_used: u64,
+}
+
+/// Global variable storage.
+///
+/// Globals are stored in slots that are never removed, so once a name is
+/// resolved to a slot index that index remains valid for the lifetime of the VM.
+/// Reassigning a global writes through to its existing slot.
+struct Globals {
+    /// Global values, addressed by slot index.
+    values: Vec<Value>,
+    /// Lookup from global variable name to slot index.
+    names: FxHashMap<String, usize>,
+    /// Inline cache of resolved slot indices, keyed by the address of the
+    /// string constant used by [`Op::GetGlobal`] and [`Op::SetGlobal`].
+    ///
+    /// This skips hashing the variable name on repeated lookups from the same
+    /// function prototype, for example in a loop.
+    ///
+    /// The cache is cleared when a run starts, or when it grows beyond
+    /// [`MAX_GLOBAL_CACHE_SIZE`], so it doesn't keep old constants alive.
+    cache: FxHashMap<*const CrowStr, GlobalCacheEntry>,
+    /// Whether lookups go through the inline cache.
+    cache_enabled: bool,
+}
+
+struct GlobalCacheEntry {
+    /// Keeps the string constant alive, so its address can't be reused
+    /// by another string and result in a false cache hit.
+    _name: Rc<CrowStr>,
+    /// Resolved global slot.
+    slot: usize,
+}
+
+/// Location of an instruction, identified by function prototype and
+/// instruction pointer.
+struct Breakpoint {
+    func: Rc<Func>,
+    ip: usize,
+}
+
+/// Outcome of running the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    /// The entry function returned.
+    Finished,
+    /// Execution hit a breakpoint, and can be continued with [`Vm::resume()`].
+    Paused,
+}
+
+/// Outcome of executing a single instruction with [`Vm::step()`].
+#[derive(Debug, PartialEq)]
+pub enum StepResult {
+    /// The instruction was executed, and there are more to step through.
+    Running,
+    /// The entry function returned, with its last result value if any.
+    Returned(Option<Value>),
+}
+
+/// Event passed to the [`Vm`] trace hook.
+///
+/// See [`Vm::set_trace_hook()`].
+pub enum TraceEvent {
+    /// A function was called, and its frame pushed onto the callstack.
+    Enter { func: Rc<Func> },
+    /// A function returned, and its frame is about to be popped off the callstack.
+    Exit {
+        func: Rc<Func>,
+        /// Actual number of result values returned by the function.
+        results: usize,
+    },
+}
+
+/// A function that can suspend itself with [`Op::Yield`], and later be
+/// resumed from where it left off with [`Vm::resume_coroutine()`].
+///
+/// While suspended, the coroutine owns its callstack and the segment of
+/// the operand stack used by its frames.
+pub struct Coroutine {
+    status: CoroutineStatus,
+    closure: Rc<Closure>,
+    /// The active frame, while suspended at a yield.
+    frame: Option<CallFrame>,
+    /// Frames of the functions that were called when the coroutine yielded.
+    calls: Vec<CallFrame>,
+    /// Stack segment of the coroutine's frames.
+    stack: Vec<Value>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoroutineStatus {
+    /// Not started yet, or suspended at a yield.
+    Suspended,
+    /// Currently executing.
+    Running,
+    /// The function returned, or raised an error.
+    /// A finished coroutine can't be resumed.
+    Finished,
+}
+
+impl Coroutine {
+    pub fn new(func: Rc<Func>) -> Self {
+        Self::from_closure(Rc::new(Closure::new(func)))
+    }
+
+    pub fn from_closure(closure: Rc<Closure>) -> Self {
+        Self {
+            status: CoroutineStatus::Suspended,
+            closure,
+            frame: None,
+            calls: Vec::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn status(&self) -> CoroutineStatus {
+        self.status
+    }
+}
+
+/// Callback notified before each instruction is executed.
+///
+/// See [`Vm::set_op_trace_hook()`].
+pub type OpTraceHook = Box<dyn FnMut(usize, &Op, &[Value])>;
+
 struct CallFrame {
     /// Instruction pointer.
     ip: usize,
@@ -49,6 +218,26 @@ enum FrameAction {
     ///
     /// Base of stack is absolute.
     Call { base: usize, results: u8 },
+
+    /// Call a function in place of the current one, reusing its frame.
+    ///
+    /// Base of stack is absolute.
+    TailCall { base: usize },
+
+    /// Pause execution at a breakpoint, before the instruction
+    /// pointed to by the frame's instruction pointer.
+    Break,
+
+    /// Suspend the running coroutine.
+    ///
+    /// Start of the yielded values is absolute.
+    Yield { start: usize },
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Vm {
@@ -56,18 +245,367 @@ impl Vm {
         Self {
             stack: vec![],
             calls: vec![],
+            frame: None,
+            breakpoints: vec![],
+            skip_breakpoint: false,
+            single_step: false,
+            in_coroutine: false,
+            in_native: false,
+            results: vec![],
+            globals: Globals::new(),
+            trace_hook: None,
+            op_trace_hook: None,
+            gas_limit: None,
+            gas_used: 0,
+        }
+    }
+
+    /// Define or reassign a global variable.
+    pub fn set_global(&mut self, name: impl ToString, value: Value) {
+        let slot = self.globals.define(name.to_string());
+        self.globals.values[slot] = value;
+    }
+
+    /// Define a global variable holding a native function,
+    /// so it can be called from bytecode by name.
+    pub fn register_native(&mut self, name: impl ToString, native_fn: NativeFn) {
+        self.set_global(name, Value::Object(Object::NativeFn(native_fn)));
+    }
+
+    /// Enable or disable the inline cache of global variable lookups.
+    ///
+    /// The cache is enabled by default. Disabling it is mainly useful
+    /// to measure its effect.
+    pub fn set_global_cache(&mut self, enabled: bool) {
+        self.globals.cache_enabled = enabled;
+        self.globals.cache.clear();
+    }
+
+    /// Get the value of a global variable.
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        self.globals.names.get(name).map(|slot| &self.globals.values[*slot])
+    }
+
+    /// Limit the number of instructions a run may execute, to sandbox untrusted scripts.
+    ///
+    /// Exceeding the limit is a runtime error. The count includes instructions
+    /// executed after resuming from a breakpoint. `None` removes the limit.
+    pub fn set_gas_limit(&mut self, gas_limit: Option<u64>) {
+        self.gas_limit = gas_limit;
+    }
+
+    /// Number of instructions executed since the last run was started.
+    ///
+    /// Only counted while a gas limit is set.
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    /// Install a callback that is notified on every function entry and exit.
+    ///
+    /// Useful for coverage tooling and profilers.
+    pub fn set_trace_hook(&mut self, hook: Box<dyn FnMut(TraceEvent)>) {
+        self.trace_hook = Some(hook);
+    }
+
+    /// Remove the trace hook, if any.
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+
+    /// Install a callback that is notified before every instruction is executed.
+    ///
+    /// The callback receives the instruction's index in its function's bytecode,
+    /// the instruction itself, and the stack slots of the executing frame.
+    /// Useful for debuggers and instruction level tracing.
+    pub fn set_op_trace_hook(&mut self, hook: OpTraceHook) {
+        self.op_trace_hook = Some(hook);
+    }
+
+    /// Remove the instruction trace hook, if any.
+    pub fn clear_op_trace_hook(&mut self) {
+        self.op_trace_hook = None;
+    }
+
+    /// Notify the trace hook.
+    ///
+    /// The event is only constructed when a hook is installed.
+    #[inline(always)]
+    fn trace(&mut self, event: impl FnOnce() -> TraceEvent) {
+        if let Some(hook) = self.trace_hook.as_mut() {
+            hook(event())
         }
     }
 
     /// Execute a function constant.
-    pub fn run_function(&mut self, _env: (), func: Rc<Func>) -> Result<()> {
+    ///
+    /// Returns [`RunState::Paused`] when a breakpoint was hit before the function returned.
+    pub fn run_function(&mut self, _env: (), func: Rc<Func>) -> Result<RunState> {
+        if self.in_native {
+            return Err(err_native_reentry());
+        }
+        if self.frame.is_some() {
+            return runtime_err("cannot run a function while execution is paused").into();
+        }
+
+        let frame = self.enter_function(func);
+        run_interpreter(self, frame)
+    }
+
+    /// Prepare a function constant for execution with [`Vm::step()`],
+    /// without executing any of its instructions.
+    pub fn load_function(&mut self, _env: (), func: Rc<Func>) -> Result<()> {
+        if self.in_native {
+            return Err(err_native_reentry());
+        }
+        if self.frame.is_some() {
+            return runtime_err("cannot load a function while execution is paused").into();
+        }
+
+        self.frame = Some(self.enter_function(func));
+        Ok(())
+    }
+
+    /// Push the entry frame of a new run.
+    fn enter_function(&mut self, func: Rc<Func>) -> CallFrame {
+        self.results.clear();
+        self.gas_used = 0;
+        self.globals.cache.clear();
+
         // All callables are wrapped in closures to simplify the VM loop.
-        let closure = Rc::new(Closure::new(func));
-        run_interpreter(self, closure)
+        // The closure is released when the frame returns, or the stack is unwound on error.
+        let frame = CallFrame::new(Rc::new(Closure::new(func)));
+
+        self.stack.push(Value::from_closure(frame.closure.clone()));
+        self.trace(|| TraceEvent::Enter {
+            func: frame.func.clone(),
+        });
+
+        frame
+    }
+
+    /// Execute exactly one instruction of a loaded or paused function.
+    ///
+    /// Breakpoints don't stop a step, so a debugger can step over them.
+    /// Once the entry function returns, its last result is given,
+    /// like [`Vm::eval()`], and the results are also available from
+    /// [`Vm::results()`].
+    pub fn step(&mut self) -> Result<StepResult> {
+        let frame = self
+            .frame
+            .take()
+            .ok_or_else(|| runtime_err("no function is loaded for stepping"))?;
+
+        self.single_step = true;
+        self.skip_breakpoint = true;
+        let state = run_interpreter(self, frame);
+        self.single_step = false;
+        self.skip_breakpoint = false;
+
+        match state? {
+            RunState::Paused => Ok(StepResult::Running),
+            RunState::Finished => Ok(StepResult::Returned(self.results.last().cloned())),
+        }
+    }
+
+    /// Execute a function constant, and return its last result value.
+    ///
+    /// Returns `None` when the function returned nothing. The stack is
+    /// left empty afterwards, so the VM is ready to evaluate the next function.
+    ///
+    /// Hitting a breakpoint is an error, though execution can
+    /// still be continued with [`Vm::resume()`].
+    pub fn eval(&mut self, func: Rc<Func>) -> Result<Option<Value>> {
+        match self.run_function((), func)? {
+            RunState::Finished => {
+                self.stack.clear();
+                Ok(self.results.last().cloned())
+            }
+            RunState::Paused => runtime_err("evaluation paused at a breakpoint").into(),
+        }
+    }
+
+    /// Resume a suspended coroutine, and return the values it yielded,
+    /// or the values it returned once it has finished.
+    ///
+    /// The arguments are passed to the coroutine's function when it is first
+    /// resumed. Afterwards they are pushed onto the coroutine's stack in place
+    /// of the values it yielded.
+    ///
+    /// Only single-level coroutines are supported, so a coroutine can only be
+    /// resumed by the host while the VM is idle, and not from a native function.
+    /// Breakpoints are ignored while a coroutine runs. An error finishes the
+    /// coroutine.
+    pub fn resume_coroutine(&mut self, coroutine: &Handle<Coroutine>, args: &[Value]) -> Result<&[Value]> {
+        if self.in_native {
+            return Err(err_native_reentry());
+        }
+        if self.frame.is_some() {
+            return runtime_err("cannot resume a coroutine while execution is paused").into();
+        }
+        if !self.stack.is_empty() || !self.calls.is_empty() {
+            return runtime_err("cannot resume a coroutine while the VM is running").into();
+        }
+
+        // The coroutine's state is moved into the VM while it runs,
+        // so it isn't borrowed when the coroutine is reachable from its own stack.
+        let mut suspended = {
+            let mut coroutine = coroutine.borrow_mut();
+            match coroutine.status {
+                CoroutineStatus::Suspended => {}
+                CoroutineStatus::Running => return runtime_err("coroutine is already running").into(),
+                CoroutineStatus::Finished => return runtime_err("cannot resume a finished coroutine").into(),
+            }
+            coroutine.status = CoroutineStatus::Running;
+
+            std::mem::swap(&mut self.stack, &mut coroutine.stack);
+            std::mem::swap(&mut self.calls, &mut coroutine.calls);
+            coroutine.frame.take()
+        };
+
+        self.results.clear();
+        self.gas_used = 0;
+        self.globals.cache.clear();
+        self.stack.extend_from_slice(args);
+
+        let frame = match suspended.as_mut() {
+            Some(frame) => frame,
+            None => {
+                // First resume, so the arguments are for the coroutine's function.
+                let closure = coroutine.borrow().closure.clone();
+                self.stack.insert(0, Value::from_closure(closure.clone()));
+                if closure.func.is_varg {
+                    collect_varargs(self, &closure.func, 0);
+                }
+                self.trace(|| TraceEvent::Enter {
+                    func: closure.func.clone(),
+                });
+                suspended.insert(CallFrame::new(closure))
+            }
+        };
+
+        self.in_coroutine = true;
+        let state = run_frames(self, frame);
+        self.in_coroutine = false;
+
+        let status = match state {
+            // A breakpoint can't pause a coroutine, so it yielded.
+            Ok(RunState::Paused) => CoroutineStatus::Suspended,
+            Ok(RunState::Finished) => CoroutineStatus::Finished,
+            Err(_) => {
+                self.unwind(suspended.take().expect("coroutine frame is active"));
+                CoroutineStatus::Finished
+            }
+        };
+
+        let mut coroutine = coroutine.borrow_mut();
+        coroutine.status = status;
+        if status == CoroutineStatus::Suspended {
+            coroutine.frame = suspended;
+        }
+        std::mem::swap(&mut self.stack, &mut coroutine.stack);
+        std::mem::swap(&mut self.calls, &mut coroutine.calls);
+
+        state.map(|_| self.results.as_slice())
+    }
+
+    /// Values returned by the entry function, once execution has finished.
+    ///
+    /// The entry function's own closure is not included.
+    pub fn results(&self) -> &[Value] {
+        &self.results
+    }
+
+    /// Continue execution that was paused at a breakpoint.
+    pub fn resume(&mut self) -> Result<RunState> {
+        let frame = self
+            .frame
+            .take()
+            .ok_or_else(|| runtime_err("execution is not paused"))?;
+
+        // Don't pause again on the instruction that caused the break.
+        self.skip_breakpoint = !self.breakpoints.is_empty();
+
+        run_interpreter(self, frame)
+    }
+
+    /// Pause execution before the instruction at `ip` is executed in the given function.
+    pub fn set_breakpoint(&mut self, func: &Rc<Func>, ip: usize) {
+        if !self
+            .breakpoints
+            .iter()
+            .any(|bp| Rc::ptr_eq(&bp.func, func) && bp.ip == ip)
+        {
+            self.breakpoints.push(Breakpoint { func: func.clone(), ip });
+        }
+    }
+
+    /// Remove all breakpoints.
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Local variable slots of the frame where execution is paused.
+    ///
+    /// Slot 0 holds the callable, followed by the arguments and local variables.
+    /// Slots the function hasn't pushed yet are not included.
+    ///
+    /// Returns an empty slice when execution is not paused.
+    pub fn current_locals(&self) -> &[Value] {
+        match &self.frame {
+            Some(frame) => {
+                let end = (frame.base + frame.func.stack_size as usize).min(self.stack.len());
+                &self.stack[frame.base.min(end)..end]
+            }
+            None => &[],
+        }
+    }
+
+    /// Check whether the frame is about to execute an instruction with a breakpoint.
+    fn is_breakpoint(&mut self, frame: &CallFrame) -> bool {
+        if std::mem::take(&mut self.skip_breakpoint) {
+            return false;
+        }
+
+        self.breakpoints
+            .iter()
+            .any(|bp| bp.ip == frame.ip && Rc::ptr_eq(&bp.func, &frame.func))
     }
 
+    /// Discard all call frames and the stack after an error,
+    /// so the VM can be used to run another function.
+    ///
+    /// Captured locals are closed first, so closures that
+    /// escaped before the error can still be called.
+    fn unwind(&mut self, frame: CallFrame) {
+        for mut frame in self.calls.drain(..).chain(std::iter::once(frame)) {
+            for up_value_handle in frame.up_values.drain(..) {
+                let up_value = &mut *up_value_handle.borrow_mut();
+                if let UpValue::Open(stack_offset) = up_value {
+                    let value = self.stack.get(*stack_offset).cloned().unwrap_or(Value::Nil);
+                    up_value.close(value);
+                }
+            }
+        }
+
+        self.stack.clear();
+    }
+
+    /// Stack slot at the given absolute index.
+    ///
+    /// Malformed bytecode can refer to slots beyond the top of the
+    /// stack, which results in an error rather than a panic.
+    fn slot(&self, index: usize) -> Result<&Value> {
+        self.stack.get(index).ok_or_else(err_stack_out_of_bounds)
+    }
+
+    fn slot_mut(&mut self, index: usize) -> Result<&mut Value> {
+        self.stack.get_mut(index).ok_or_else(err_stack_out_of_bounds)
+    }
+
+    #[allow(dead_code)]
     fn grow_stack(&mut self, additional: usize) {
-        self.stack.extend((0..additional).map(|_| Value::Int(0)))
+        self.stack.extend((0..additional).map(|_| Value::Nil))
     }
 
     fn pop_int(&mut self) -> Result<i64> {
@@ -94,6 +632,35 @@ impl Vm {
         Ok([a, b])
     }
 
+    fn pop_string(&mut self) -> Result<Rc<CrowStr>> {
+        self.stack
+            .pop()
+            .ok_or_else(err_stack_underflow)?
+            .as_string()
+            .cloned()
+            .ok_or_else(err_string_expected)
+    }
+
+    fn pop2_string(&mut self) -> Result<[Rc<CrowStr>; 2]> {
+        let b = self.pop_string()?;
+        let a = self.pop_string()?;
+        Ok([a, b])
+    }
+
+    /// Pop a value to be used as a table key.
+    fn pop_key(&mut self) -> Result<ValueKey> {
+        let value = self.stack.pop().ok_or_else(err_stack_underflow)?;
+        ValueKey::from_value(&value).ok_or_else(err_key_expected)
+    }
+
+    fn pop_float(&mut self) -> Result<f64> {
+        self.stack
+            .pop()
+            .ok_or_else(err_stack_underflow)?
+            .as_float()
+            .ok_or_else(err_float_expected)
+    }
+
     fn pop2_float(&mut self) -> Result<[f64; 2]> {
         let b = self
             .stack
@@ -111,6 +678,74 @@ impl Vm {
     }
 }
 
+impl Globals {
+    fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            names: FxHashMap::default(),
+            cache: FxHashMap::default(),
+            cache_enabled: true,
+        }
+    }
+
+    /// Define a new global variable slot, or return the existing slot.
+    fn define(&mut self, name: String) -> usize {
+        let Self { values, names, .. } = self;
+        *names.entry(name).or_insert_with(|| {
+            values.push(Value::Nil);
+            values.len() - 1
+        })
+    }
+
+    /// Resolve the slot of the global with the given name, using the inline cache.
+    ///
+    /// The global is defined if it doesn't exist yet.
+    fn resolve(&mut self, name: &Rc<CrowStr>) -> usize {
+        match self.lookup(name) {
+            Some(slot) => slot,
+            None => {
+                let slot = self.define(name.to_string());
+                self.cache_slot(name, slot);
+                slot
+            }
+        }
+    }
+
+    /// Find the slot of the global with the given name, using the inline cache.
+    ///
+    /// Returns `None` if the global is not defined.
+    fn lookup(&mut self, name: &Rc<CrowStr>) -> Option<usize> {
+        if !self.cache_enabled {
+            return self.names.get(name.as_str()).copied();
+        }
+        if let Some(entry) = self.cache.get(&Rc::as_ptr(name)) {
+            return Some(entry.slot);
+        }
+
+        let slot = *self.names.get(name.as_str())?;
+        self.cache_slot(name, slot);
+
+        Some(slot)
+    }
+
+    fn cache_slot(&mut self, name: &Rc<CrowStr>, slot: usize) {
+        if !self.cache_enabled {
+            return;
+        }
+        if self.cache.len() >= MAX_GLOBAL_CACHE_SIZE {
+            self.cache.clear();
+        }
+
+        self.cache.insert(
+            Rc::as_ptr(name),
+            GlobalCacheEntry {
+                _name: name.clone(),
+                slot,
+            },
+        );
+    }
+}
+
 impl CallFrame {
     fn new(closure: Rc<Closure>) -> Self {
         Self {
@@ -144,98 +779,271 @@ struct DumpVm<'a> {
 }
 
 /// Interpreter entry point.
-fn run_interpreter(vm: &mut Vm, closure: Rc<Closure>) -> Result<()> {
-    // FIXME: Memory management to ensure this Rc<Closure> isn't leaked.
-    let mut frame = CallFrame::new(closure.clone());
-
-    vm.stack.push(Value::from_closure(frame.closure.clone()));
+///
+/// Executes the given frame until the entry function returns, or a breakpoint
+/// is hit. When paused, the active frame is parked in the [`Vm`] so it can be
+/// inspected and resumed.
+fn run_interpreter(vm: &mut Vm, mut frame: CallFrame) -> Result<RunState> {
+    match run_frames(vm, &mut frame) {
+        Ok(RunState::Paused) => {
+            vm.frame = Some(frame);
+            Ok(RunState::Paused)
+        }
+        Ok(RunState::Finished) => Ok(RunState::Finished),
+        Err(err) => {
+            vm.unwind(frame);
+            Err(err)
+        }
+    }
+}
 
+fn run_frames(vm: &mut Vm, frame: &mut CallFrame) -> Result<RunState> {
     loop {
-        match run_op_loop(vm, &mut frame)? {
+        match run_op_loop(vm, frame)? {
+            FrameAction::Break => return Ok(RunState::Paused),
             FrameAction::Return { start, count } => {
-                // println!(
-                //     "return: frame.base->{}, slot->{:?}, start->{}, count->{}",
-                //     frame.base, vm.stack[frame.base], start, count
-                // );
+                if let Some(state) = return_from_frame(vm, frame, start, count as usize)? {
+                    return Ok(state);
+                }
+            }
+            FrameAction::Call {
+                base: callee_base,
+                results,
+            } => {
+                // base was made absolute by the call instruction.
+                let callee = vm
+                    .stack
+                    .get(callee_base)
+                    .ok_or_else(|| runtime_err("call base is beyond the top of the stack"))?;
 
-                // Drop callable to decrement reference count.
-                // let _ = vm.stack[frame.base].as_func();
+                // Native functions are called directly, without a frame.
+                if let Value::Object(Object::NativeFn(native_fn)) = *callee {
+                    call_native(vm, native_fn, callee_base, Some(results as usize))?;
+                } else {
+                    if vm.calls.len() >= MAX_CALL_DEPTH {
+                        return runtime_err("call stack overflow").into();
+                    }
 
-                if vm.calls.is_empty() {
-                    for _ in 0..count {
-                        println!("return: {:?}", vm.stack.pop());
+                    let closure = callee.as_closure().cloned().ok_or_else(err_closure_expected)?;
+                    if closure.func.is_varg {
+                        collect_varargs(vm, &closure.func, callee_base);
                     }
-                    vm.stack.truncate(frame.base);
-                    return Ok(());
+
+                    let new_frame = CallFrame {
+                        ip: 0,
+                        top: 1,
+                        base: callee_base,
+                        results: results as usize,
+                        func: closure.func.clone(),
+                        closure,
+                        up_values: Vec::new(),
+                    };
+
+                    vm.calls.push(std::mem::replace(frame, new_frame));
+                    vm.trace(|| TraceEvent::Enter {
+                        func: frame.func.clone(),
+                    });
+                }
+            }
+            FrameAction::Yield { start } => {
+                // Open up-values point into the coroutine's stack segment,
+                // which is moved out of the VM while it's suspended.
+                if !frame.up_values.is_empty() || vm.calls.iter().any(|call| !call.up_values.is_empty()) {
+                    return runtime_err("cannot yield while local variables are captured by closures").into();
                 }
 
-                // Copy the multiple returns to the base of the stack.
-                // Erasing the callable.
-                //
-                // The caller may be expecting more results
-                // than what the callee is actually returning.
-                if frame.results > (count as usize) {
-                    return runtime_err(format!(
-                        "caller expected {} results, but callee only returned {count}",
-                        frame.results
-                    ))
-                    .into();
+                vm.results.extend(vm.stack.drain(start..));
+                return Ok(RunState::Paused);
+            }
+            FrameAction::TailCall { base: callee_base } => {
+                let callee = vm
+                    .stack
+                    .get(callee_base)
+                    .ok_or_else(|| runtime_err("call base is beyond the top of the stack"))?;
+
+                // A native function's results are returned as if by the current function.
+                if let Value::Object(Object::NativeFn(native_fn)) = *callee {
+                    let count = call_native(vm, native_fn, callee_base, None)?;
+                    if let Some(state) = return_from_frame(vm, frame, callee_base, count)? {
+                        return Ok(state);
+                    }
+                } else {
+                    let closure = callee.as_closure().cloned().ok_or_else(err_closure_expected)?;
+
+                    // Move the callee and its arguments down to the current frame's base,
+                    // discarding the current function's locals. The caller still
+                    // expects the same number of results.
+                    vm.stack.drain(frame.base..callee_base);
+                    if closure.func.is_varg {
+                        collect_varargs(vm, &closure.func, frame.base);
+                    }
+                    frame.ip = 0;
+                    frame.top = 1;
+                    frame.func = closure.func.clone();
+                    frame.closure = closure;
                 }
+            }
+        }
 
-                // The callee may return more results, but the caller could just discard them.
-                let result_count = frame.results.min(count as usize);
+        // The instruction that switched frames completes the step.
+        if vm.single_step {
+            return Ok(RunState::Paused);
+        }
+    }
+}
 
-                // Slice the stack to the callee's span so it's easier to work with.
-                let stack = &mut vm.stack[frame.base..];
+/// Pop the frame that returned the results at the absolute `start` of the stack,
+/// and make them available to its caller.
+///
+/// Returns the final state once the entry function returns.
+fn return_from_frame(vm: &mut Vm, frame: &mut CallFrame, start: usize, count: usize) -> Result<Option<RunState>> {
+    vm.trace(|| TraceEvent::Exit {
+        func: frame.func.clone(),
+        results: count,
+    });
+
+    // The caller may be expecting more results
+    // than what the callee is actually returning.
+    //
+    // The entry function's frame expects no results.
+    if frame.results > count {
+        return runtime_err(format!(
+            "caller expected {} results, but callee only returned {count}",
+            frame.results
+        ))
+        .into();
+    }
 
-                // Translate absolute to relative stack index.
-                let start = start - frame.base;
+    // This overflow can happen if the bytecode is malformed.
+    // (Result instruction returned wrong count)
+    if start < frame.base || start + count > vm.stack.len() {
+        return runtime_err("returned results overflow stack").into();
+    }
 
-                // This overflow can happen if the bytecode is malformed.
-                // (Result instruction returned wrong count)
-                if start + result_count > stack.len() {
-                    // println!("stack.len() -> {}", stack.len());
-                    return runtime_err("returned results overflow stack").into();
-                }
+    // The entry function's frame has no caller to receive its results,
+    // so they're moved out of the stack along with its own closure in slot 0.
+    if vm.calls.is_empty() {
+        vm.results.extend(vm.stack.drain(start..start + count));
+        vm.stack.truncate(frame.base);
+        return Ok(Some(RunState::Finished));
+    }
 
-                // Copy the callee's results to its base, so they're available to the caller.
-                for offset in 0..result_count {
-                    stack[offset] = stack[start as usize + offset].clone();
-                }
+    // The callee may return more results, but the caller could just discard them.
+    let result_count = frame.results.min(count);
 
-                vm.stack.truncate(frame.base + result_count);
-                // println!("vm.stack (after truncate) -> {:?}", vm.stack);
+    // Move the kept results down to the callee's base, so they're available
+    // to the caller. This erases the callable and the callee's locals, along
+    // with any discarded results.
+    vm.stack.truncate(start + result_count);
+    vm.stack.drain(frame.base..start);
 
-                frame = vm.calls.pop().unwrap();
-            }
-            FrameAction::Call {
-                base: callee_base,
-                results,
-            } => {
-                // base is relative to the caller's base.
-                let slot = vm.stack[callee_base].clone();
+    *frame = vm.calls.pop().expect("callstack checked for parent frame");
 
-                // println!("call: frame.base->{}, callee_base->{:?}", frame.base, slot);
+    Ok(None)
+}
 
-                let closure = vm.stack[callee_base]
-                    .as_closure()
-                    .cloned()
-                    .ok_or_else(err_closure_expected)?;
+/// Close the frame's open up-values, copying the captured locals off the stack.
+fn close_up_values(vm: &mut Vm, frame: &mut CallFrame) -> Result<()> {
+    for up_value_handle in frame.up_values.drain(..) {
+        let up_value = &mut *up_value_handle.borrow_mut();
+        if let UpValue::Open(stack_offset) = up_value {
+            let value = vm.slot(*stack_offset)?.clone();
+            up_value.close(value);
+        }
+    }
+    Ok(())
+}
 
-                let new_frame = CallFrame {
-                    ip: 0,
-                    top: 1,
-                    base: callee_base,
-                    results: results as usize,
-                    func: closure.func.clone(),
-                    closure,
-                    up_values: Vec::new(),
-                };
+/// Collect the arguments beyond a variadic function's arity into an array,
+/// which takes their place on the stack after the declared parameters.
+///
+/// Missing declared parameters are filled with `nil`.
+fn collect_varargs(vm: &mut Vm, func: &Func, callee_base: usize) {
+    let varargs_start = callee_base + 1 + func.arity as usize;
+    if vm.stack.len() < varargs_start {
+        vm.stack.resize(varargs_start, Value::Nil);
+    }
 
-                vm.calls.push(std::mem::replace(&mut frame, new_frame));
-            }
+    let mut array = Array::with_capacity(vm.stack.len() - varargs_start);
+    for value in vm.stack.drain(varargs_start..) {
+        array.push(value);
+    }
+    vm.stack.push(Value::Object(Object::Array(Handle::new(array))));
+}
+
+/// Call a native function with the arguments above the callee's slot,
+/// and replace the callee and its arguments with the expected results.
+///
+/// All of the native function's results are kept when `results` is `None`.
+/// Returns the number of results left on the stack.
+fn call_native(vm: &mut Vm, native_fn: NativeFn, callee_base: usize, results: Option<usize>) -> Result<usize> {
+    let args = vm.stack.split_off(callee_base + 1);
+    vm.stack.truncate(callee_base);
+
+    vm.in_native = true;
+    let values = native_fn(vm, &args);
+    vm.in_native = false;
+
+    let mut values = values?;
+    let results = results.unwrap_or(values.len());
+    if results > values.len() {
+        return runtime_err(format!(
+            "caller expected {results} results, but native function only returned {}",
+            values.len()
+        ))
+        .into();
+    }
+
+    values.truncate(results);
+    vm.stack.extend(values);
+
+    Ok(results)
+}
+
+/// Format an integer in the given radix, which must be between 2 and 36.
+fn format_int_radix(value: i64, radix: u32) -> String {
+    let mut magnitude = value.unsigned_abs();
+    let mut digits = Vec::new();
+
+    loop {
+        let digit = (magnitude % radix as u64) as u32;
+        digits.push(char::from_digit(digit, radix).expect("radix is between 2 and 36"));
+        magnitude /= radix as u64;
+        if magnitude == 0 {
+            break;
         }
     }
+
+    if value < 0 {
+        digits.push('-');
+    }
+
+    digits.iter().rev().collect()
+}
+
+fn err_stack_out_of_bounds() -> Error {
+    runtime_err("stack index out of bounds")
+}
+
+fn err_int_overflow() -> Error {
+    runtime_err("integer overflow")
+}
+
+fn err_native_reentry() -> Error {
+    runtime_err("cannot run bytecode from inside a native function")
+}
+
+fn err_division_by_zero() -> Error {
+    runtime_err("division by zero")
+}
+
+/// Amount of bits to shift by, where anything from 64 up is clamped
+/// so the checked shift results in zero.
+fn shift_amount(shift: i64) -> Result<u32> {
+    if shift < 0 {
+        return runtime_err(format!("negative shift amount: {shift}")).into();
+    }
+    Ok(shift.min(64) as u32)
 }
 
 fn err_const_notfound() -> Error {
@@ -250,6 +1058,7 @@ fn err_stack_underflow() -> Error {
     runtime_err("stack underflow")
 }
 
+#[allow(dead_code)]
 fn err_func_expected() -> Error {
     runtime_err("function value expected")
 }
@@ -270,10 +1079,22 @@ fn err_string_expected() -> Error {
     runtime_err("string value expected")
 }
 
+fn err_array_expected() -> Error {
+    runtime_err("array value expected")
+}
+
+fn err_array_index(index: i64, len: usize) -> Error {
+    runtime_err(format!("array index out of bounds: {index} (length {len})"))
+}
+
 fn err_table_expected() -> Error {
     runtime_err("table value expected")
 }
 
+fn err_key_expected() -> Error {
+    runtime_err("value cannot be used as a table key")
+}
+
 fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
     // let Vm { stack: whole_stack, .. } = vm;
 
@@ -281,6 +1102,10 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
     // let stack = &whole_stack[frame.base..];
 
     loop {
+        if !vm.breakpoints.is_empty() && !vm.in_coroutine && vm.is_breakpoint(frame) {
+            return Ok(FrameAction::Break);
+        }
+
         let op = frame
             .func
             .code
@@ -289,8 +1114,21 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
             .ok_or_else(|| runtime_err("instruction pointer out of bytecode bounds"))?;
         frame.ip += 1;
 
-        dump_vm(vm, frame);
-        println!("{:04} : {:?}", frame.ip, op);
+        if let Some(gas_limit) = vm.gas_limit {
+            vm.gas_used += 1;
+            if vm.gas_used > gas_limit {
+                return runtime_err("instruction limit exceeded").into();
+            }
+        }
+
+        if let Some(hook) = vm.op_trace_hook.as_mut() {
+            hook(frame.ip - 1, &op, vm.stack.get(frame.base..).unwrap_or_default());
+        }
+
+        if cfg!(feature = "trace_vm") {
+            dump_vm(vm, frame);
+            println!("{:04} : {:?}", frame.ip - 1, op);
+        }
 
         match op {
             Op::NoOp => { /* Do nothing */ }
@@ -299,44 +1137,112 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
                     vm.stack.pop();
                 }
             }
-            Op::End => return Ok(FrameAction::Return { start: 0, count: 0 }),
-            Op::Return { results: count } => {
-                // Close up-values.
-                //
+            Op::Dup => {
+                let value = vm.stack.last().cloned().ok_or_else(err_stack_underflow)?;
+                vm.stack.push(value);
+            }
+            Op::Swap => {
+                let len = vm.stack.len();
+                if len < 2 {
+                    return Err(err_stack_underflow());
+                }
+                vm.stack.swap(len - 1, len - 2);
+            }
+            Op::End | Op::Return { .. } => {
+                // End is an implicit return with no results.
+                let count = match op {
+                    Op::Return { results } => results,
+                    _ => 0,
+                };
+
                 // This frame is about the go out of scope, so any captured
                 // local variables must be preserved on the heap.
-                for up_value_handle in frame.up_values.drain(..) {
-                    let up_value = &mut *up_value_handle.borrow_mut();
-                    if let UpValue::Open(stack_offset) = up_value {
-                        let value = vm.stack[*stack_offset].clone();
-                        up_value.close(value);
-                    }
-                }
+                close_up_values(vm, frame)?;
 
                 // Top values on stack are considered the return values.
-                let start = vm.stack.len() - count as usize;
+                let start = vm
+                    .stack
+                    .len()
+                    .checked_sub(count as usize)
+                    .ok_or_else(err_stack_underflow)?;
                 return Ok(FrameAction::Return { start, count });
             }
 
+            Op::Yield { results } => {
+                if !vm.in_coroutine {
+                    return runtime_err("cannot yield outside of a coroutine").into();
+                }
+
+                let start = vm
+                    .stack
+                    .len()
+                    .checked_sub(results as usize)
+                    .ok_or_else(err_stack_underflow)?;
+                return Ok(FrameAction::Yield { start });
+            }
+
             Op::Call { base, results } => {
+                // Slot 0 holds the caller's own callable, which
+                // must not be overwritten by the callee's results.
+                if base == 0 {
+                    return runtime_err("call base must be above the caller's callable slot").into();
+                }
+
                 return Ok(FrameAction::Call {
                     base: frame.base + base as usize,
                     results,
-                })
+                });
             }
 
-            Op::Load { .. } => {
-                todo!()
+            Op::TailCall { base } => {
+                if base == 0 {
+                    return runtime_err("call base must be above the caller's callable slot").into();
+                }
+
+                // The frame is reused by the callee, which overwrites its locals.
+                close_up_values(vm, frame)?;
+
+                return Ok(FrameAction::TailCall {
+                    base: frame.base + base as usize,
+                });
             }
-            Op::Store { .. } => {
-                todo!()
+
+            Op::Load { offset, len } => {
+                let start = frame.base + offset as usize;
+                let end = start + len as usize;
+                if end > vm.stack.len() {
+                    return runtime_err(format!("load of {len} values at offset {offset} overflows stack")).into();
+                }
+                vm.stack.extend_from_within(start..end);
+            }
+            Op::Store { offset, len } => {
+                let start = frame.base + offset as usize;
+                let values_start = vm
+                    .stack
+                    .len()
+                    .checked_sub(len as usize)
+                    .ok_or_else(err_stack_underflow)?;
+                // The destination slots must remain on the stack once the values are popped.
+                if start + len as usize > values_start {
+                    return runtime_err(format!("store of {len} values at offset {offset} overflows stack")).into();
+                }
+                let values = vm.stack.split_off(values_start);
+                for (index, value) in values.into_iter().enumerate() {
+                    vm.stack[start + index] = value;
+                }
             }
 
             Op::SetLocal { slot } => {
-                vm.stack[frame.base + slot as usize] = vm.stack.last().cloned().ok_or_else(err_stack_underflow)?;
+                let value = vm.stack.last().cloned().ok_or_else(err_stack_underflow)?;
+                *vm.slot_mut(frame.base + slot as usize)? = value;
+            }
+            Op::StoreLocal { slot } => {
+                let value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
+                *vm.slot_mut(frame.base + slot as usize)? = value;
             }
             Op::GetLocal { slot } => {
-                vm.stack.push(vm.stack[frame.base + slot as usize].clone());
+                let value = vm.slot(frame.base + slot as usize)?.clone();
+                vm.stack.push(value);
             }
 
             Op::SetUpValue { upvalue_id } => {
@@ -351,7 +1257,7 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
                     .borrow_mut()
                 {
                     UpValue::Open(stack_offset) => {
-                        vm.stack[*stack_offset] = value;
+                        *vm.slot_mut(*stack_offset)? = value;
                     }
                     UpValue::Closed(upvalue) => {
                         *upvalue = value;
@@ -368,7 +1274,8 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
                     .borrow()
                 {
                     UpValue::Open(stack_offset) => {
-                        vm.stack.push(vm.stack[*stack_offset].clone());
+                        let value = vm.slot(*stack_offset)?.clone();
+                        vm.stack.push(value);
                     }
                     UpValue::Closed(upvalue) => {
                         vm.stack.push(upvalue.clone());
@@ -376,8 +1283,32 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
                 }
             }
 
-            Op::SetGlobal { .. } => todo!(),
-            Op::GetGlobal { .. } => todo!(),
+            Op::SetGlobal { string } => {
+                let name = frame
+                    .func
+                    .constants
+                    .strings
+                    .get(string as usize)
+                    .ok_or_else(err_const_notfound)?;
+                // Pop before resolving, so an underflow doesn't define the global.
+                let value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
+                let slot = vm.globals.resolve(name);
+                vm.globals.values[slot] = value;
+            }
+            Op::GetGlobal { string } => {
+                let name = frame
+                    .func
+                    .constants
+                    .strings
+                    .get(string as usize)
+                    .ok_or_else(err_const_notfound)?;
+                // An undefined global reads as nil.
+                let value = match vm.globals.lookup(name) {
+                    Some(slot) => vm.globals.values[slot].clone(),
+                    None => Value::Nil,
+                };
+                vm.stack.push(value);
+            }
 
             Op::PushIntIn(value) => {
                 vm.stack.push(Value::Int(value.as_i64()));
@@ -391,14 +1322,22 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
                     .ok_or_else(|| runtime_err(format!("no integer constant defined: {}", const_id.as_usize())))?;
                 vm.stack.push(Value::Int(x));
             }
-            Op::PushFloat(_const_id) => todo!(),
+            Op::PushFloat(const_id) => {
+                let x = *frame
+                    .func
+                    .constants
+                    .floats
+                    .get(const_id.as_usize())
+                    .ok_or_else(|| runtime_err(format!("no float constant defined: {}", const_id.as_usize())))?;
+                vm.stack.push(Value::Float(x));
+            }
             Op::PushString(string_id) => {
                 let string = frame
                     .func
                     .constants
                     .strings
                     .get(string_id.as_usize())
-                    .ok_or_else(err_const_notfound)?
+                    .ok_or_else(|| runtime_err(format!("no string constant defined: {}", string_id.as_usize())))?
                     .clone();
                 vm.stack.push(Value::Object(Object::String(string)));
             }
@@ -411,6 +1350,9 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
                     .ok_or_else(|| runtime_err(format!("no function found at constant {}", const_id.as_usize())))?;
                 vm.stack.push(Value::from_func(func.clone()));
             }
+            Op::PushTrue => vm.stack.push(Value::Bool(true)),
+            Op::PushFalse => vm.stack.push(Value::Bool(false)),
+            Op::PushNil => vm.stack.push(Value::Nil),
             Op::CreateClosure { func_id } => {
                 let func = frame
                     .func
@@ -419,7 +1361,7 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
                     .get(func_id.as_usize())
                     .cloned()
                     .ok_or_else(err_const_notfound)?;
-                let mut upvalues = Vec::new();
+                let mut upvalues = UpValues::with_capacity(func.up_values.len());
                 let parent_upvalues = frame.closure.up_values.borrow();
 
                 for upvalue_origin in func.up_values.iter() {
@@ -441,39 +1383,87 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
                         }
                         // Share a handle to an existing up-value.
                         UpValueOrigin::Outer(upvalue_id) => {
-                            upvalues.push(parent_upvalues[upvalue_id as usize].clone());
+                            let up_value = parent_upvalues
+                                .get(upvalue_id as usize)
+                                .ok_or_else(err_upvalue_notfound)?;
+                            upvalues.push(up_value.clone());
                         }
                     }
                 }
 
-                let closure = Closure::with_up_values(func, upvalues.into_boxed_slice());
+                let closure = Closure::with_up_values(func, upvalues);
                 let closure_rc = Rc::new(closure);
                 vm.stack.push(Value::Object(Object::Closure(closure_rc)));
             }
 
             Op::Int_Neg => {
-                let a = vm.stack[frame.ip].as_int().ok_or_else(err_int_expected)?;
-                vm.stack[frame.ip] = Value::Int(-a);
+                let a = vm.pop_int()?;
+                vm.stack.push(Value::Int(a.checked_neg().ok_or_else(err_int_overflow)?));
+            }
+            Op::Int_Abs => {
+                let a = vm.pop_int()?;
+                let abs = a
+                    .checked_abs()
+                    .ok_or_else(|| runtime_err("integer overflow in absolute value"))?;
+                vm.stack.push(Value::Int(abs));
             }
             Op::Int_Add => {
                 let [a, b] = vm.pop2_int()?;
-                vm.stack.push(Value::Int(a + b));
+                vm.stack
+                    .push(Value::Int(a.checked_add(b).ok_or_else(err_int_overflow)?));
             }
             Op::Int_Sub => {
                 let [a, b] = vm.pop2_int()?;
-                vm.stack.push(Value::Int(a - b));
+                vm.stack
+                    .push(Value::Int(a.checked_sub(b).ok_or_else(err_int_overflow)?));
             }
             Op::Int_Mul => {
                 let [a, b] = vm.pop2_int()?;
-                vm.stack.push(Value::Int(a * b));
+                vm.stack
+                    .push(Value::Int(a.checked_mul(b).ok_or_else(err_int_overflow)?));
             }
             Op::Int_Div => {
                 let [a, b] = vm.pop2_int()?;
-                vm.stack.push(Value::Int(a / b));
+                if b == 0 {
+                    return Err(err_division_by_zero());
+                }
+                vm.stack
+                    .push(Value::Int(a.checked_div(b).ok_or_else(err_int_overflow)?));
             }
             Op::Int_Mod => {
                 let [a, b] = vm.pop2_int()?;
-                vm.stack.push(Value::Int(a % b));
+                if b == 0 {
+                    return Err(err_division_by_zero());
+                }
+                vm.stack
+                    .push(Value::Int(a.checked_rem(b).ok_or_else(err_int_overflow)?));
+            }
+
+            Op::Int_And => {
+                let [a, b] = vm.pop2_int()?;
+                vm.stack.push(Value::Int(a & b));
+            }
+            Op::Int_Or => {
+                let [a, b] = vm.pop2_int()?;
+                vm.stack.push(Value::Int(a | b));
+            }
+            Op::Int_Xor => {
+                let [a, b] = vm.pop2_int()?;
+                vm.stack.push(Value::Int(a ^ b));
+            }
+            Op::Int_Not => {
+                let a = vm.pop_int()?;
+                vm.stack.push(Value::Int(!a));
+            }
+            Op::Int_Shl => {
+                let [a, b] = vm.pop2_int()?;
+                let shift = shift_amount(b)?;
+                vm.stack.push(Value::Int(a.checked_shl(shift).unwrap_or(0)));
+            }
+            Op::Int_Shr => {
+                let [a, b] = vm.pop2_int()?;
+                let shift = shift_amount(b)?;
+                vm.stack.push(Value::Int(a.checked_shr(shift).unwrap_or(0)));
             }
 
             Op::Int_Ne => {
@@ -502,8 +1492,12 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
             }
 
             Op::Float_Neg => {
-                let a = vm.stack[frame.ip].as_float().ok_or_else(err_float_expected)?;
-                vm.stack[frame.ip] = Value::Float(-a);
+                let a = vm.pop_float()?;
+                vm.stack.push(Value::Float(-a));
+            }
+            Op::Float_Abs => {
+                let a = vm.pop_float()?;
+                vm.stack.push(Value::Float(a.abs()));
             }
             Op::Float_Add => {
                 let [a, b] = vm.pop2_float()?;
@@ -551,8 +1545,115 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
                 vm.stack.push(Value::from_bool(a >= b));
             }
 
+            Op::Int_ToStr => {
+                let radix = vm.pop_int()?;
+                let value = vm.pop_int()?;
+                let radix = u32::try_from(radix)
+                    .ok()
+                    .filter(|radix| (2..=36).contains(radix))
+                    .ok_or_else(|| runtime_err(format!("radix must be between 2 and 36: {radix}")))?;
+                let string = format_int_radix(value, radix);
+                vm.stack
+                    .push(Value::Object(Object::String(Rc::new(CrowStr::new(string)))));
+            }
+            Op::Float_ToStr => {
+                let precision = vm.pop_int()?;
+                let value = vm.pop_float()?;
+                if !(0..=MAX_FLOAT_PRECISION).contains(&precision) {
+                    return runtime_err(format!(
+                        "precision must be between 0 and {MAX_FLOAT_PRECISION}: {precision}"
+                    ))
+                    .into();
+                }
+                let string = format!("{value:.*}", precision as usize);
+                vm.stack
+                    .push(Value::Object(Object::String(Rc::new(CrowStr::new(string)))));
+            }
+            Op::Int_ToFloat => {
+                let value = vm.pop_int()?;
+                vm.stack.push(Value::Float(value as f64));
+            }
+            Op::Float_ToInt => {
+                let value = vm.pop_float()?.trunc();
+                // The upper bound, 2^63, is exactly representable but one past `i64::MAX`.
+                if value.is_nan() || value < i64::MIN as f64 || value >= i64::MAX as f64 {
+                    return runtime_err(format!("float out of integer range: {value}")).into();
+                }
+                vm.stack.push(Value::Int(value as i64));
+            }
+
             Op::Str_Concat => todo!(),
             Op::Str_Slice => todo!(),
+            Op::Str_CharAt => {
+                let index = vm.pop_int()?;
+                let string = vm
+                    .stack
+                    .pop()
+                    .ok_or_else(err_stack_underflow)?
+                    .as_string()
+                    .cloned()
+                    .ok_or_else(err_string_expected)?;
+
+                let ch = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| string.as_str().chars().nth(index))
+                    .ok_or_else(|| runtime_err(format!("string index out of range: {index}")))?;
+
+                vm.stack.push(Value::Object(Object::String(Rc::new(CrowStr::new(ch)))));
+            }
+            Op::Str_Contains => {
+                let [string, needle] = vm.pop2_string()?;
+                vm.stack
+                    .push(Value::from_bool(string.as_str().contains(needle.as_str())));
+            }
+            Op::Str_StartsWith => {
+                let [string, prefix] = vm.pop2_string()?;
+                vm.stack
+                    .push(Value::from_bool(string.as_str().starts_with(prefix.as_str())));
+            }
+            Op::Str_EndsWith => {
+                let [string, suffix] = vm.pop2_string()?;
+                vm.stack
+                    .push(Value::from_bool(string.as_str().ends_with(suffix.as_str())));
+            }
+
+            Op::NewArray { capacity } => {
+                let array = Array::with_capacity(capacity.as_usize());
+                vm.stack.push(Value::Object(Object::Array(Handle::new(array))));
+            }
+            Op::NewTable { capacity } => {
+                let table = Table::with_capacity(capacity.as_usize());
+                vm.stack.push(Value::Object(Object::Table(Handle::new(table))));
+            }
+            Op::Array_Push => {
+                let value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
+                let array_value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
+                let array = array_value.as_array().ok_or_else(err_array_expected)?;
+                array.borrow_mut().push(value);
+            }
+            Op::Array_Get => {
+                let index = vm.pop_int()?;
+                let array_value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
+                let array = array_value.as_array().ok_or_else(err_array_expected)?;
+                let value = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| array.borrow().get(index).cloned())
+                    .ok_or_else(|| err_array_index(index, array.borrow().len()))?;
+                vm.stack.push(value);
+            }
+            Op::Array_Set => {
+                let value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
+                let index = vm.pop_int()?;
+                let array_value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
+                let array = array_value.as_array().ok_or_else(err_array_expected)?;
+                let mut array = array.borrow_mut();
+                let len = array.len();
+                let element = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| array.get_mut(index))
+                    .ok_or_else(|| err_array_index(index, len))?;
+                *element = value;
+            }
 
             Op::Table_Create => {
                 let table = Table::new();
@@ -561,46 +1662,31 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
             }
             Op::Table_Insert => {
                 let value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
-                let key = vm
-                    .stack
-                    .pop()
-                    .ok_or_else(err_stack_underflow)?
-                    .as_string()
-                    .ok_or_else(err_string_expected)?
-                    .clone();
+                let key = vm.pop_key()?;
                 let table_handle = vm.stack.pop().ok_or_else(err_stack_underflow)?;
                 let table = table_handle.as_table().ok_or_else(err_table_expected)?;
-                table.borrow_mut().insert(key.to_string(), value);
+                table.borrow_mut().insert(key, value);
             }
             Op::Table_Get => {
-                let key_value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
-                let key = key_value.as_string().ok_or_else(err_string_expected)?;
+                let key = vm.pop_key()?;
                 let table_value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
                 let table = table_value.as_table().ok_or_else(err_table_expected)?;
-                let value = table
-                    .borrow()
-                    .get(key.as_str())
-                    .ok_or_else(|| runtime_err(format!("key not found: {:?}", key.as_str())))?
-                    .clone();
+                // A missing key reads as nil.
+                let value = table.borrow().get(&key).cloned().unwrap_or(Value::Nil);
                 vm.stack.push(value);
             }
             Op::Table_Contains => {
-                let key_value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
-                let key = key_value.as_string().ok_or_else(err_string_expected)?;
+                let key = vm.pop_key()?;
                 let table_value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
                 let table = table_value.as_table().ok_or_else(err_table_expected)?;
-                vm.stack.push(Value::Int(if table.borrow().get(key.as_str()).is_some() {
-                    1
-                } else {
-                    0
-                }))
+                let contains = table.borrow().get(&key).is_some();
+                vm.stack.push(Value::from_bool(contains));
             }
             Op::Table_Remove => {
-                let key_value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
-                let key = key_value.as_string().ok_or_else(err_string_expected)?;
+                let key = vm.pop_key()?;
                 let table_value = vm.stack.pop().ok_or_else(err_stack_underflow)?;
                 let table = table_value.as_table().ok_or_else(err_table_expected)?;
-                table.borrow_mut().remove(key.as_str());
+                table.borrow_mut().remove(&key);
             }
 
             Op::JumpNe { addr } => {
@@ -640,12 +1726,21 @@ fn run_op_loop(vm: &mut Vm, frame: &mut CallFrame) -> Result<FrameAction> {
                 }
             }
             Op::JumpZero { addr } => {
-                if vm.pop_int()? == 0 {
+                let is_zero = match vm.stack.pop().ok_or_else(err_stack_underflow)? {
+                    Value::Int(value) => value == 0,
+                    Value::Bool(value) => !value,
+                    _ => return Err(err_int_expected()),
+                };
+                if is_zero {
                     frame.jump(addr.as_i64())
                 }
             }
             Op::Jump { addr } => frame.jump(addr.as_i64()),
         }
+
+        if vm.single_step {
+            return Ok(FrameAction::Break);
+        }
     }
 }
 
@@ -669,7 +1764,7 @@ impl<'a> DumpVm<'a> {
 impl<'a> fmt::Display for DumpVm<'a> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         if self.flags & Self::FLAG_DUMP_STACK != 0 {
-            let Vm { stack, calls } = self.vm;
+            let Vm { stack, calls, .. } = self.vm;
             // For convenience combine the call stack with the currently active frame.
             let mut iter = calls.iter().chain(std::iter::once(self.frame)).enumerate();
             let mut maybe_frame = iter.next();
@@ -7,3 +7,33 @@ fn test_core_hello_world() {
 fn test_local_arithmetic() {
     crow::compile_file("tests/language/local/arithmetic.crow").unwrap();
 }
+
+#[test]
+fn test_missing_file() {
+    let errors = crow::compile_file("tests/language/missing.crow").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    let err = &errors[0];
+    assert_eq!(err.kind, crow::ErrorKind::Io);
+    assert!(
+        err.message.starts_with("tests/language/missing.crow: "),
+        "{}",
+        err.message
+    );
+}
+
+#[test]
+fn test_multiple_errors() {
+    let errors = crow::compile("let x: Float = 1; let y = z;", "main.crow").unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        ["mismatched types; expected Float, found Int", "unknown variable: z"]
+    );
+}
+
+#[test]
+fn test_warnings() {
+    let module = crow::compile("let x = 1; let _y = 2;", "main.crow").unwrap();
+    let messages: Vec<&str> = module.warnings.iter().map(|err| err.message.as_str()).collect();
+    assert_eq!(messages, ["unused variable: x"]);
+}